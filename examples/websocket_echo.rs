@@ -0,0 +1,25 @@
+use monoio_netreq::http::client::MonoioClient;
+use monoio_netreq::http::websocket::Message;
+
+#[monoio::main(driver = "uring", timer = true)]
+async fn main() -> anyhow::Result<()> {
+    let client = MonoioClient::builder().build();
+
+    let mut ws = client
+        .websocket("wss://echo.websocket.org")
+        .permessage_deflate()
+        .connect()
+        .await?;
+
+    ws.send(Message::Text("hello from monoio".to_string())).await?;
+    while let Some(message) = ws.recv().await? {
+        println!("Received: {:?}", message);
+        if matches!(message, Message::Text(ref text) if text == "hello from monoio") {
+            break;
+        }
+    }
+
+    ws.close(None).await?;
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+// Freshness of an advertisement without `ma` parameter, RFC 7838 section 3.1
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+// Longer advertisements are only trusted for a day, which also keeps expiry instants representable
+const MAX_MAX_AGE: Duration = DEFAULT_MAX_AGE;
+
+/// Parsed `Alt-Svc` header value, RFC 7838.
+#[derive(Debug, PartialEq)]
+pub(crate) enum AltSvc {
+    /// All alternatives of the origin are invalidated.
+    Clear,
+    Services(Vec<AltService>),
+}
+
+/// An alternative service advertised by an origin.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AltService {
+    /// ALPN protocol id, such as `h3`.
+    pub(crate) protocol: String,
+    /// Alternative host, `None` for the host of the origin.
+    pub(crate) host: Option<String>,
+    pub(crate) port: u16,
+    pub(crate) max_age: Duration,
+}
+
+/// Parses an `Alt-Svc` header value, alternatives that can't be parsed are skipped.
+pub(crate) fn parse(value: &str) -> AltSvc {
+    if value.trim().eq_ignore_ascii_case("clear") {
+        return AltSvc::Clear;
+    }
+
+    let services = value
+        .split(',')
+        .filter_map(|entry| parse_service(entry.trim()))
+        .collect();
+
+    AltSvc::Services(services)
+}
+
+fn parse_service(entry: &str) -> Option<AltService> {
+    let mut params = entry.split(';');
+    let (protocol, authority) = params.next()?.split_once('=')?;
+    let authority = authority.trim().trim_matches('"');
+    let (host, port) = authority.rsplit_once(':')?;
+
+    let mut max_age = DEFAULT_MAX_AGE;
+    for param in params {
+        if let Some((name, value)) = param.split_once('=') {
+            if name.trim().eq_ignore_ascii_case("ma") {
+                let secs = value.trim().trim_matches('"').parse().ok()?;
+                max_age = Duration::from_secs(secs).min(MAX_MAX_AGE);
+            }
+        }
+    }
+
+    Some(AltService {
+        protocol: protocol.trim().to_string(),
+        host: (!host.is_empty()).then(|| host.trim_start_matches('[').trim_end_matches(']').to_string()),
+        port: port.parse().ok()?,
+        max_age,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_services_and_clear() {
+        assert_eq!(parse(" clear "), AltSvc::Clear);
+
+        let AltSvc::Services(services) = parse("h3=\":443\"; ma=3600, h2=\"alt.example.com:8443\"") else {
+            panic!("expected services");
+        };
+        assert_eq!(
+            services,
+            [
+                AltService { protocol: "h3".to_string(), host: None, port: 443, max_age: Duration::from_secs(3600) },
+                AltService {
+                    protocol: "h2".to_string(),
+                    host: Some("alt.example.com".to_string()),
+                    port: 8443,
+                    max_age: DEFAULT_MAX_AGE,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn max_age_is_capped() {
+        let AltSvc::Services(services) = parse("h3=\":443\"; ma=18446744073709551615") else {
+            panic!("expected services");
+        };
+        assert_eq!(services[0].max_age, MAX_MAX_AGE);
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use bytes::Bytes;
+use http::HeaderValue;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use super::DigestCredentials;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Algorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl Algorithm {
+    fn parse(value: &str) -> Option<Algorithm> {
+        match value.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Algorithm::Md5),
+            "MD5-SESS" => Some(Algorithm::Md5Sess),
+            "SHA-256" => Some(Algorithm::Sha256),
+            "SHA-256-SESS" => Some(Algorithm::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Md5Sess => "MD5-sess",
+            Algorithm::Sha256 => "SHA-256",
+            Algorithm::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    fn is_session(&self) -> bool {
+        matches!(self, Algorithm::Md5Sess | Algorithm::Sha256Sess)
+    }
+
+    fn hash(&self, data: &[u8]) -> String {
+        match self {
+            Algorithm::Md5 | Algorithm::Md5Sess => hex::encode(Md5::digest(data)),
+            Algorithm::Sha256 | Algorithm::Sha256Sess => hex::encode(Sha256::digest(data)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Qop {
+    Auth,
+    AuthInt,
+}
+
+/// A `Digest` challenge from a `WWW-Authenticate` header, RFC 7616.
+#[derive(Debug)]
+pub(crate) struct DigestChallenge {
+    realm: String,
+    nonce: String,
+    opaque: Option<String>,
+    algorithm: Algorithm,
+    qop: Option<Qop>,
+    userhash: bool,
+}
+
+impl DigestChallenge {
+    /// Parses a single challenge split from a `WWW-Authenticate` value, `None` unless it is a Digest one.
+    /// Challenges with an unsupported algorithm or qop are ignored.
+    pub(crate) fn parse(value: &str) -> Option<DigestChallenge> {
+        let value = value.trim_start();
+        let (scheme, params) = value.split_once(char::is_whitespace)?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut opaque = None;
+        let mut algorithm = Algorithm::Md5;
+        let mut qop = None;
+        let mut userhash = false;
+        for (name, value) in auth_params(params) {
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value),
+                "nonce" => nonce = Some(value),
+                "opaque" => opaque = Some(value),
+                "algorithm" => algorithm = Algorithm::parse(&value)?,
+                "qop" => {
+                    // auth is preferred, auth-int is only used when it is the only option
+                    let options: Vec<&str> = value.split(',').map(str::trim).collect();
+                    qop = if options.iter().any(|q| q.eq_ignore_ascii_case("auth")) {
+                        Some(Qop::Auth)
+                    } else if options.iter().any(|q| q.eq_ignore_ascii_case("auth-int")) {
+                        Some(Qop::AuthInt)
+                    } else {
+                        return None;
+                    };
+                }
+                "userhash" => userhash = value.eq_ignore_ascii_case("true"),
+                _ => {}
+            }
+        }
+
+        Some(DigestChallenge {
+            realm: realm?,
+            nonce: nonce?,
+            opaque,
+            algorithm,
+            qop,
+            userhash,
+        })
+    }
+
+    /// Ranks challenges offered together, SHA-256 is preferred over MD5.
+    pub(crate) fn strength(&self) -> u8 {
+        match self.algorithm {
+            Algorithm::Sha256 | Algorithm::Sha256Sess => 1,
+            Algorithm::Md5 | Algorithm::Md5Sess => 0,
+        }
+    }
+
+    /// Computes the `Authorization` value answering the challenge for the request.
+    pub(crate) fn authorization(
+        &self,
+        credentials: &DigestCredentials,
+        parts: &http::request::Parts,
+        body: Option<&Bytes>,
+    ) -> Result<HeaderValue, Error> {
+        let cnonce = cnonce();
+        let uri = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+        let value = self.response(credentials, parts.method.as_str(), uri, body, &cnonce);
+
+        let mut value = HeaderValue::try_from(value).map_err(Error::InvalidHeaderValue)?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+
+    fn response(
+        &self,
+        credentials: &DigestCredentials,
+        method: &str,
+        uri: &str,
+        body: Option<&Bytes>,
+        cnonce: &str,
+    ) -> String {
+        const NC: &str = "00000001";
+        let algorithm = self.algorithm;
+
+        let mut ha1 = algorithm.hash(format!("{}:{}:{}", credentials.username, self.realm, credentials.password).as_bytes());
+        if algorithm.is_session() {
+            ha1 = algorithm.hash(format!("{ha1}:{}:{cnonce}", self.nonce).as_bytes());
+        }
+
+        let ha2 = match self.qop {
+            Some(Qop::AuthInt) => {
+                let body_hash = algorithm.hash(body.map_or(&[][..], |b| b.as_ref()));
+                algorithm.hash(format!("{method}:{uri}:{body_hash}").as_bytes())
+            }
+            _ => algorithm.hash(format!("{method}:{uri}").as_bytes()),
+        };
+
+        let response = match self.qop {
+            Some(qop) => {
+                let qop = qop_name(qop);
+                algorithm.hash(format!("{ha1}:{}:{NC}:{cnonce}:{qop}:{ha2}", self.nonce).as_bytes())
+            }
+            None => algorithm.hash(format!("{ha1}:{}:{ha2}", self.nonce).as_bytes()),
+        };
+
+        let username = if self.userhash {
+            algorithm.hash(format!("{}:{}", credentials.username, self.realm).as_bytes())
+        } else {
+            quote(&credentials.username)
+        };
+
+        let mut value = format!(
+            "Digest username=\"{username}\", realm=\"{}\", uri=\"{uri}\", algorithm={}, nonce=\"{}\", response=\"{response}\"",
+            quote(&self.realm),
+            algorithm.name(),
+            quote(&self.nonce),
+        );
+        if let Some(qop) = self.qop {
+            value.push_str(&format!(", qop={}, nc={NC}, cnonce=\"{cnonce}\"", qop_name(qop)));
+        }
+        if let Some(opaque) = self.opaque.as_ref() {
+            value.push_str(&format!(", opaque=\"{}\"", quote(opaque)));
+        }
+        if self.userhash {
+            value.push_str(", userhash=true");
+        }
+
+        value
+    }
+}
+
+fn qop_name(qop: Qop) -> &'static str {
+    match qop {
+        Qop::Auth => "auth",
+        Qop::AuthInt => "auth-int",
+    }
+}
+
+/// Escapes a value sent as a quoted string.
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Client nonce built from the randomly seeded std hasher.
+fn cnonce() -> String {
+    let mut nonce = [0u8; 16];
+    for chunk in nonce.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish();
+        chunk.copy_from_slice(&random.to_le_bytes());
+    }
+    hex::encode(nonce)
+}
+
+/// Splits a `WWW-Authenticate` value into its challenges, RFC 9110 section 11.6.1.
+/// A comma separated item starts a new challenge unless it is an auth parameter of the previous one.
+pub(crate) fn challenges(value: &str) -> Vec<&str> {
+    let starts: Vec<usize> = split_items(value)
+        .into_iter()
+        .filter(|(_, item)| !item.trim().is_empty() && !is_auth_param(item))
+        .map(|(start, _)| start)
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).map_or(value.len(), |&next| next - 1);
+            value[start..end].trim().trim_end_matches(',').trim_end()
+        })
+        .collect()
+}
+
+/// Splits a header value on the commas outside of quoted strings, along with the offset of each item.
+fn split_items(value: &str) -> Vec<(usize, &str)> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                items.push((start, &value[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push((start, &value[start..]));
+    items
+}
+
+/// Whether a comma separated item is a `name=value` parameter rather than the scheme of a challenge.
+fn is_auth_param(item: &str) -> bool {
+    let item = item.trim_start();
+    let name_end = item.find(|c: char| c.is_whitespace() || c == '=').unwrap_or(item.len());
+    item[name_end..].trim_start().starts_with('=')
+}
+
+/// Splits comma separated `name=value` auth parameters, unquoting quoted strings.
+fn auth_params(input: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while matches!(chars.peek(), Some(c) if c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+
+        let name: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        let name = name.trim().to_string();
+        if name.is_empty() {
+            return params;
+        }
+
+        while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.peek().copied() {
+                if c == ',' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+            value = value.trim().to_string();
+        }
+
+        params.push((name, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn credentials() -> DigestCredentials {
+        DigestCredentials {
+            username: "Mufasa".to_string(),
+            password: "Circle of Life".to_string(),
+        }
+    }
+
+    #[test]
+    fn challenges_are_split_on_schemes() {
+        let value = r#"Basic realm="simple, quoted", Digest realm="x", nonce="abc", qop="auth,auth-int", Bearer"#;
+        assert_eq!(
+            challenges(value),
+            [
+                r#"Basic realm="simple, quoted""#,
+                r#"Digest realm="x", nonce="abc", qop="auth,auth-int""#,
+                "Bearer",
+            ]
+        );
+        assert_eq!(challenges("Negotiate abc==, Digest realm = \"x\""), ["Negotiate abc==", "Digest realm = \"x\""]);
+        assert!(challenges(" , ").is_empty());
+    }
+
+    #[test]
+    fn digest_challenge_is_picked_among_others() {
+        let value = r#"Basic realm="x", Digest realm="http-auth@example.org", nonce="n", qop="auth-int, auth""#;
+        let parsed: Vec<DigestChallenge> = challenges(value).into_iter().filter_map(DigestChallenge::parse).collect();
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].realm, "http-auth@example.org");
+        assert_eq!(parsed[0].qop, Some(Qop::Auth));
+    }
+
+    // RFC 7616 section 3.9.1
+    #[test]
+    fn responses_match_the_rfc_example() {
+        let challenge = |algorithm: &str| {
+            DigestChallenge::parse(&format!(
+                r#"Digest realm="http-auth@example.org", qop="auth, auth-int", algorithm={algorithm}, nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v", opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#
+            ))
+            .unwrap()
+        };
+
+        let md5 = challenge("MD5").response(&credentials(), "GET", "/dir/index.html", None, CNONCE);
+        assert!(md5.contains(r#"response="8ca523f5e9506fed4657c9700eebdbec""#), "{md5}");
+        assert!(md5.contains("qop=auth, nc=00000001"), "{md5}");
+        assert!(md5.contains(r#"opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#), "{md5}");
+
+        let sha256 = challenge("SHA-256").response(&credentials(), "GET", "/dir/index.html", None, CNONCE);
+        assert!(
+            sha256.contains(r#"response="753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1""#),
+            "{sha256}"
+        );
+    }
+}
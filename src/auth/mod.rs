@@ -0,0 +1,145 @@
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{HeaderMap, HeaderValue};
+
+use crate::error::Error;
+use self::digest::DigestChallenge;
+
+pub use self::oauth2::OAuth2ClientCredentials;
+
+mod digest;
+mod oauth2;
+
+/// Future returned by a `TokenProvider`.
+pub type TokenFuture<'a> = Pin<Box<dyn Future<Output = Result<String, Error>> + 'a>>;
+
+/// Source of bearer tokens for a client, such as an OAuth2 token endpoint.
+/// The token is sent as `Authorization: Bearer <token>` with every request that doesn't set
+/// its own `Authorization` header. A request answered with `401 Unauthorized` is retried once
+/// with the token returned by `refresh`.
+/// # Examples
+/// ```
+/// struct StaticToken(String);
+///
+/// impl TokenProvider for StaticToken {
+///     fn token(&self) -> TokenFuture<'_> {
+///         Box::pin(async move { Ok(self.0.clone()) })
+///     }
+///
+///     fn refresh(&self, _rejected: &str) -> TokenFuture<'_> {
+///         self.token()
+///     }
+/// }
+/// ```
+pub trait TokenProvider {
+    /// Returns the current token, fetching one if none is cached.
+    fn token(&self) -> TokenFuture<'_>;
+
+    /// Returns a new token after the server rejected `rejected`.
+    fn refresh(&self, rejected: &str) -> TokenFuture<'_>;
+}
+
+/// Username and password answering a Digest challenge.
+#[derive(Clone, Debug)]
+pub(crate) struct DigestCredentials {
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+/// Authentication applied by the clients around sending a request.
+#[derive(Clone, Default)]
+pub(crate) struct RequestAuth {
+    pub(crate) digest: Option<DigestCredentials>,
+    pub(crate) token_provider: Option<Rc<dyn TokenProvider>>,
+}
+
+impl Debug for RequestAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestAuth")
+            .field("digest", &self.digest.as_ref().map(|c| &c.username))
+            .field("token_provider", &self.token_provider.is_some())
+            .finish()
+    }
+}
+
+impl RequestAuth {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.digest.is_some() || self.token_provider.is_some()
+    }
+
+    /// Adds the bearer token of the provider, unless the request has its own credentials.
+    pub(crate) async fn authorize(&self, headers: &mut HeaderMap) -> Result<(), Error> {
+        let Some(provider) = self.token_provider.as_ref() else {
+            return Ok(());
+        };
+        if self.digest.is_some() || headers.contains_key(AUTHORIZATION) {
+            return Ok(());
+        }
+
+        let token = provider.token().await?;
+        headers.insert(AUTHORIZATION, bearer(&token)?);
+        Ok(())
+    }
+
+    /// Computes the credentials answering a `401 Unauthorized` response,
+    /// `None` if the request can't be authenticated.
+    pub(crate) async fn reauthorize(
+        &self,
+        parts: &http::request::Parts,
+        body: Option<&Bytes>,
+        response_headers: &HeaderMap,
+    ) -> Result<Option<HeaderValue>, Error> {
+        if let Some(credentials) = self.digest.as_ref() {
+            let challenge = response_headers
+                .get_all(WWW_AUTHENTICATE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(digest::challenges)
+                .filter_map(DigestChallenge::parse)
+                .max_by_key(|challenge| challenge.strength());
+            return match challenge {
+                Some(challenge) => challenge.authorization(credentials, parts, body).map(Some),
+                None => Ok(None),
+            };
+        }
+
+        let Some(provider) = self.token_provider.as_ref() else {
+            return Ok(None);
+        };
+        let Some(rejected) = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+        else {
+            // Credentials set by the caller are not replaced
+            return Ok(None);
+        };
+
+        let token = provider.refresh(rejected).await?;
+        bearer(&token).map(Some)
+    }
+}
+
+/// Encodes `Authorization: Basic` credentials.
+pub(crate) fn basic(username: &str, password: Option<&str>) -> HeaderValue {
+    let credentials = format!("{username}:{}", password.unwrap_or(""));
+    let mut value = HeaderValue::try_from(format!("Basic {}", BASE64.encode(credentials)))
+        .expect("base64 is a valid header value");
+    value.set_sensitive(true);
+    value
+}
+
+/// Encodes `Authorization: Bearer` credentials.
+pub(crate) fn bearer(token: &str) -> Result<HeaderValue, Error> {
+    let mut value = HeaderValue::try_from(format!("Bearer {token}")).map_err(Error::InvalidHeaderValue)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
@@ -0,0 +1,215 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
+use http::{Method, StatusCode};
+use serde::Deserialize;
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use crate::hyper::client::MonoioHyperClient;
+#[cfg(not(feature = "hyper-tls"))]
+use crate::http::client::MonoioClient;
+use crate::error::Error;
+use super::{basic, TokenFuture, TokenProvider};
+
+// Tokens are renewed this long before they expire, so they don't expire in flight
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires: Option<Instant>,
+    refresh_token: Option<String>,
+}
+
+impl CachedToken {
+    fn is_fresh(&self) -> bool {
+        self.expires.is_none_or(|expires| Instant::now() + EXPIRY_MARGIN < expires)
+    }
+}
+
+/// OAuth2 client credentials grant, RFC 6749 section 4.4.
+/// Tokens are cached until they expire, then renewed with the refresh token when the server
+/// issued one, or with a new client credentials grant.
+/// The token endpoint is called through `client`, which should not use this provider itself.
+/// # Examples
+/// ```
+/// let token_client = MonoioClient::builder().enable_https().build();
+/// let credentials = OAuth2ClientCredentials::new(token_client, "https://auth.example.com/token", "id", "secret")
+///     .scope("read write");
+/// let client = MonoioClient::builder()
+///     .enable_https()
+///     .token_provider(credentials)
+///     .build();
+/// ```
+pub struct OAuth2ClientCredentials<C> {
+    client: C,
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+    cached: RefCell<Option<CachedToken>>,
+}
+
+impl<C> OAuth2ClientCredentials<C> {
+    pub fn new(
+        client: C,
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        OAuth2ClientCredentials {
+            client,
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scope: None,
+            cached: RefCell::new(None),
+        }
+    }
+
+    /// Sets the space separated scopes requested with the token.
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Returns the cached token if it is still fresh.
+    fn cached_token(&self) -> Option<String> {
+        let cached = self.cached.borrow();
+        cached
+            .as_ref()
+            .filter(|token| token.is_fresh())
+            .map(|token| token.access_token.clone())
+    }
+
+    /// Drops the cached token if it is the one the server rejected.
+    fn invalidate(&self, rejected: &str) {
+        let mut cached = self.cached.borrow_mut();
+        if let Some(token) = cached.as_mut().filter(|token| token.access_token == rejected) {
+            token.expires = Some(Instant::now());
+        }
+    }
+
+    /// Form body of the next token request, a refresh grant if a refresh token is known.
+    fn grant(&self) -> Bytes {
+        let mut form = match self.cached.borrow().as_ref().and_then(|t| t.refresh_token.as_ref()) {
+            Some(refresh_token) => format!("grant_type=refresh_token&refresh_token={}", form_encode(refresh_token)),
+            None => "grant_type=client_credentials".to_string(),
+        };
+        if let Some(scope) = self.scope.as_ref() {
+            form.push_str("&scope=");
+            form.push_str(&form_encode(scope));
+        }
+
+        Bytes::from(form)
+    }
+
+    fn store(&self, status: StatusCode, response: Result<TokenResponse, Error>) -> Result<String, Error> {
+        if !status.is_success() {
+            // A rejected refresh token is dropped, the next request uses the client credentials
+            self.cached.borrow_mut().take();
+            return Err(Error::UnexpectedStatus(status));
+        }
+
+        let response = response?;
+        let refresh_token = response
+            .refresh_token
+            .or_else(|| self.cached.borrow_mut().take().and_then(|t| t.refresh_token));
+        *self.cached.borrow_mut() = Some(CachedToken {
+            access_token: response.access_token.clone(),
+            expires: response.expires_in.map(|secs| Instant::now() + Duration::from_secs(secs)),
+            refresh_token,
+        });
+
+        Ok(response.access_token)
+    }
+}
+
+/// Encodes a value of an `application/x-www-form-urlencoded` body.
+fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => encoded.push(byte as char),
+            b' ' => encoded.push('+'),
+            byte => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(not(feature = "hyper-tls"))]
+impl OAuth2ClientCredentials<MonoioClient> {
+    async fn fetch(&self) -> Result<String, Error> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
+        let response = self
+            .client
+            .make_request()
+            .set_method(Method::POST)
+            .set_uri(self.token_url.as_str())
+            .set_header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .set_header(ACCEPT, "application/json")
+            .set_header(AUTHORIZATION, basic(&form_encode(&self.client_id), Some(&form_encode(&self.client_secret))))
+            .send_body(self.grant())
+            .await?;
+        let status = response.status();
+        self.store(status, response.json().await)
+    }
+}
+
+#[cfg(not(feature = "hyper-tls"))]
+impl TokenProvider for OAuth2ClientCredentials<MonoioClient> {
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(self.fetch())
+    }
+
+    fn refresh(&self, rejected: &str) -> TokenFuture<'_> {
+        self.invalidate(rejected);
+        Box::pin(self.fetch())
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+impl OAuth2ClientCredentials<MonoioHyperClient> {
+    async fn fetch(&self) -> Result<String, Error> {
+        if let Some(token) = self.cached_token() {
+            return Ok(token);
+        }
+
+        let response = self
+            .client
+            .new_request()
+            .set_method(Method::POST)
+            .set_uri(self.token_url.as_str())
+            .set_header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .set_header(ACCEPT, "application/json")
+            .set_header(AUTHORIZATION, basic(&form_encode(&self.client_id), Some(&form_encode(&self.client_secret))))
+            .send_body(self.grant())
+            .await?;
+        let status = response.status();
+        self.store(status, response.json().await)
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+impl TokenProvider for OAuth2ClientCredentials<MonoioHyperClient> {
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(self.fetch())
+    }
+
+    fn refresh(&self, rejected: &str) -> TokenFuture<'_> {
+        self.invalidate(rejected);
+        Box::pin(self.fetch())
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::Poll;
+
+#[cfg(not(feature = "hyper-tls"))]
+use monoio_http::common::body::HttpBody;
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use bytes::Bytes;
+
+use crate::error::Error;
+#[cfg(not(feature = "hyper-tls"))]
+use crate::http::client::MonoioClient;
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use crate::hyper::client::MonoioHyperClient;
+use crate::key::PoolKey;
+use crate::request::HttpRequest;
+use crate::response::HttpResponse;
+
+const DEFAULT_MAX_CONCURRENCY: usize = 64;
+const DEFAULT_MAX_PER_HOST: usize = 8;
+
+/// Requests sent concurrently from the current thread, at most `max_concurrency` at once
+/// and `max_per_host` at once to the same origin. With the default client, origins known to speak
+/// HTTP/2 are also held to the `max_concurrent_streams` set on its builder.
+/// A request holds its slot until its response is returned, the body of a streamed response is read
+/// afterwards. Results are returned in the order the requests were added.
+/// # Examples
+/// ```
+/// let mut batch = client.batch().max_concurrency(32).max_per_host(4);
+/// for id in 0..500 {
+///     batch = batch.request(client.make_request().set_uri(format!("https://api.example.com/items/{id}")));
+/// }
+/// for result in batch.send().await {
+///     println!("{}", result?.status());
+/// }
+/// ```
+pub struct Batch<C> {
+    requests: Vec<HttpRequest<C>>,
+    max_concurrency: usize,
+    max_per_host: usize,
+    client: C,
+}
+
+impl<C> Batch<C> {
+    pub(crate) fn new(client: C) -> Self {
+        Batch {
+            requests: Vec::new(),
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            max_per_host: DEFAULT_MAX_PER_HOST,
+            client,
+        }
+    }
+
+    /// Sets the number of requests in flight at once, 64 by default.
+    pub fn max_concurrency(mut self, val: usize) -> Self {
+        self.max_concurrency = val.max(1);
+        self
+    }
+
+    /// Sets the number of requests in flight at once to the same origin, 8 by default.
+    pub fn max_per_host(mut self, val: usize) -> Self {
+        self.max_per_host = val.max(1);
+        self
+    }
+
+    pub fn request(mut self, request: HttpRequest<C>) -> Self {
+        self.requests.push(request);
+        self
+    }
+
+    pub fn requests(mut self, requests: impl IntoIterator<Item = HttpRequest<C>>) -> Self {
+        self.requests.extend(requests);
+        self
+    }
+
+    /// Groups the requests by origin with the number of requests allowed in flight to each,
+    /// requests without a valid URI share a group and fail once sent.
+    fn jobs(self, stream_limit: impl Fn(&C, &PoolKey) -> Option<usize>) -> (Vec<Job<HttpRequest<C>>>, Limits) {
+        let mut per_key = HashMap::new();
+        let jobs = self
+            .requests
+            .into_iter()
+            .map(|request| {
+                let key = request.uri().and_then(|uri| PoolKey::try_from(uri).ok());
+                per_key.entry(key.clone()).or_insert_with(|| {
+                    let streams = key.as_ref().and_then(|key| stream_limit(&self.client, key));
+                    streams.map_or(self.max_per_host, |streams| self.max_per_host.min(streams.max(1)))
+                });
+                Job { key, item: request }
+            })
+            .collect();
+
+        let limits = Limits {
+            global: self.max_concurrency,
+            per_key,
+        };
+        (jobs, limits)
+    }
+}
+
+#[cfg(not(feature = "hyper-tls"))]
+impl Batch<MonoioClient> {
+    pub async fn send(self) -> Vec<Result<HttpResponse<HttpBody>, Error>> {
+        let (jobs, limits) = self.jobs(|client, key| client.stream_limit(key));
+        run(jobs, limits, |request| request.send()).await
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+impl Batch<MonoioHyperClient> {
+    pub async fn send(self) -> Vec<Result<HttpResponse<Bytes>, Error>> {
+        let (jobs, limits) = self.jobs(|_, _| None);
+        run(jobs, limits, |request| request.send()).await
+    }
+}
+
+/// Polls `futures` concurrently on the current task, at most `limit` at once,
+/// and returns their outputs in order. Futures are created up front but only polled once started.
+/// # Examples
+/// ```
+/// let pages = join_all_limited((1..=20).map(|page| fetch_page(&client, page)), 4).await;
+/// ```
+pub async fn join_all_limited<F, I>(futures: I, limit: usize) -> Vec<F::Output>
+    where
+        F: Future,
+        I: IntoIterator<Item = F>,
+{
+    let jobs = futures.into_iter().map(|item| Job { key: None, item }).collect();
+    let limits = Limits {
+        global: limit.max(1),
+        per_key: HashMap::new(),
+    };
+    run(jobs, limits, |fut| fut).await
+}
+
+struct Job<T> {
+    key: Option<PoolKey>,
+    item: T,
+}
+
+struct Limits {
+    global: usize,
+    per_key: HashMap<Option<PoolKey>, usize>,
+}
+
+impl Limits {
+    fn for_key(&self, key: &Option<PoolKey>) -> usize {
+        self.per_key.get(key).copied().unwrap_or(usize::MAX)
+    }
+}
+
+/// Starts jobs in order as the limits allow, skipping over jobs whose origin is saturated,
+/// and polls the started ones until all completed.
+async fn run<T, F, S>(jobs: Vec<Job<T>>, limits: Limits, start: S) -> Vec<F::Output>
+    where
+        F: Future,
+        S: Fn(T) -> F,
+{
+    let total = jobs.len();
+    let mut queue: VecDeque<(usize, Job<T>)> = jobs.into_iter().enumerate().collect();
+    let mut running: Vec<(usize, Option<PoolKey>, Pin<Box<F>>)> = Vec::new();
+    let mut in_flight: HashMap<Option<PoolKey>, usize> = HashMap::new();
+    let mut outputs: Vec<Option<F::Output>> = (0..total).map(|_| None).collect();
+
+    poll_fn(|cx| loop {
+        let mut i = 0;
+        while i < queue.len() && running.len() < limits.global {
+            let count = in_flight.get(&queue[i].1.key).copied().unwrap_or(0);
+            if count >= limits.for_key(&queue[i].1.key) {
+                i += 1;
+                continue;
+            }
+            if let Some((index, job)) = queue.remove(i) {
+                *in_flight.entry(job.key.clone()).or_default() += 1;
+                running.push((index, job.key, Box::pin(start(job.item))));
+            }
+        }
+
+        // Every started future is polled on each wake up, their wakers all point to this task
+        let mut completed = false;
+        running.retain_mut(|(index, key, fut)| match fut.as_mut().poll(cx) {
+            Poll::Ready(output) => {
+                outputs[*index] = Some(output);
+                if let Some(count) = in_flight.get_mut(key) {
+                    *count -= 1;
+                }
+                completed = true;
+                false
+            }
+            Poll::Pending => true,
+        });
+
+        if running.is_empty() && queue.is_empty() {
+            return Poll::Ready(());
+        }
+        // Completed jobs freed slots for queued ones
+        if !completed {
+            return Poll::Pending;
+        }
+    })
+    .await;
+
+    outputs.into_iter().flatten().collect()
+}
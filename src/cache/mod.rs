@@ -0,0 +1,399 @@
+mod policy;
+mod storage;
+
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use bytes::Bytes;
+use http::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, VARY};
+use http::request::Parts as RequestParts;
+use http::response::Parts as ResponseParts;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Version};
+use serde::{Deserialize, Serialize};
+
+use policy::CacheControl;
+pub use storage::{CacheStorage, DiskStorage, MemoryStorage};
+
+/// Cache outcome of a request, available through `HttpResponse::extensions()` on clients with a cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The response was served from the cache without contacting the server.
+    Hit,
+    /// The stored response was validated by the server with a `304 Not Modified`.
+    Revalidated,
+    /// The response was fetched from the server.
+    Miss,
+}
+
+/// A stored response along with the request information needed to reuse it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    status: u16,
+    version: String,
+    headers: Vec<(String, Vec<u8>)>,
+    #[serde(with = "base64_body")]
+    body: Vec<u8>,
+    vary: Vec<(String, Option<Vec<u8>>)>,
+    request_time: u64,
+    response_time: u64,
+}
+
+impl CacheEntry {
+    fn new(req: &RequestParts, res: &ResponseParts, body: &Bytes, request_time: u64) -> Self {
+        let vary = vary_headers(&res.headers)
+            .map(|name| {
+                let value = req.headers.get(name.as_str()).map(|v| v.as_bytes().to_vec());
+                (name, value)
+            })
+            .collect();
+
+        CacheEntry {
+            status: res.status.as_u16(),
+            version: format!("{:?}", res.version),
+            headers: to_pairs(&res.headers),
+            body: body.to_vec(),
+            vary,
+            request_time,
+            response_time: policy::now(),
+        }
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::with_capacity(self.headers.len());
+        for (name, value) in self.headers.iter() {
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(name.as_str()), HeaderValue::from_bytes(value)) {
+                headers.append(name, value);
+            }
+        }
+
+        headers
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK)
+    }
+
+    fn matches_vary(&self, headers: &HeaderMap) -> bool {
+        self.vary.iter().all(|(name, stored)| {
+            let current = headers.get(name.as_str()).map(|v| v.as_bytes());
+            current == stored.as_deref()
+        })
+    }
+
+    fn to_response(&self, status: CacheStatus) -> Response<Bytes> {
+        let mut response = Response::new(Bytes::from(self.body.clone()));
+        *response.status_mut() = self.status();
+        *response.version_mut() = parse_version(&self.version);
+        *response.headers_mut() = self.headers();
+        response.extensions_mut().insert(status);
+
+        response
+    }
+}
+
+/// Result of looking up a request in the cache.
+pub(crate) enum Lookup {
+    /// A fresh stored response that can be returned as is.
+    Fresh(Response<Bytes>),
+    /// A stale stored response, conditional headers were added to the request.
+    Revalidate(CacheEntry),
+    Miss,
+}
+
+/// Client side http cache following RFC 9111.
+/// Only `GET` responses are stored, successful unsafe requests invalidate the stored response of their URI.
+/// # Examples
+/// ```
+/// let client = MonoioClient::builder()
+///     .with_cache(HttpCache::in_memory(256))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct HttpCache {
+    storage: Rc<dyn CacheStorage>,
+    shared: bool,
+}
+
+impl Debug for HttpCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpCache").field("shared", &self.shared).finish()
+    }
+}
+
+impl HttpCache {
+    /// Creates a cache on top of a custom storage.
+    pub fn new<S: CacheStorage + 'static>(storage: S) -> Self {
+        HttpCache {
+            storage: Rc::new(storage),
+            shared: false,
+        }
+    }
+
+    /// Creates a cache keeping at most `capacity` responses in memory, evicting the least recently used.
+    pub fn in_memory(capacity: usize) -> Self {
+        HttpCache::new(MemoryStorage::new(capacity))
+    }
+
+    /// Creates a cache persisting responses as files inside `dir`.
+    pub fn on_disk(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        Ok(HttpCache::new(DiskStorage::new(dir)?))
+    }
+
+    /// Makes the cache behave as a shared cache, honouring `s-maxage` and refusing `private` responses.
+    /// Default is a private cache.
+    pub fn shared(mut self, val: bool) -> Self {
+        self.shared = val;
+        self
+    }
+
+    fn key(method: &Method, uri: &http::Uri) -> String {
+        format!("{method} {uri}")
+    }
+
+    /// Looks up a stored response for the request.
+    /// Stale responses with validators turn the request into a conditional one, a request `max-stale`
+    /// accepts stale responses unless they are marked `must-revalidate`. Such responses are never
+    /// served without the server, a failed revalidation is returned as an error.
+    pub(crate) fn lookup<B>(&self, req: &mut Request<B>) -> Lookup {
+        if *req.method() != Method::GET {
+            return Lookup::Miss;
+        }
+
+        // Conditional requests made by the caller are passed through untouched
+        let headers = req.headers();
+        if headers.contains_key(IF_NONE_MATCH) || headers.contains_key(IF_MODIFIED_SINCE) {
+            return Lookup::Miss;
+        }
+
+        let req_cc = CacheControl::parse(headers);
+        if req_cc.no_store {
+            return Lookup::Miss;
+        }
+
+        let entry = match self.storage.get(&Self::key(req.method(), req.uri())) {
+            Some(entry) if entry.matches_vary(headers) => entry,
+            _ => return Lookup::Miss,
+        };
+
+        let stored_headers = entry.headers();
+        let res_cc = CacheControl::parse(&stored_headers);
+        let lifetime = policy::freshness_lifetime(entry.status(), &stored_headers, self.shared);
+        let age = policy::current_age(&stored_headers, entry.request_time, entry.response_time, policy::now());
+
+        let must_revalidate = res_cc.must_revalidate || (self.shared && res_cc.proxy_revalidate);
+        let usable = age < lifetime
+            || (!must_revalidate && req_cc.max_stale.is_some_and(|max_stale| age - lifetime <= max_stale));
+        let within_request_limits = req_cc.max_age.is_none_or(|max_age| age <= max_age)
+            && req_cc.min_fresh.is_none_or(|min_fresh| age.saturating_add(min_fresh) < lifetime);
+        if !res_cc.no_cache && !req_cc.no_cache && usable && within_request_limits {
+            return Lookup::Fresh(entry.to_response(CacheStatus::Hit));
+        }
+
+        let etag = stored_headers.get(ETAG).cloned();
+        let last_modified = stored_headers.get(LAST_MODIFIED).cloned();
+        if etag.is_none() && last_modified.is_none() {
+            return Lookup::Miss;
+        }
+
+        let headers = req.headers_mut();
+        if let Some(etag) = etag {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = last_modified {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        Lookup::Revalidate(entry)
+    }
+
+    /// Refreshes a stored response with the headers of a `304 Not Modified` and returns it.
+    pub(crate) fn revalidated(
+        &self,
+        req: &RequestParts,
+        mut entry: CacheEntry,
+        res: &ResponseParts,
+        request_time: u64,
+    ) -> Response<Bytes> {
+        let mut headers = entry.headers();
+        for (name, value) in res.headers.iter() {
+            headers.insert(name, value.clone());
+        }
+
+        entry.headers = to_pairs(&headers);
+        entry.request_time = request_time;
+        entry.response_time = policy::now();
+        self.storage.put(&Self::key(&req.method, &req.uri), entry.clone());
+
+        entry.to_response(CacheStatus::Revalidated)
+    }
+
+    /// Returns true if the response to the request may be stored, RFC 9111 section 3.
+    pub(crate) fn is_storable(&self, req: &RequestParts, res: &ResponseParts) -> bool {
+        if req.method != Method::GET {
+            return false;
+        }
+
+        let req_cc = CacheControl::parse(&req.headers);
+        let res_cc = CacheControl::parse(&res.headers);
+        if req_cc.no_store || res_cc.no_store || (self.shared && res_cc.private) {
+            return false;
+        }
+
+        // A Vary of `*` can never be matched by a later request
+        if vary_headers(&res.headers).any(|name| name == "*") {
+            return false;
+        }
+
+        let validators = res.headers.contains_key(ETAG) || res.headers.contains_key(LAST_MODIFIED);
+        policy::has_explicit_freshness(&res.headers, &res_cc, self.shared)
+            || res_cc.public
+            || (policy::heuristically_cacheable(res.status) && (validators || res_cc.no_cache))
+    }
+
+    pub(crate) fn store(&self, req: &RequestParts, res: &ResponseParts, body: &Bytes, request_time: u64) {
+        let entry = CacheEntry::new(req, res, body, request_time);
+        self.storage.put(&Self::key(&req.method, &req.uri), entry);
+    }
+
+    /// Drops the stored response of the request URI after a successful unsafe request.
+    pub(crate) fn invalidate(&self, req: &RequestParts, res: &ResponseParts) {
+        let unsafe_method = !matches!(req.method, Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE);
+        if unsafe_method && (res.status.is_success() || res.status.is_redirection()) {
+            self.storage.remove(&Self::key(&Method::GET, &req.uri));
+        }
+    }
+
+    pub(crate) fn request_time() -> u64 {
+        policy::now()
+    }
+}
+
+/// Returns the lowercase header names listed in the `Vary` headers of a response.
+fn vary_headers(headers: &HeaderMap) -> impl Iterator<Item = String> + '_ {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+}
+
+fn to_pairs(headers: &HeaderMap) -> Vec<(String, Vec<u8>)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), value.as_bytes().to_vec()))
+        .collect()
+}
+
+fn parse_version(version: &str) -> Version {
+    match version {
+        "HTTP/0.9" => Version::HTTP_09,
+        "HTTP/1.0" => Version::HTTP_10,
+        "HTTP/2.0" => Version::HTTP_2,
+        "HTTP/3.0" => Version::HTTP_3,
+        _ => Version::HTTP_11,
+    }
+}
+
+/// Keeps the body of serialized entries as base64 text rather than an array of numbers.
+mod base64_body {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(body))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let data = String::deserialize(deserializer)?;
+        STANDARD.decode(data).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{AGE, CACHE_CONTROL};
+
+    use super::*;
+
+    const URI: &str = "http://example.com/resource";
+
+    /// Stores a response to `GET URI` with the given `Cache-Control` that is 120 seconds old.
+    fn cache_with(cache_control: &'static str) -> HttpCache {
+        cache_aged(cache_control, "120")
+    }
+
+    fn cache_aged(cache_control: &'static str, age: &'static str) -> HttpCache {
+        let cache = HttpCache::in_memory(8);
+        let (req, ()) = Request::get(URI).body(()).unwrap().into_parts();
+        let (res, ()) = Response::builder()
+            .header(CACHE_CONTROL, cache_control)
+            .header(AGE, age)
+            .header(ETAG, "\"v1\"")
+            .body(())
+            .unwrap()
+            .into_parts();
+        cache.store(&req, &res, &Bytes::from_static(b"stored"), policy::now());
+        cache
+    }
+
+    fn lookup(cache: &HttpCache, cache_control: Option<&'static str>) -> Lookup {
+        let mut req = Request::get(URI);
+        if let Some(value) = cache_control {
+            req = req.header(CACHE_CONTROL, value);
+        }
+        cache.lookup(&mut req.body(()).unwrap())
+    }
+
+    #[test]
+    fn fresh_responses_are_served() {
+        let cache = cache_with("max-age=600");
+        assert!(matches!(lookup(&cache, None), Lookup::Fresh(_)));
+        assert!(matches!(lookup(&cache, Some("no-cache")), Lookup::Revalidate(_)));
+        assert!(matches!(lookup(&cache, Some("max-age=60")), Lookup::Revalidate(_)));
+    }
+
+    #[test]
+    fn stale_responses_need_max_stale() {
+        let cache = cache_with("max-age=60");
+        assert!(matches!(lookup(&cache, None), Lookup::Revalidate(_)));
+        assert!(matches!(lookup(&cache, Some("max-stale=30")), Lookup::Revalidate(_)));
+        assert!(matches!(lookup(&cache, Some("max-stale=120")), Lookup::Fresh(_)));
+        assert!(matches!(lookup(&cache, Some("max-stale")), Lookup::Fresh(_)));
+    }
+
+    #[test]
+    fn must_revalidate_responses_are_never_served_stale() {
+        let cache = cache_with("max-age=60, must-revalidate");
+        assert!(matches!(lookup(&cache, Some("max-stale")), Lookup::Revalidate(_)));
+
+        let cache = cache_with("max-age=60, proxy-revalidate");
+        assert!(matches!(lookup(&cache, Some("max-stale")), Lookup::Fresh(_)));
+        let cache = cache.shared(true);
+        assert!(matches!(lookup(&cache, Some("max-stale")), Lookup::Revalidate(_)));
+    }
+
+    #[test]
+    fn huge_ages_are_stale() {
+        let cache = cache_aged("max-age=600", "18446744073709551615");
+        assert!(matches!(lookup(&cache, None), Lookup::Revalidate(_)));
+        assert!(matches!(lookup(&cache, Some("min-fresh=60")), Lookup::Revalidate(_)));
+        assert!(matches!(lookup(&cache, Some("max-stale")), Lookup::Fresh(_)));
+    }
+
+    #[test]
+    fn serialized_bodies_are_base64() {
+        let (req, ()) = Request::get(URI).body(()).unwrap().into_parts();
+        let (res, ()) = Response::new(()).into_parts();
+        let entry = CacheEntry::new(&req, &res, &Bytes::from_static(b"stored"), policy::now());
+
+        let json: serde_json::Value = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["body"], "c3RvcmVk");
+        let entry: CacheEntry = serde_json::from_value(json).unwrap();
+        assert_eq!(entry.body, b"stored");
+    }
+}
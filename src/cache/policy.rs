@@ -0,0 +1,125 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use http::header::{AGE, CACHE_CONTROL, DATE, EXPIRES, LAST_MODIFIED};
+use http::{HeaderMap, StatusCode};
+
+// Status codes that may be cached without explicit freshness information, RFC 9110 section 15.1
+const HEURISTICALLY_CACHEABLE: [u16; 11] = [200, 203, 204, 206, 300, 301, 308, 404, 405, 410, 414];
+
+/// The subset of `Cache-Control` directives relevant to a private or shared client cache.
+#[derive(Default, Debug)]
+pub(crate) struct CacheControl {
+    pub(crate) no_store: bool,
+    pub(crate) no_cache: bool,
+    pub(crate) private: bool,
+    pub(crate) public: bool,
+    pub(crate) must_revalidate: bool,
+    pub(crate) proxy_revalidate: bool,
+    pub(crate) max_age: Option<u64>,
+    pub(crate) s_maxage: Option<u64>,
+    pub(crate) min_fresh: Option<u64>,
+    pub(crate) max_stale: Option<u64>,
+}
+
+impl CacheControl {
+    pub(crate) fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = CacheControl::default();
+        let directives = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','));
+
+        for directive in directives {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = value.and_then(|v| v.parse::<u64>().ok());
+
+            match name.to_ascii_lowercase().as_str() {
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" => cc.must_revalidate = true,
+                "proxy-revalidate" => cc.proxy_revalidate = true,
+                "max-age" => cc.max_age = seconds,
+                "s-maxage" => cc.s_maxage = seconds,
+                "min-fresh" => cc.min_fresh = seconds,
+                // Without a value any staleness is accepted
+                "max-stale" => cc.max_stale = if value.is_some() { seconds } else { Some(u64::MAX) },
+                _ => {}
+            }
+        }
+
+        cc
+    }
+}
+
+pub(crate) fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn header_date(headers: &HeaderMap, name: http::HeaderName) -> Option<u64> {
+    let value = headers.get(name)?.to_str().ok()?;
+    httpdate::parse_http_date(value).ok().map(unix_secs)
+}
+
+/// Returns true if the response carries information allowing a cache to compute its freshness.
+pub(crate) fn has_explicit_freshness(headers: &HeaderMap, cc: &CacheControl, shared: bool) -> bool {
+    cc.max_age.is_some() || (shared && cc.s_maxage.is_some()) || headers.contains_key(EXPIRES)
+}
+
+/// Computes the freshness lifetime in seconds of a stored response, RFC 9111 section 4.2.1.
+pub(crate) fn freshness_lifetime(status: StatusCode, headers: &HeaderMap, shared: bool) -> u64 {
+    let cc = CacheControl::parse(headers);
+    if let Some(s_maxage) = cc.s_maxage.filter(|_| shared) {
+        return s_maxage;
+    }
+    if let Some(max_age) = cc.max_age {
+        return max_age;
+    }
+
+    let date = header_date(headers, DATE);
+    if headers.contains_key(EXPIRES) {
+        // An invalid Expires value means the response is already expired
+        return match (header_date(headers, EXPIRES), date) {
+            (Some(expires), Some(date)) => expires.saturating_sub(date),
+            _ => 0,
+        };
+    }
+
+    // Heuristic freshness of 10% of the time since the last modification
+    match (date, header_date(headers, LAST_MODIFIED)) {
+        (Some(date), Some(modified)) if HEURISTICALLY_CACHEABLE.contains(&status.as_u16()) => {
+            date.saturating_sub(modified) / 10
+        }
+        _ => 0,
+    }
+}
+
+/// Computes the current age in seconds of a stored response, RFC 9111 section 4.2.3.
+pub(crate) fn current_age(headers: &HeaderMap, request_time: u64, response_time: u64, now: u64) -> u64 {
+    let date = header_date(headers, DATE).unwrap_or(response_time);
+    let age = headers
+        .get(AGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let apparent_age = response_time.saturating_sub(date);
+    let response_delay = response_time.saturating_sub(request_time);
+    let corrected_initial_age = apparent_age.max(age.saturating_add(response_delay));
+    let resident_time = now.saturating_sub(response_time);
+
+    corrected_initial_age.saturating_add(resident_time)
+}
+
+pub(crate) fn heuristically_cacheable(status: StatusCode) -> bool {
+    HEURISTICALLY_CACHEABLE.contains(&status.as_u16())
+}
+
+pub(crate) fn now() -> u64 {
+    unix_secs(SystemTime::now())
+}
@@ -0,0 +1,129 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use super::CacheEntry;
+
+/// Backing store of the http cache.
+/// Implementations use interior mutability since the cache is shared by every clone of a client.
+pub trait CacheStorage {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    fn remove(&self, key: &str);
+}
+
+/// In-memory storage evicting the least recently used entry once `capacity` entries are stored.
+pub struct MemoryStorage {
+    capacity: usize,
+    state: RefCell<LruState>,
+}
+
+#[derive(Default)]
+struct LruState {
+    tick: u64,
+    entries: HashMap<String, (u64, CacheEntry)>,
+    order: BTreeMap<u64, String>,
+}
+
+impl LruState {
+    fn touch(&mut self, key: &str) -> Option<&CacheEntry> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (last_used, entry) = self.entries.get_mut(key)?;
+        self.order.remove(last_used);
+        self.order.insert(tick, key.to_string());
+        *last_used = tick;
+
+        Some(entry)
+    }
+}
+
+impl MemoryStorage {
+    pub fn new(capacity: usize) -> Self {
+        MemoryStorage {
+            capacity,
+            state: RefCell::new(LruState::default()),
+        }
+    }
+}
+
+impl CacheStorage for MemoryStorage {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.state.borrow_mut().touch(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let mut state = self.state.borrow_mut();
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some((last_used, _)) = state.entries.insert(key.to_string(), (tick, entry)) {
+            state.order.remove(&last_used);
+        }
+        state.order.insert(tick, key.to_string());
+
+        while state.entries.len() > self.capacity {
+            match state.order.pop_first() {
+                Some((_, evicted)) => state.entries.remove(&evicted),
+                None => break,
+            };
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut state = self.state.borrow_mut();
+        if let Some((last_used, _)) = state.entries.remove(key) {
+            state.order.remove(&last_used);
+        }
+    }
+}
+
+/// On-disk storage keeping one JSON file per entry inside a directory.
+/// File operations are blocking, prefer it for small documents that are expensive to fetch.
+pub struct DiskStorage {
+    dir: PathBuf,
+}
+
+impl DiskStorage {
+    /// Creates the storage, creating `dir` if it does not exist.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(DiskStorage { dir })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let name = hex::encode(Sha256::digest(key.as_bytes()));
+        self.dir.join(format!("{name}.json"))
+    }
+}
+
+impl CacheStorage for DiskStorage {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let data = std::fs::read(self.entry_path(key)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let Ok(data) = serde_json::to_vec(&entry) else {
+            return;
+        };
+
+        // Write to a temporary file first so readers never observe a partially written entry
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("tmp");
+        if std::fs::write(&tmp, data).is_ok() {
+            let _ = std::fs::rename(&tmp, &path);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = std::fs::remove_file(self.entry_path(key));
+    }
+}
@@ -0,0 +1,383 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use http::{StatusCode, Uri};
+
+use crate::error::Error;
+use crate::key::PoolKey;
+
+const DEFAULT_CONSECUTIVE_FAILURES: u32 = 5;
+const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+const DEFAULT_HALF_OPEN_PROBES: u32 = 1;
+
+/// State of the circuit of an origin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are sent and their outcomes counted.
+    Closed,
+    /// Requests fail right away with `Error::CircuitOpen`.
+    Open,
+    /// A limited number of probe requests are sent, the circuit closes once they all succeeded
+    /// and opens again on the first failure.
+    HalfOpen,
+}
+
+/// Counters of the circuit of an origin, for metrics.
+#[derive(Clone, Debug)]
+pub struct CircuitSnapshot {
+    pub host: String,
+    pub port: u16,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// Failures among the requests of the failure rate window.
+    pub recent_failures: usize,
+    /// Requests in the failure rate window.
+    pub recent_requests: usize,
+}
+
+/// Circuit breaker per origin, failing requests fast with `Error::CircuitOpen` once an origin failed
+/// too many times in a row or too large a share of its recent requests. After `open_duration`
+/// the circuit half-opens and lets probe requests through to decide whether to close it.
+/// Errors and `5xx` responses are failures by default. Requests answered from the cache
+/// or a cassette are not counted. A breaker can be shared by several clients by cloning it.
+/// # Examples
+/// ```
+/// let breaker = CircuitBreaker::new()
+///     .consecutive_failures(3)
+///     .failure_rate(0.5, 20)
+///     .open_duration(Duration::from_secs(10))
+///     .on_state_change(|host, port, state| println!("{host}:{port} is now {state:?}"));
+///
+/// let client = MonoioClient::builder().circuit_breaker(breaker.clone()).build();
+/// match client.make_request().set_uri("http://backend:8080/health").send().await {
+///     Err(Error::CircuitOpen(origin)) => println!("{origin} is down, skipping"),
+///     result => handle(result?),
+/// }
+/// ```
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    state: Rc<RefCell<BreakerState>>,
+}
+
+type StateHook = Rc<dyn Fn(&str, u16, CircuitState)>;
+
+struct BreakerState {
+    consecutive_failures: u32,
+    failure_rate: Option<(f64, usize)>,
+    open_duration: Duration,
+    half_open_probes: u32,
+    is_failure: Rc<dyn Fn(StatusCode) -> bool>,
+    on_state_change: Option<StateHook>,
+    circuits: HashMap<PoolKey, Circuit>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        let state = BreakerState {
+            consecutive_failures: DEFAULT_CONSECUTIVE_FAILURES,
+            failure_rate: None,
+            open_duration: DEFAULT_OPEN_DURATION,
+            half_open_probes: DEFAULT_HALF_OPEN_PROBES,
+            is_failure: Rc::new(|status: StatusCode| status.is_server_error()),
+            on_state_change: None,
+            circuits: HashMap::new(),
+        };
+        CircuitBreaker {
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Returns a breaker opening after 5 consecutive failures for 30 seconds, with a single probe request.
+    pub fn new() -> Self {
+        CircuitBreaker::default()
+    }
+
+    /// Opens the circuit once an origin failed `val` times in a row, at least once.
+    pub fn consecutive_failures(self, val: u32) -> Self {
+        self.state.borrow_mut().consecutive_failures = val.max(1);
+        self
+    }
+
+    /// Also opens the circuit once at least `ratio` of the last `window` requests to an origin failed,
+    /// as soon as `window` requests were made. `ratio` is clamped to `(0, 1]`, so at least one failure is needed.
+    pub fn failure_rate(self, ratio: f64, window: usize) -> Self {
+        let ratio = if ratio > 0.0 { ratio.min(1.0) } else { f64::MIN_POSITIVE };
+        self.state.borrow_mut().failure_rate = Some((ratio, window.max(1)));
+        self
+    }
+
+    /// Sets how long a circuit stays open before letting probe requests through, 30 seconds by default.
+    pub fn open_duration(self, val: Duration) -> Self {
+        self.state.borrow_mut().open_duration = val;
+        self
+    }
+
+    /// Sets the number of probe requests sent while half-open, all of them must succeed
+    /// to close the circuit. 1 by default.
+    pub fn half_open_probes(self, val: u32) -> Self {
+        self.state.borrow_mut().half_open_probes = val.max(1);
+        self
+    }
+
+    /// Replaces the statuses counted as failures, `5xx` by default.
+    pub fn failure_status<F>(self, is_failure: F) -> Self
+        where
+            F: Fn(StatusCode) -> bool + 'static,
+    {
+        self.state.borrow_mut().is_failure = Rc::new(is_failure);
+        self
+    }
+
+    /// Calls `hook` with the host and port of an origin whenever its circuit changes state.
+    pub fn on_state_change<F>(self, hook: F) -> Self
+        where
+            F: Fn(&str, u16, CircuitState) + 'static,
+    {
+        self.state.borrow_mut().on_state_change = Some(Rc::new(hook));
+        self
+    }
+
+    /// Returns the state of the circuit of the origin of `uri`, closed for unknown origins.
+    pub fn state(&self, uri: &Uri) -> CircuitState {
+        let Ok(key) = PoolKey::try_from(uri) else {
+            return CircuitState::Closed;
+        };
+        let state = self.state.borrow();
+        state.circuits.get(&key).map_or(CircuitState::Closed, |circuit| circuit.state)
+    }
+
+    /// Returns the counters of every origin a request was sent to.
+    pub fn snapshot(&self) -> Vec<CircuitSnapshot> {
+        let state = self.state.borrow();
+        state
+            .circuits
+            .iter()
+            .map(|(key, circuit)| CircuitSnapshot {
+                host: key.host.to_string(),
+                port: key.port,
+                state: circuit.state,
+                consecutive_failures: circuit.consecutive_failures,
+                recent_failures: circuit.recent.iter().filter(|failed| **failed).count(),
+                recent_requests: circuit.recent.len(),
+            })
+            .collect()
+    }
+
+    /// Lets the request through unless the circuit of its origin is open.
+    pub(crate) fn permit(&self, uri: &Uri) -> Result<CircuitPermit, Error> {
+        let Ok(key) = PoolKey::try_from(uri) else {
+            // Requests without an origin fail before reaching the network
+            return Ok(CircuitPermit { breaker: self.clone(), key: None, probe: false });
+        };
+
+        let mut changed = None;
+        let result = {
+            let state = &mut *self.state.borrow_mut();
+            let circuit = state.circuits.entry(key.clone()).or_default();
+            if circuit.state == CircuitState::Open && circuit.opened_at.elapsed() >= state.open_duration {
+                circuit.half_open();
+                changed = Some(CircuitState::HalfOpen);
+            }
+
+            match circuit.state {
+                CircuitState::Closed => Ok(false),
+                CircuitState::HalfOpen if circuit.probes_left(state.half_open_probes) => {
+                    circuit.probes_in_flight += 1;
+                    Ok(true)
+                }
+                _ => Err(Error::CircuitOpen(format!("{}:{}", key.host, key.port))),
+            }
+        };
+        self.notify(&key, changed);
+
+        let probe = result?;
+        Ok(CircuitPermit { breaker: self.clone(), key: Some(key), probe })
+    }
+
+    /// Counts the outcome of a request, `None` when it failed with an error.
+    fn record(&self, key: &PoolKey, probe: bool, status: Option<StatusCode>) {
+        let changed = {
+            let state = &mut *self.state.borrow_mut();
+            let failed = match status {
+                Some(status) => (state.is_failure)(status),
+                None => true,
+            };
+            let Some(circuit) = state.circuits.get_mut(key) else {
+                return;
+            };
+
+            if probe {
+                circuit.probes_in_flight = circuit.probes_in_flight.saturating_sub(1);
+            }
+            match circuit.state {
+                CircuitState::HalfOpen if probe && failed => {
+                    circuit.open();
+                    Some(CircuitState::Open)
+                }
+                CircuitState::HalfOpen if probe => {
+                    circuit.probe_successes += 1;
+                    (circuit.probe_successes >= state.half_open_probes).then(|| {
+                        circuit.close();
+                        CircuitState::Closed
+                    })
+                }
+                // Requests started before the circuit opened don't count
+                CircuitState::Closed if !probe => {
+                    circuit.count(failed, state.failure_rate);
+                    circuit.should_open(state.consecutive_failures, state.failure_rate).then(|| {
+                        circuit.open();
+                        CircuitState::Open
+                    })
+                }
+                _ => None,
+            }
+        };
+        self.notify(key, changed);
+    }
+
+    /// Gives back the probe slot of a request dropped before its outcome was known.
+    fn release(&self, key: &PoolKey) {
+        if let Some(circuit) = self.state.borrow_mut().circuits.get_mut(key) {
+            circuit.probes_in_flight = circuit.probes_in_flight.saturating_sub(1);
+        }
+    }
+
+    /// Calls the state hook outside of the borrow, so it may inspect the breaker.
+    fn notify(&self, key: &PoolKey, changed: Option<CircuitState>) {
+        let Some(state) = changed else {
+            return;
+        };
+        let hook = self.state.borrow().on_state_change.clone();
+        if let Some(hook) = hook {
+            hook(&key.host, key.port, state);
+        }
+    }
+}
+
+/// Request let through by a `CircuitBreaker`, reporting its outcome with `finish`.
+pub(crate) struct CircuitPermit {
+    breaker: CircuitBreaker,
+    key: Option<PoolKey>,
+    probe: bool,
+}
+
+impl CircuitPermit {
+    pub(crate) fn finish(mut self, status: Option<StatusCode>) {
+        if let Some(key) = self.key.take() {
+            self.breaker.record(&key, self.probe, status);
+        }
+    }
+}
+
+impl Drop for CircuitPermit {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take().filter(|_| self.probe) {
+            self.breaker.release(&key);
+        }
+    }
+}
+
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    recent: VecDeque<bool>,
+    opened_at: Instant,
+    probes_in_flight: u32,
+    probe_successes: u32,
+}
+
+impl Default for Circuit {
+    fn default() -> Self {
+        Circuit {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            recent: VecDeque::new(),
+            opened_at: Instant::now(),
+            probes_in_flight: 0,
+            probe_successes: 0,
+        }
+    }
+}
+
+impl Circuit {
+    fn count(&mut self, failed: bool, failure_rate: Option<(f64, usize)>) {
+        self.consecutive_failures = if failed { self.consecutive_failures + 1 } else { 0 };
+        if let Some((_, window)) = failure_rate {
+            if self.recent.len() == window {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(failed);
+        }
+    }
+
+    fn should_open(&self, consecutive_failures: u32, failure_rate: Option<(f64, usize)>) -> bool {
+        if self.consecutive_failures >= consecutive_failures {
+            return true;
+        }
+        failure_rate.is_some_and(|(ratio, window)| {
+            let failures = self.recent.iter().filter(|failed| **failed).count();
+            self.recent.len() == window && failures as f64 >= ratio * window as f64
+        })
+    }
+
+    fn probes_left(&self, half_open_probes: u32) -> bool {
+        self.probes_in_flight + self.probe_successes < half_open_probes
+    }
+
+    fn open(&mut self) {
+        self.state = CircuitState::Open;
+        self.opened_at = Instant::now();
+        self.probe_successes = 0;
+    }
+
+    fn half_open(&mut self) {
+        self.state = CircuitState::HalfOpen;
+        self.probes_in_flight = 0;
+        self.probe_successes = 0;
+    }
+
+    fn close(&mut self) {
+        self.state = CircuitState::Closed;
+        self.consecutive_failures = 0;
+        self.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(breaker: &CircuitBreaker, uri: &Uri, status: StatusCode) {
+        breaker.permit(uri).unwrap().finish(Some(status));
+    }
+
+    #[test]
+    fn failure_rate_needs_failures() {
+        let uri = Uri::from_static("http://example.com/");
+        for ratio in [0.0, -1.0, f64::NAN] {
+            let breaker = CircuitBreaker::new().consecutive_failures(10).failure_rate(ratio, 2);
+            record(&breaker, &uri, StatusCode::OK);
+            record(&breaker, &uri, StatusCode::OK);
+            assert_eq!(breaker.state(&uri), CircuitState::Closed);
+            record(&breaker, &uri, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(breaker.state(&uri), CircuitState::Open);
+        }
+    }
+
+    #[test]
+    fn failure_rate_above_one_means_every_request() {
+        let uri = Uri::from_static("http://example.com/");
+        let breaker = CircuitBreaker::new().consecutive_failures(10).failure_rate(2.0, 3);
+        for status in [StatusCode::BAD_GATEWAY, StatusCode::BAD_GATEWAY, StatusCode::OK, StatusCode::BAD_GATEWAY] {
+            record(&breaker, &uri, status);
+        }
+        assert_eq!(breaker.state(&uri), CircuitState::Closed);
+        record(&breaker, &uri, StatusCode::BAD_GATEWAY);
+        record(&breaker, &uri, StatusCode::BAD_GATEWAY);
+        assert_eq!(breaker.state(&uri), CircuitState::Open);
+    }
+}
@@ -0,0 +1,319 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use http::header::{CONTENT_LENGTH, CONTENT_RANGE, ETAG, LAST_MODIFIED};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use monoio::fs::{self, File, OpenOptions};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::progress::ProgressHook;
+
+const HASH_READ_CHUNK: usize = 64 * 1024;
+
+type ProgressCallback = Box<dyn FnMut(u64, Option<u64>)>;
+
+/// Options applied while streaming a response body into a file.
+#[derive(Default)]
+pub struct DownloadOptions {
+    expected_sha256: Option<String>,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl DownloadOptions {
+    pub fn new() -> Self {
+        DownloadOptions::default()
+    }
+
+    /// Verifies the downloaded file against a hex encoded SHA-256 digest before it is moved into place.
+    /// On mismatch the partial file is removed and `Error::ChecksumMismatch` is returned.
+    pub fn expect_sha256(mut self, hex_digest: impl Into<String>) -> Self {
+        self.expected_sha256 = Some(hex_digest.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Registers a callback invoked after every chunk written to disk.
+    /// It receives the number of bytes in the file so far and the expected total when known.
+    /// # Examples
+    /// ```
+    /// let options = DownloadOptions::new().on_progress(|done, total| println!("{done}/{total:?}"));
+    /// ```
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+        where
+            F: FnMut(u64, Option<u64>) + 'static,
+    {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+}
+
+/// Returns the path of the temporary file a download is written to before the final rename.
+pub(crate) fn part_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(target.as_os_str());
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+/// Returns the path of the file keeping the validator of the response a `.part` file was started from.
+pub(crate) fn validator_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(target.as_os_str());
+    name.push(".part.validator");
+    PathBuf::from(name)
+}
+
+/// Returns the validator of the response an interrupted download of `target` was started from,
+/// sent as `If-Range` so the server answers with the whole content if it changed since.
+pub(crate) async fn partial_validator(target: &Path) -> Option<HeaderValue> {
+    let value = fs::read(validator_path(target)).await.ok()?;
+    HeaderValue::from_bytes(value.trim_ascii()).ok()
+}
+
+/// Removes the partial file of `target` along with its validator, the next download starts over.
+pub(crate) async fn discard_partial(target: &Path) {
+    let _ = fs::remove_file(part_path(target)).await;
+    let _ = fs::remove_file(validator_path(target)).await;
+}
+
+/// Returns the strong `ETag` of a response, or its `Last-Modified` date,
+/// weak entity tags can't be used with `If-Range`.
+fn validator(headers: &HeaderMap) -> Option<&HeaderValue> {
+    headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+}
+
+/// Returns the number of bytes already downloaded for `target` by an interrupted download.
+pub(crate) async fn partial_len(target: &Path) -> u64 {
+    fs::metadata(part_path(target))
+        .await
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+/// Determines where the response body has to be written in the file given the requested range start.
+/// Returns `None` if the server reports the requested range as unsatisfiable for content
+/// of the size of the partial file, which means it already holds the complete content.
+pub(crate) fn resume_offset(
+    status: StatusCode,
+    headers: &HeaderMap,
+    requested: u64,
+) -> Result<Option<u64>, Error> {
+    match status {
+        StatusCode::PARTIAL_CONTENT => {
+            let start = content_range(headers).map(|(start, _)| start);
+            if start != Some(requested) {
+                return Err(Error::UnexpectedStatus(status));
+            }
+            Ok(Some(requested))
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE if requested > 0 => match unsatisfied_range_total(headers) {
+            Some(total) if total == requested => Ok(None),
+            _ => Err(Error::UnexpectedStatus(status)),
+        },
+        // Server ignored the range, start over
+        s if s.is_success() => Ok(Some(0)),
+        s => Err(Error::UnexpectedStatus(s)),
+    }
+}
+
+/// Computes the expected size of the complete file, when the headers allow it.
+pub(crate) fn expected_total(headers: &HeaderMap, offset: u64) -> Option<u64> {
+    if let Some((_, Some(total))) = content_range(headers) {
+        return Some(total);
+    }
+
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(|len| offset + len)
+}
+
+/// Parses `Content-Range: bytes <start>-<end>/<total>` into its start and optional total.
+fn content_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+
+    Some((start, total.trim().parse().ok()))
+}
+
+/// Parses `Content-Range: bytes */<total>` of a `416 Range Not Satisfiable` response.
+fn unsatisfied_range_total(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    value.strip_prefix("bytes */")?.trim().parse().ok()
+}
+
+/// Writes chunks of a download into a `.part` file and moves it over the target once complete.
+pub(crate) struct FileSink {
+    file: File,
+    target: PathBuf,
+    part: PathBuf,
+    offset: u64,
+    total: Option<u64>,
+    hasher: Option<Sha256>,
+    options: DownloadOptions,
+    hook: Option<ProgressHook>,
+}
+
+impl FileSink {
+    /// Opens the `.part` file of `target` to write from `offset`. A download starting over keeps
+    /// the validator found in `headers`, so it can be resumed if the content did not change.
+    pub(crate) async fn create(
+        target: &Path,
+        offset: u64,
+        total: Option<u64>,
+        headers: &HeaderMap,
+        options: DownloadOptions,
+    ) -> Result<Self, Error> {
+        let part = part_path(target);
+        if offset == 0 {
+            match validator(headers) {
+                Some(value) => {
+                    let (res, _) = fs::write(validator_path(target), Bytes::copy_from_slice(value.as_bytes())).await;
+                    res.map_err(Error::IoError)?;
+                }
+                None => {
+                    let _ = fs::remove_file(validator_path(target)).await;
+                }
+            }
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(offset == 0)
+            .open(&part)
+            .await
+            .map_err(Error::IoError)?;
+
+        let mut hasher = options.expected_sha256.as_ref().map(|_| Sha256::new());
+        if let Some(hasher) = hasher.as_mut() {
+            // The digest has to cover the bytes written by the interrupted download as well
+            hash_prefix(&file, offset, hasher).await?;
+        }
+
+        Ok(FileSink {
+            file,
+            target: target.to_path_buf(),
+            part,
+            offset,
+            total,
+            hasher,
+            options,
+            hook: None,
+        })
+    }
+
+    /// Additionally reports progress to the download hook registered on the request.
+    pub(crate) fn report_to(mut self, hook: Option<ProgressHook>) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    pub(crate) async fn write(&mut self, chunk: Bytes) -> Result<(), Error> {
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&chunk);
+        }
+
+        let len = chunk.len() as u64;
+        let (res, _) = self.file.write_all_at(chunk, self.offset).await;
+        res.map_err(Error::IoError)?;
+        self.offset += len;
+
+        if let Some(callback) = self.options.on_progress.as_mut() {
+            callback(self.offset, self.total);
+        }
+        if let Some(hook) = self.hook.as_ref() {
+            hook.report(self.offset, self.total);
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the file to disk, verifies the checksum and atomically renames it to the target path.
+    /// Returns the size of the complete file.
+    pub(crate) async fn finish(self) -> Result<u64, Error> {
+        self.file.sync_all().await.map_err(Error::IoError)?;
+        self.file.close().await.map_err(Error::IoError)?;
+
+        if let (Some(hasher), Some(expected)) = (self.hasher, self.options.expected_sha256) {
+            let actual = hex::encode(hasher.finalize());
+            if actual != expected {
+                discard_partial(&self.target).await;
+                return Err(Error::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        fs::rename(&self.part, &self.target).await.map_err(Error::IoError)?;
+        let _ = fs::remove_file(validator_path(&self.target)).await;
+
+        Ok(self.offset)
+    }
+}
+
+async fn hash_prefix(file: &File, len: u64, hasher: &mut Sha256) -> Result<(), Error> {
+    let mut pos = 0;
+    let mut buf = Vec::with_capacity(HASH_READ_CHUNK);
+
+    while pos < len {
+        buf.clear();
+        let (res, read_buf) = file.read_at(buf, pos).await;
+        let n = res.map_err(Error::IoError)?;
+        if n == 0 {
+            break;
+        }
+
+        let n = n.min((len - pos) as usize);
+        hasher.update(&read_buf[..n]);
+        pos += n as u64;
+        buf = read_buf;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(fields: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in fields {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn partial_content_must_start_at_the_requested_offset() {
+        let range = headers(&[("content-range", "bytes 40-99/100")]);
+        assert_eq!(resume_offset(StatusCode::PARTIAL_CONTENT, &range, 40).unwrap(), Some(40));
+        assert!(resume_offset(StatusCode::PARTIAL_CONTENT, &range, 20).is_err());
+        assert_eq!(expected_total(&range, 40), Some(100));
+
+        // Ignored range
+        let full = headers(&[("content-length", "100")]);
+        assert_eq!(resume_offset(StatusCode::OK, &full, 40).unwrap(), Some(0));
+        assert_eq!(expected_total(&full, 0), Some(100));
+    }
+
+    #[test]
+    fn unsatisfiable_range_must_match_the_partial_length() {
+        let status = StatusCode::RANGE_NOT_SATISFIABLE;
+        assert_eq!(resume_offset(status, &headers(&[("content-range", "bytes */100")]), 100).unwrap(), None);
+        assert!(resume_offset(status, &headers(&[("content-range", "bytes */120")]), 100).is_err());
+        assert!(resume_offset(status, &headers(&[("content-range", "bytes */100")]), 120).is_err());
+        assert!(resume_offset(status, &HeaderMap::new(), 100).is_err());
+    }
+
+    #[test]
+    fn validator_prefers_strong_entity_tags() {
+        let modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(validator(&headers(&[("etag", "\"v1\""), ("last-modified", modified)])).unwrap(), "\"v1\"");
+        assert_eq!(validator(&headers(&[("etag", "W/\"v1\""), ("last-modified", modified)])).unwrap(), modified);
+        assert!(validator(&headers(&[("etag", "W/\"v1\"")])).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use http::Error as HttpError;
+use http::StatusCode;
 use http::header::InvalidHeaderValue;
 use monoio_transports::{FromUriError, TransportError as MonoioTransportError};
 #[cfg(not(feature = "hyper-tls"))]
@@ -38,14 +39,51 @@ pub enum Error {
     BytesError(String),
     #[error("serde body deserialize error: {0:?}")]
     SerdeDeserializeError(SerdeError),
+    #[error("serde body serialize error: {0:?}")]
+    SerdeSerializeError(SerdeError),
     #[error("Hyper Connector was not initialized")]
     ConnectorNotInitialized,
+    #[error("response body exceeds the limit of {0} bytes")]
+    ResponseBodyTooLarge(usize),
+    #[error("response headers exceed the configured limits: {0}")]
+    ResponseHeadersTooLarge(String),
+    #[error("file io error: {0:?}")]
+    IoError(std::io::Error),
+    #[error("checksum mismatch, expected {expected} but got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("unexpected response status: {0}")]
+    UnexpectedStatus(StatusCode),
+    #[error("error parsing http response head: {0}")]
+    HttpResponseHeadError(String),
+    #[error("websocket error: {0}")]
+    WebSocketError(String),
+    #[error("server-sent events error: {0}")]
+    SseError(String),
+    #[error("protocol upgrade error: {0}")]
+    UpgradeError(String),
+    #[error("http3 error: {0}")]
+    Http3Error(String),
+    #[error("request signing error: {0}")]
+    SigningError(String),
+    #[error("circuit breaker open for {0}")]
+    CircuitOpen(String),
+    #[cfg(feature = "mock")]
+    #[error("mock transport error: {0}")]
+    MockError(String),
+    #[cfg(feature = "vcr")]
+    #[error("cassette error: {0}")]
+    CassetteError(String),
+    #[cfg(feature = "grpc")]
+    #[error("grpc error: {0}")]
+    GrpcError(Box<crate::grpc::Status>),
 }
 
 #[derive(Debug, Error)]
 pub enum TransportError {
     #[error("http connector error: {0:?}")]
     HttpConnectorError(MonoioTransportError),
+    #[error("connect error: {0}")]
+    ConnectError(String),
     #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
     #[error("hyper poll error: {0:?}")]
     HyperPollError(HyperError<PollConnectError<std::io::Error>>),
@@ -0,0 +1,192 @@
+#[cfg(feature = "grpc-prost")]
+use std::marker::PhantomData;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::error::Error;
+use super::status::{Code, Status};
+
+// Length-prefixed message header: compressed flag followed by the big endian message length
+const HEADER_SIZE: usize = 5;
+
+/// Serializes request messages and deserializes response messages of a gRPC method.
+pub trait Codec {
+    /// Request message type.
+    type Encode;
+    /// Response message type.
+    type Decode;
+
+    fn encode(&mut self, item: &Self::Encode, buf: &mut BytesMut) -> Result<(), Error>;
+
+    fn decode(&mut self, buf: Bytes) -> Result<Self::Decode, Error>;
+}
+
+/// Passes messages through as raw bytes, for callers doing their own serialization.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BytesCodec;
+
+impl Codec for BytesCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+
+    fn encode(&mut self, item: &Bytes, buf: &mut BytesMut) -> Result<(), Error> {
+        buf.extend_from_slice(item);
+        Ok(())
+    }
+
+    fn decode(&mut self, buf: Bytes) -> Result<Bytes, Error> {
+        Ok(buf)
+    }
+}
+
+/// Protocol Buffers codec backed by prost.
+/// Available only on crate feature grpc-prost
+/// # Examples
+/// ```
+/// let reply = grpc
+///     .unary("/helloworld.Greeter/SayHello", &request, ProstCodec::<HelloRequest, HelloReply>::default())
+///     .await?;
+/// ```
+#[cfg(feature = "grpc-prost")]
+pub struct ProstCodec<T, U> {
+    _marker: PhantomData<(T, U)>,
+}
+
+#[cfg(feature = "grpc-prost")]
+impl<T, U> Default for ProstCodec<T, U> {
+    fn default() -> Self {
+        ProstCodec { _marker: PhantomData }
+    }
+}
+
+#[cfg(feature = "grpc-prost")]
+impl<T, U> Clone for ProstCodec<T, U> {
+    fn clone(&self) -> Self {
+        ProstCodec { _marker: PhantomData }
+    }
+}
+
+#[cfg(feature = "grpc-prost")]
+impl<T: prost::Message, U: prost::Message + Default> Codec for ProstCodec<T, U> {
+    type Encode = T;
+    type Decode = U;
+
+    fn encode(&mut self, item: &T, buf: &mut BytesMut) -> Result<(), Error> {
+        item.encode(buf)
+            .map_err(|e| Error::GrpcError(Box::new(Status::new(Code::Internal, e.to_string()))))
+    }
+
+    fn decode(&mut self, buf: Bytes) -> Result<U, Error> {
+        U::decode(buf).map_err(|e| Error::GrpcError(Box::new(Status::new(Code::Internal, e.to_string()))))
+    }
+}
+
+/// Appends `item` to `buf` as a length-prefixed message.
+pub(crate) fn encode_message<C: Codec>(codec: &mut C, item: &C::Encode, buf: &mut BytesMut) -> Result<(), Error> {
+    let start = buf.len();
+    buf.put_bytes(0, HEADER_SIZE);
+    codec.encode(item, buf)?;
+
+    let len = u32::try_from(buf.len() - start - HEADER_SIZE)
+        .map_err(|_| Error::GrpcError(Box::new(Status::new(Code::ResourceExhausted, "request message too large"))))?;
+    buf[start + 1..start + HEADER_SIZE].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+/// Splits the response body into length-prefixed messages.
+pub(crate) struct MessageDecoder {
+    buf: BytesMut,
+    max_message_size: usize,
+}
+
+impl MessageDecoder {
+    pub(crate) fn new(max_message_size: usize) -> Self {
+        MessageDecoder {
+            buf: BytesMut::new(),
+            max_message_size,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Returns the next complete message, `None` if more data is needed.
+    pub(crate) fn next_message(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.buf.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+
+        if self.buf[0] != 0 {
+            return Err(Error::GrpcError(Box::new(Status::new(
+                Code::Internal,
+                "compressed messages are not supported",
+            ))));
+        }
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if len > self.max_message_size {
+            return Err(Error::GrpcError(Box::new(Status::new(
+                Code::ResourceExhausted,
+                format!("message of {len} bytes exceeds the limit of {} bytes", self.max_message_size),
+            ))));
+        }
+        if self.buf.len() < HEADER_SIZE + len {
+            return Ok(None);
+        }
+
+        self.buf.advance(HEADER_SIZE);
+        Ok(Some(self.buf.split_to(len).freeze()))
+    }
+
+    /// Whether a partial message is left over.
+    pub(crate) fn has_remaining(&self) -> bool {
+        !self.buf.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(result: Result<Option<Bytes>, Error>) -> Code {
+        match result {
+            Err(Error::GrpcError(status)) => status.code(),
+            _ => panic!("expected a gRPC error"),
+        }
+    }
+
+    #[test]
+    fn messages_are_length_prefixed() {
+        let mut buf = BytesMut::new();
+        encode_message(&mut BytesCodec, &Bytes::from_static(b"hi"), &mut buf).unwrap();
+        encode_message(&mut BytesCodec, &Bytes::new(), &mut buf).unwrap();
+        assert_eq!(&buf[..], b"\0\0\0\0\x02hi\0\0\0\0\0");
+    }
+
+    #[test]
+    fn decoder_waits_for_complete_messages() {
+        let mut decoder = MessageDecoder::new(16);
+        decoder.feed(b"\0\0\0");
+        assert_eq!(decoder.next_message().unwrap(), None);
+        decoder.feed(b"\0\x03ab");
+        assert_eq!(decoder.next_message().unwrap(), None);
+        assert!(decoder.has_remaining());
+
+        decoder.feed(b"c\0\0\0\0\x01d");
+        assert_eq!(decoder.next_message().unwrap().as_deref(), Some(&b"abc"[..]));
+        assert_eq!(decoder.next_message().unwrap().as_deref(), Some(&b"d"[..]));
+        assert_eq!(decoder.next_message().unwrap(), None);
+        assert!(!decoder.has_remaining());
+    }
+
+    #[test]
+    fn decoder_rejects_compressed_and_oversized_messages() {
+        let mut decoder = MessageDecoder::new(16);
+        decoder.feed(b"\x01\0\0\0\x01a");
+        assert_eq!(code(decoder.next_message()), Code::Internal);
+
+        let mut decoder = MessageDecoder::new(16);
+        decoder.feed(b"\0\0\0\0\x11");
+        assert_eq!(code(decoder.next_message()), Code::ResourceExhausted);
+    }
+}
@@ -0,0 +1,563 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use http::header::{CONTENT_TYPE, TE};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Uri, Version};
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use http_body_util::BodyExt as HyperBodyExt;
+#[cfg(not(feature = "hyper-tls"))]
+use monoio_http::{
+    common::body::{Body, HttpBody},
+    h1::payload::{stream_payload_pair, StreamPayload, StreamPayloadSender},
+};
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use crate::hyper::{client::{MonoioHyperClient, StreamingBody}, hyper_body::{BodySender, HyperBody}};
+#[cfg(not(feature = "hyper-tls"))]
+use crate::http::client::MonoioClient;
+use crate::error::Error;
+use self::codec::{encode_message, MessageDecoder};
+
+pub use self::codec::{BytesCodec, Codec};
+#[cfg(feature = "grpc-prost")]
+pub use self::codec::ProstCodec;
+pub use self::status::{Code, Status};
+
+mod codec;
+mod status;
+
+const GRPC_TIMEOUT: HeaderName = HeaderName::from_static("grpc-timeout");
+const GRPC_ACCEPT_ENCODING: HeaderName = HeaderName::from_static("grpc-accept-encoding");
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+enum GrpcBody {
+    #[cfg(not(feature = "hyper-tls"))]
+    Monoio(HttpBody),
+    #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+    Hyper(StreamingBody),
+}
+
+impl GrpcBody {
+    /// Returns the next data chunk, trailers received along the way are stored in `trailers`.
+    async fn next_chunk(&mut self, trailers: &mut HeaderMap) -> Option<Result<Bytes, Error>> {
+        match self {
+            #[cfg(not(feature = "hyper-tls"))]
+            GrpcBody::Monoio(body) => {
+                let chunk = body.next_data().await;
+                if chunk.is_none() {
+                    // Trailers are only carried by HTTP/2 streams
+                    if let HttpBody::H2(stream) = body {
+                        match stream.trailers().await {
+                            Ok(fields) => trailers.extend(fields.unwrap_or_default()),
+                            Err(e) => return Some(Err(Error::BytesError(e.to_string()))),
+                        }
+                    }
+                }
+                chunk.map(|chunk| chunk.map_err(|e| Error::BytesError(e.to_string())))
+            }
+            #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+            GrpcBody::Hyper(body) => loop {
+                match body.frame().await? {
+                    Ok(frame) => match frame.into_data() {
+                        Ok(data) => return Some(Ok(data)),
+                        Err(frame) => {
+                            if let Ok(fields) = frame.into_trailers() {
+                                trailers.extend(fields);
+                            }
+                        }
+                    },
+                    Err(e) => return Some(Err(Error::BytesError(e.to_string()))),
+                }
+            },
+        }
+    }
+}
+
+/// gRPC client over the HTTP/2 connections of a `MonoioClient` or `MonoioHyperClient`.
+/// Messages are length-prefixed and serialized by a `Codec`, the call status is read from
+/// the `grpc-status` and `grpc-message` trailers and returned as `Error::GrpcError` unless OK.
+/// Calls go through the authentication, signing, circuit breaker, rate limiter and mock transport
+/// of the client. The request messages of streaming calls are fed by the caller through a
+/// `RequestSender` while the call is in flight, the response messages are read as they arrive.
+/// Available only on crate feature grpc
+/// # Examples
+/// ```
+/// let client = MonoioClient::builder().http2_prior_knowledge().build();
+/// let grpc = GrpcClient::new(client, Uri::from_static("http://localhost:50051"))
+///     .timeout(Duration::from_secs(5));
+/// let reply = grpc
+///     .unary("/helloworld.Greeter/SayHello", &request, ProstCodec::<HelloRequest, HelloReply>::default())
+///     .await?;
+/// ```
+pub struct GrpcClient<C> {
+    client: C,
+    origin: Uri,
+    metadata: HeaderMap,
+    timeout: Option<Duration>,
+    max_message_size: usize,
+}
+
+impl<C> GrpcClient<C> {
+    /// Creates a client calling the service at `origin`, given as `scheme://host:port`.
+    pub fn new(client: C, origin: Uri) -> Self {
+        GrpcClient {
+            client,
+            origin,
+            metadata: HeaderMap::new(),
+            timeout: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Adds a metadata entry sent as a header with every call.
+    pub fn metadata(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.metadata.append(name, value);
+        self
+    }
+
+    /// Sets the deadline of every call, sent to the server as `grpc-timeout`.
+    /// Calls running past it fail with `Code::DeadlineExceeded`.
+    pub fn timeout(mut self, val: Duration) -> Self {
+        self.timeout = Some(val);
+        self
+    }
+
+    /// Sets the maximum size of a response message. Default is 4 MiB.
+    pub fn max_decoding_message_size(mut self, val: usize) -> Self {
+        self.max_message_size = val;
+        self
+    }
+
+    /// Builds the HTTP/2 request of a call to `path`, such as `/package.Service/Method`.
+    fn request<B>(&self, path: &str, body: B) -> Result<Request<B>, Error> {
+        let mut parts = self.origin.clone().into_parts();
+        parts.path_and_query = Some(path.try_into().map_err(|e: http::uri::InvalidUri| {
+            Error::HttpRequestBuilder(e.into())
+        })?);
+        let uri = Uri::from_parts(parts).map_err(|e| Error::HttpRequestBuilder(e.into()))?;
+
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .version(Version::HTTP_2)
+            .body(body)
+            .map_err(Error::HttpRequestBuilder)?;
+
+        let headers = request.headers_mut();
+        headers.extend(self.metadata.clone());
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+        headers.insert(GRPC_ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+        if let Some(timeout) = self.timeout {
+            headers.insert(GRPC_TIMEOUT, encode_timeout(timeout));
+        }
+
+        Ok(request)
+    }
+
+    fn encode<D: Codec>(codec: &mut D, message: &D::Encode) -> Result<Bytes, Error> {
+        let mut buf = BytesMut::new();
+        encode_message(codec, message, &mut buf)?;
+        Ok(buf.freeze())
+    }
+
+    /// Returns the deadline of a call starting now, none if the timeout doesn't fit the clock.
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.and_then(|timeout| Instant::now().checked_add(timeout))
+    }
+
+    fn streaming_call<D: Codec>(&self, call: PendingCall, codec: D) -> Streaming<D> {
+        Streaming {
+            call: Some(call),
+            body: None,
+            decoder: MessageDecoder::new(self.max_message_size),
+            codec,
+            headers: HeaderMap::new(),
+            trailers: None,
+            deadline: self.deadline(),
+        }
+    }
+}
+
+/// Response head and body of a call, resolved once the server answered.
+type PendingCall = Pin<Box<dyn Future<Output = Result<(http::response::Parts, GrpcBody), Error>>>>;
+
+enum BodySink {
+    #[cfg(not(feature = "hyper-tls"))]
+    Monoio(StreamPayloadSender),
+    #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+    Hyper(BodySender),
+}
+
+/// Request messages of a streaming call, written as the connection accepts them.
+/// The request stream ends with `finish` or once the sender is dropped.
+/// Available only on crate feature grpc
+pub struct RequestSender<D> {
+    codec: D,
+    sink: BodySink,
+}
+
+impl<D: Codec> RequestSender<D> {
+    /// Encodes `message` and queues it on the call.
+    /// # Examples
+    /// ```
+    /// let (mut sender, mut stream) = grpc.streaming("/route.Guide/RouteChat", codec)?;
+    /// sender.send(&note)?;
+    /// let reply = stream.message().await?;
+    /// ```
+    pub fn send(&mut self, message: &D::Encode) -> Result<(), Error> {
+        let mut buf = BytesMut::new();
+        encode_message(&mut self.codec, message, &mut buf)?;
+        match &mut self.sink {
+            #[cfg(not(feature = "hyper-tls"))]
+            BodySink::Monoio(sender) => sender.feed_data(Some(buf.freeze())),
+            #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+            BodySink::Hyper(sender) => sender.send(buf.freeze()),
+        }
+        Ok(())
+    }
+
+    /// Ends the request stream, the server sees the end of the request messages.
+    pub fn finish(self) {}
+}
+
+impl<D> Drop for RequestSender<D> {
+    fn drop(&mut self) {
+        match &mut self.sink {
+            #[cfg(not(feature = "hyper-tls"))]
+            BodySink::Monoio(sender) => sender.feed_data(None),
+            // The body ends once its sender is dropped
+            #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+            BodySink::Hyper(_) => {}
+        }
+    }
+}
+
+/// Response messages of a call, followed by the call status.
+/// Available only on crate feature grpc
+pub struct Streaming<D> {
+    // Call waiting for the response head
+    call: Option<PendingCall>,
+    body: Option<GrpcBody>,
+    decoder: MessageDecoder,
+    codec: D,
+    headers: HeaderMap,
+    trailers: Option<HeaderMap>,
+    deadline: Option<Instant>,
+}
+
+impl<D: Codec> Streaming<D> {
+    /// Response headers sent by the server before the messages, waiting for them if needed.
+    pub async fn metadata(&mut self) -> Result<&HeaderMap, Error> {
+        let deadline = self.deadline;
+        with_deadline(deadline, self.head()).await?;
+        Ok(&self.headers)
+    }
+
+    /// Returns the next response message, `None` once the server ended the call with an OK status.
+    /// # Examples
+    /// ```
+    /// let mut stream = grpc.server_streaming("/route.Guide/ListFeatures", &rect, codec).await?;
+    /// while let Some(feature) = stream.message().await? {
+    ///     println!("{feature:?}");
+    /// }
+    /// ```
+    pub async fn message(&mut self) -> Result<Option<D::Decode>, Error> {
+        let deadline = self.deadline;
+        with_deadline(deadline, async {
+            self.head().await?;
+            self.read_message().await
+        })
+        .await
+    }
+
+    /// Reads the remaining messages and returns the trailers, they hold the call status.
+    pub async fn trailers(&mut self) -> Result<&HeaderMap, Error> {
+        while self.message().await?.is_some() {}
+        Ok(self.trailers.get_or_insert_with(HeaderMap::new))
+    }
+
+    /// Reads the single response message of a unary or client streaming call.
+    /// # Examples
+    /// ```
+    /// let (mut sender, stream) = grpc.client_streaming("/route.Guide/RecordRoute", codec)?;
+    /// for point in points {
+    ///     sender.send(&point)?;
+    /// }
+    /// sender.finish();
+    /// let summary = stream.single().await?;
+    /// ```
+    pub async fn single(mut self) -> Result<D::Decode, Error> {
+        let message = self
+            .message()
+            .await?
+            .ok_or_else(|| Error::GrpcError(Box::new(Status::new(Code::Internal, "missing response message"))))?;
+        if self.message().await?.is_some() {
+            return Err(Error::GrpcError(Box::new(Status::new(Code::Internal, "more than one response message"))));
+        }
+
+        Ok(message)
+    }
+
+    /// Waits for the response head of the call and validates it.
+    async fn head(&mut self) -> Result<(), Error> {
+        if let Some(call) = self.call.take() {
+            let (parts, body) = call.await?;
+            self.accept(parts, body)?;
+        }
+        Ok(())
+    }
+
+    /// Validates the response head, a trailers-only response ends the call right away.
+    fn accept(&mut self, parts: http::response::Parts, body: GrpcBody) -> Result<(), Error> {
+        let (status, version, headers) = (parts.status, parts.version, parts.headers);
+        if version != Version::HTTP_2 {
+            return Err(Error::GrpcError(Box::new(Status::new(
+                Code::Unavailable,
+                format!("gRPC requires HTTP/2, the server answered with {version:?}"),
+            ))));
+        }
+        if status != StatusCode::OK {
+            return Err(Error::GrpcError(Box::new(Status::new(
+                Code::from_http(status),
+                format!("unexpected http status {status}"),
+            ))));
+        }
+
+        let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+        if !content_type.starts_with("application/grpc") {
+            return Err(Error::GrpcError(Box::new(Status::new(
+                Code::Unknown,
+                format!("unexpected content type {content_type:?}"),
+            ))));
+        }
+
+        self.headers = headers;
+        if let Some(status) = Status::from_trailers(&self.headers) {
+            self.trailers = Some(self.headers.clone());
+            if status.code() != Code::Ok {
+                return Err(Error::GrpcError(Box::new(status)));
+            }
+        } else {
+            self.body = Some(body);
+        }
+
+        Ok(())
+    }
+
+    async fn read_message(&mut self) -> Result<Option<D::Decode>, Error> {
+        loop {
+            if let Some(message) = self.decoder.next_message()? {
+                return self.codec.decode(message).map(Some);
+            }
+
+            let Some(body) = self.body.as_mut() else {
+                return Ok(None);
+            };
+            let mut trailers = HeaderMap::new();
+            match body.next_chunk(&mut trailers).await {
+                Some(chunk) => self.decoder.feed(&chunk?),
+                None => {
+                    self.body = None;
+                    return self.finish(trailers).map(|()| None);
+                }
+            }
+        }
+    }
+
+    /// Checks the call status once the body ended.
+    fn finish(&mut self, trailers: HeaderMap) -> Result<(), Error> {
+        if self.decoder.has_remaining() {
+            return Err(Error::GrpcError(Box::new(Status::new(Code::Internal, "truncated response message"))));
+        }
+
+        let status = Status::from_trailers(&trailers)
+            .unwrap_or_else(|| Status::new(Code::Unknown, "missing grpc-status trailer"));
+        self.trailers = Some(trailers);
+        match status.code() {
+            Code::Ok => Ok(()),
+            _ => Err(Error::GrpcError(Box::new(status))),
+        }
+    }
+}
+
+/// Formats a `grpc-timeout` value, using the finest unit that fits in 8 digits.
+fn encode_timeout(timeout: Duration) -> HeaderValue {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    let value = [(1, 'n'), (1_000, 'u'), (1_000_000, 'm'), (1_000_000_000, 'S'), (60_000_000_000, 'M')]
+        .into_iter()
+        .find_map(|(unit, suffix)| {
+            let value = nanos.div_ceil(unit);
+            (value <= MAX).then(|| format!("{value}{suffix}"))
+        })
+        .unwrap_or_else(|| format!("{}H", (nanos / 3_600_000_000_000).min(MAX)));
+
+    HeaderValue::try_from(value).expect("grpc-timeout is ascii")
+}
+
+#[cfg(not(feature = "hyper-tls"))]
+impl GrpcClient<MonoioClient> {
+    /// Calls a unary method: one request message, one response message.
+    pub async fn unary<D: Codec>(&self, path: &str, message: &D::Encode, mut codec: D) -> Result<D::Decode, Error> {
+        let body = Self::encode(&mut codec, message)?;
+        self.call(path, body, None, codec)?.single().await
+    }
+
+    /// Calls a server streaming method, the response messages are read from the returned stream.
+    pub async fn server_streaming<D: Codec>(
+        &self,
+        path: &str,
+        message: &D::Encode,
+        mut codec: D,
+    ) -> Result<Streaming<D>, Error> {
+        let body = Self::encode(&mut codec, message)?;
+        let mut stream = self.call(path, body, None, codec)?;
+        stream.metadata().await?;
+        Ok(stream)
+    }
+
+    /// Calls a client streaming method. The request messages are fed through the returned sender,
+    /// the response message is read with `Streaming::single` once the sender is finished.
+    pub fn client_streaming<D: Codec + Clone>(
+        &self,
+        path: &str,
+        codec: D,
+    ) -> Result<(RequestSender<D>, Streaming<D>), Error> {
+        self.streaming(path, codec)
+    }
+
+    /// Calls a streaming method. The request messages are fed through the returned sender,
+    /// the response messages are read from the returned stream.
+    /// The pooled connections send the whole request body before they read the response head,
+    /// so the sender has to be finished before the first response message is read.
+    /// The Hyper client reads the response messages while the request messages are sent.
+    pub fn streaming<D: Codec + Clone>(
+        &self,
+        path: &str,
+        codec: D,
+    ) -> Result<(RequestSender<D>, Streaming<D>), Error> {
+        let (payload, sender) = stream_payload_pair();
+        let stream = self.call(path, Bytes::new(), Some(payload), codec.clone())?;
+        Ok((RequestSender { codec, sink: BodySink::Monoio(sender) }, stream))
+    }
+
+    /// Starts a call sending `body`, or the messages fed to `request_stream` when set.
+    fn call<D: Codec>(
+        &self,
+        path: &str,
+        body: Bytes,
+        request_stream: Option<StreamPayload>,
+        codec: D,
+    ) -> Result<Streaming<D>, Error> {
+        let request = self.request(path, Some(body))?;
+        let uri = request.uri().clone();
+        let client = self.client.clone();
+        let mut options = client.request_options();
+        options.request_stream = request_stream;
+
+        let call = async move {
+            let response = client.execute_streaming(request, uri, &options).await?;
+            let (parts, body) = response.into_parts();
+            Ok((parts, GrpcBody::Monoio(body)))
+        };
+        Ok(self.streaming_call(Box::pin(call), codec))
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+impl GrpcClient<MonoioHyperClient> {
+    /// Calls a unary method: one request message, one response message.
+    pub async fn unary<D: Codec>(&self, path: &str, message: &D::Encode, mut codec: D) -> Result<D::Decode, Error> {
+        let body = Self::encode(&mut codec, message)?;
+        self.call(path, HyperBody::from(body), codec)?.single().await
+    }
+
+    /// Calls a server streaming method, the response messages are read from the returned stream.
+    pub async fn server_streaming<D: Codec>(
+        &self,
+        path: &str,
+        message: &D::Encode,
+        mut codec: D,
+    ) -> Result<Streaming<D>, Error> {
+        let body = Self::encode(&mut codec, message)?;
+        let mut stream = self.call(path, HyperBody::from(body), codec)?;
+        stream.metadata().await?;
+        Ok(stream)
+    }
+
+    /// Calls a client streaming method. The request messages are fed through the returned sender,
+    /// the response message is read with `Streaming::single` once the sender is finished.
+    pub fn client_streaming<D: Codec + Clone>(
+        &self,
+        path: &str,
+        codec: D,
+    ) -> Result<(RequestSender<D>, Streaming<D>), Error> {
+        self.streaming(path, codec)
+    }
+
+    /// Calls a streaming method. The request messages are fed through the returned sender
+    /// and sent while the response messages are read from the returned stream.
+    pub fn streaming<D: Codec + Clone>(
+        &self,
+        path: &str,
+        codec: D,
+    ) -> Result<(RequestSender<D>, Streaming<D>), Error> {
+        let (sender, body) = HyperBody::channel();
+        let stream = self.call(path, body, codec.clone())?;
+        Ok((RequestSender { codec, sink: BodySink::Hyper(sender) }, stream))
+    }
+
+    fn call<D: Codec>(&self, path: &str, body: HyperBody, codec: D) -> Result<Streaming<D>, Error> {
+        let request = self.request(path, body)?;
+        let uri = request.uri().clone();
+        let client = self.client.clone();
+        let options = client.request_options();
+
+        let call = async move {
+            let response = client.execute_streaming(request, uri, &options).await?;
+            let (parts, body) = response.into_parts();
+            Ok((parts, GrpcBody::Hyper(body)))
+        };
+        Ok(self.streaming_call(Box::pin(call), codec))
+    }
+}
+
+async fn with_deadline<T>(
+    deadline: Option<Instant>,
+    call: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match deadline {
+        Some(deadline) => {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            monoio::time::timeout(remaining, call).await.unwrap_or_else(|_| {
+                Err(Error::GrpcError(Box::new(Status::new(Code::DeadlineExceeded, "deadline exceeded"))))
+            })
+        }
+        None => call.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_uses_the_finest_unit_that_fits() {
+        let cases = [
+            (Duration::from_nanos(50), "50n"),
+            (Duration::from_millis(1), "1000000n"),
+            (Duration::from_millis(100), "100000u"),
+            (Duration::from_nanos(100_000_001), "100001u"),
+            (Duration::from_secs(3600), "3600000m"),
+            (Duration::from_secs(200 * 86_400), "17280000S"),
+            (Duration::from_secs(10 * 365 * 86_400), "5256000M"),
+            (Duration::MAX, "99999999H"),
+        ];
+        for (timeout, expected) in cases {
+            assert_eq!(encode_timeout(timeout), expected, "{timeout:?}");
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+
+use http::{HeaderMap, StatusCode};
+
+/// gRPC status codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Code {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl Code {
+    /// Converts the value of a `grpc-status` trailer, unknown values map to `Code::Unknown`.
+    pub fn from_i32(code: i32) -> Code {
+        match code {
+            0 => Code::Ok,
+            1 => Code::Cancelled,
+            3 => Code::InvalidArgument,
+            4 => Code::DeadlineExceeded,
+            5 => Code::NotFound,
+            6 => Code::AlreadyExists,
+            7 => Code::PermissionDenied,
+            8 => Code::ResourceExhausted,
+            9 => Code::FailedPrecondition,
+            10 => Code::Aborted,
+            11 => Code::OutOfRange,
+            12 => Code::Unimplemented,
+            13 => Code::Internal,
+            14 => Code::Unavailable,
+            15 => Code::DataLoss,
+            16 => Code::Unauthenticated,
+            _ => Code::Unknown,
+        }
+    }
+
+    /// Maps the HTTP status of a response that is not a gRPC response,
+    /// as specified by the gRPC HTTP to gRPC status code mapping.
+    pub(crate) fn from_http(status: StatusCode) -> Code {
+        match status.as_u16() {
+            400 => Code::Internal,
+            401 => Code::Unauthenticated,
+            403 => Code::PermissionDenied,
+            404 => Code::Unimplemented,
+            429 | 502 | 503 | 504 => Code::Unavailable,
+            _ => Code::Unknown,
+        }
+    }
+}
+
+/// Outcome of a gRPC call, sent by the server in the `grpc-status` and `grpc-message` trailers.
+#[derive(Clone, Debug)]
+pub struct Status {
+    code: Code,
+    message: String,
+    metadata: HeaderMap,
+}
+
+impl Status {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Status {
+            code,
+            message: message.into(),
+            metadata: HeaderMap::new(),
+        }
+    }
+
+    /// Reads the status from trailers, or from the headers of a trailers-only response.
+    /// Returns `None` if `grpc-status` is missing.
+    pub(crate) fn from_trailers(trailers: &HeaderMap) -> Option<Status> {
+        let code = trailers
+            .get("grpc-status")?
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<i32>().ok())
+            .map_or(Code::Unknown, Code::from_i32);
+        let message = trailers
+            .get("grpc-message")
+            .map(|v| percent_decode(v.as_bytes()))
+            .unwrap_or_default();
+
+        Some(Status {
+            code,
+            message,
+            metadata: trailers.clone(),
+        })
+    }
+
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Trailers the status was read from.
+    pub fn metadata(&self) -> &HeaderMap {
+        &self.metadata
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status {:?}", self.code)?;
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the percent encoding of `grpc-message`, invalid sequences are kept as is.
+fn percent_decode(value: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        let escaped = (value[i] == b'%' && i + 2 < value.len())
+            .then(|| std::str::from_utf8(&value[i + 1..i + 3]).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(value[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+
+    fn trailers(fields: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        for (name, value) in fields {
+            trailers.insert(*name, HeaderValue::from_static(value));
+        }
+        trailers
+    }
+
+    #[test]
+    fn status_is_read_from_trailers() {
+        let status = Status::from_trailers(&trailers(&[
+            ("grpc-status", "5"),
+            ("grpc-message", "user%20not%20found%20%E2%9C%93"),
+        ]))
+        .unwrap();
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "user not found ✓");
+        assert_eq!(status.metadata()["grpc-status"], "5");
+
+        let status = Status::from_trailers(&trailers(&[("grpc-status", "0")])).unwrap();
+        assert_eq!(status.code(), Code::Ok);
+        assert_eq!(status.message(), "");
+    }
+
+    #[test]
+    fn missing_or_unknown_status() {
+        assert!(Status::from_trailers(&trailers(&[("grpc-message", "lost")])).is_none());
+        for value in ["99", "-1", "ok"] {
+            let status = Status::from_trailers(&trailers(&[("grpc-status", value)])).unwrap();
+            assert_eq!(status.code(), Code::Unknown);
+        }
+    }
+
+    #[test]
+    fn invalid_percent_sequences_are_kept() {
+        assert_eq!(percent_decode(b"100%zz%4"), "100%zz%4");
+        assert_eq!(percent_decode(b"%41%42"), "AB");
+    }
+}
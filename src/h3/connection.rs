@@ -0,0 +1,296 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use http::header::HOST;
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Version};
+use monoio::net::udp::UdpSocket;
+use quiche::h3::{self, Header, NameValue};
+
+use crate::{
+    error::{Error, Result},
+    request::PROHIBITED_HEADERS,
+    response::{ResponseLimits, Trailers},
+};
+
+pub(crate) const MAX_DATAGRAM_SIZE: usize = 1350;
+const RECV_BUF_SIZE: usize = 65535;
+const BODY_CHUNK_SIZE: usize = 16 * 1024;
+
+/// QUIC connection state along with the UDP socket driving it.
+pub(crate) struct QuicIo {
+    pub(crate) socket: UdpSocket,
+    pub(crate) quic: quiche::Connection,
+    pub(crate) local: SocketAddr,
+    pub(crate) peer: SocketAddr,
+    recv_buf: Option<Vec<u8>>,
+}
+
+impl QuicIo {
+    pub(crate) fn new(socket: UdpSocket, quic: quiche::Connection, local: SocketAddr, peer: SocketAddr) -> Self {
+        QuicIo {
+            socket,
+            quic,
+            local,
+            peer,
+            recv_buf: None,
+        }
+    }
+
+    /// Sends every packet quiche has ready.
+    async fn flush(&mut self) -> Result<()> {
+        loop {
+            let mut out = vec![0u8; MAX_DATAGRAM_SIZE];
+            let len = match self.quic.send(&mut out) {
+                Ok((len, _)) => len,
+                Err(quiche::Error::Done) => return Ok(()),
+                Err(e) => return Err(quic_error(e)),
+            };
+
+            out.truncate(len);
+            let (res, _) = self.socket.send(out).await;
+            res.map_err(Error::IoError)?;
+        }
+    }
+
+    /// Waits for the next datagram, or fires the connection timer if it expires first.
+    async fn recv(&mut self) -> Result<()> {
+        let buf = self
+            .recv_buf
+            .take()
+            .unwrap_or_else(|| Vec::with_capacity(RECV_BUF_SIZE));
+        let received = match self.quic.timeout() {
+            Some(timeout) => monoio::time::timeout(timeout, self.socket.recv(buf)).await.ok(),
+            None => Some(self.socket.recv(buf).await),
+        };
+
+        let Some((res, mut buf)) = received else {
+            self.quic.on_timeout();
+            return Ok(());
+        };
+
+        let len = res.map_err(Error::IoError)?;
+        let info = quiche::RecvInfo {
+            from: self.peer,
+            to: self.local,
+        };
+        match self.quic.recv(&mut buf[..len], info) {
+            Ok(_) | Err(quiche::Error::Done) => {}
+            Err(e) => return Err(quic_error(e)),
+        }
+
+        buf.clear();
+        self.recv_buf = Some(buf);
+        Ok(())
+    }
+
+    /// Drives the QUIC handshake to completion.
+    pub(crate) async fn handshake(&mut self) -> Result<()> {
+        while !self.quic.is_established() {
+            self.flush().await?;
+            if self.quic.is_closed() {
+                return Err(closed_error(&self.quic));
+            }
+            self.recv().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An established HTTP/3 connection, used by one request at a time.
+pub(crate) struct Connection {
+    io: QuicIo,
+    h3: h3::Connection,
+    goaway: bool,
+    idle_since: Instant,
+}
+
+impl Connection {
+    pub(crate) async fn new(mut io: QuicIo) -> Result<Self> {
+        let config = h3::Config::new().map_err(h3_error)?;
+        let h3 = h3::Connection::with_transport(&mut io.quic, &config).map_err(h3_error)?;
+        io.flush().await?;
+
+        Ok(Connection {
+            io,
+            h3,
+            goaway: false,
+            idle_since: Instant::now(),
+        })
+    }
+
+    /// Whether the connection can carry another request.
+    /// Idle connections are not driven, so they are only reused well within the idle timeout.
+    pub(crate) fn reusable(&self, idle_timeout: Duration) -> bool {
+        !self.goaway
+            && !self.io.quic.is_closed()
+            && !self.io.quic.is_draining()
+            && self.idle_since.elapsed() < idle_timeout / 2
+    }
+
+    /// Sends a request and collects its response.
+    pub(crate) async fn request<B>(
+        &mut self,
+        req: &Request<B>,
+        body: Option<&Bytes>,
+        limits: &ResponseLimits,
+    ) -> Result<Response<Bytes>> {
+        let headers = request_headers(req)?;
+        let body = body.filter(|b| !b.is_empty());
+
+        let stream_id = loop {
+            match self.h3.send_request(&mut self.io.quic, &headers, body.is_none()) {
+                Ok(id) => break id,
+                Err(h3::Error::StreamBlocked) => {
+                    self.io.flush().await?;
+                    self.io.recv().await?;
+                }
+                Err(e) => return Err(h3_error(e)),
+            }
+        };
+
+        if let Some(body) = body {
+            let mut written = 0;
+            while written < body.len() {
+                match self.h3.send_body(&mut self.io.quic, stream_id, &body[written..], true) {
+                    Ok(len) => written += len,
+                    Err(h3::Error::Done) | Err(h3::Error::StreamBlocked) => {
+                        self.io.flush().await?;
+                        self.io.recv().await?;
+                    }
+                    Err(e) => return Err(h3_error(e)),
+                }
+            }
+        }
+        self.io.flush().await?;
+
+        let mut head: Option<Response<()>> = None;
+        let mut trailers = HeaderMap::new();
+        let mut body = BytesMut::new();
+        let mut chunk = vec![0u8; BODY_CHUNK_SIZE];
+        loop {
+            loop {
+                match self.h3.poll(&mut self.io.quic) {
+                    Ok((id, h3::Event::Headers { list, .. })) if id == stream_id => {
+                        // Interim responses are skipped, a header block after the response holds trailers
+                        if head.is_some() {
+                            trailers = header_fields(&list)?;
+                            continue;
+                        }
+                        let response = response_head(&list)?;
+                        if !response.status().is_informational() {
+                            limits.check_headers(response.headers())?;
+                            head = Some(response);
+                        }
+                    }
+                    Ok((id, h3::Event::Data)) if id == stream_id => {
+                        loop {
+                            let len = match self.h3.recv_body(&mut self.io.quic, stream_id, &mut chunk) {
+                                Ok(len) => len,
+                                Err(h3::Error::Done) => break,
+                                Err(e) => return Err(h3_error(e)),
+                            };
+                            if let Some(max) = limits.max_body_size {
+                                if body.len() + len > max {
+                                    return Err(Error::ResponseBodyTooLarge(max));
+                                }
+                            }
+                            body.extend_from_slice(&chunk[..len]);
+                        }
+                    }
+                    Ok((id, h3::Event::Finished)) if id == stream_id => {
+                        let head = head.ok_or_else(|| {
+                            Error::Http3Error("stream finished without a response".to_string())
+                        })?;
+                        self.idle_since = Instant::now();
+                        let mut response = head.map(|()| body.freeze());
+                        response.extensions_mut().insert(Trailers(trailers));
+                        return Ok(response);
+                    }
+                    Ok((id, h3::Event::Reset(code))) if id == stream_id => {
+                        return Err(Error::Http3Error(format!("stream reset by the server with code {code}")));
+                    }
+                    Ok((_, h3::Event::GoAway)) => self.goaway = true,
+                    Ok(_) => {}
+                    Err(h3::Error::Done) => break,
+                    Err(e) => return Err(h3_error(e)),
+                }
+            }
+
+            if self.io.quic.is_closed() {
+                return Err(closed_error(&self.io.quic));
+            }
+            self.io.flush().await?;
+            self.io.recv().await?;
+        }
+    }
+}
+
+fn request_headers<B>(req: &Request<B>) -> Result<Vec<Header>> {
+    let authority = req
+        .uri()
+        .authority()
+        .ok_or_else(|| Error::Http3Error(format!("missing authority in {}", req.uri())))?;
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+
+    let mut headers = vec![
+        Header::new(b":method", req.method().as_str().as_bytes()),
+        Header::new(b":scheme", b"https"),
+        Header::new(b":authority", authority.as_str().as_bytes()),
+        Header::new(b":path", path.as_bytes()),
+    ];
+
+    // Connection specific headers are not allowed, the host is carried by :authority
+    for (name, value) in req.headers().iter() {
+        if name == HOST || name == "http2-settings" || PROHIBITED_HEADERS.contains(name) {
+            continue;
+        }
+        headers.push(Header::new(name.as_str().as_bytes(), value.as_bytes()));
+    }
+
+    Ok(headers)
+}
+
+fn response_head(list: &[Header]) -> Result<Response<()>> {
+    let mut response = Response::new(());
+    *response.version_mut() = Version::HTTP_3;
+
+    let status = list
+        .iter()
+        .find(|header| header.name() == b":status")
+        .and_then(|header| std::str::from_utf8(header.value()).ok())
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(|code| StatusCode::from_u16(code).ok());
+    *response.status_mut() =
+        status.ok_or_else(|| Error::Http3Error("missing :status pseudo header".to_string()))?;
+    *response.headers_mut() = header_fields(list)?;
+    Ok(response)
+}
+
+/// Converts a header block, skipping the pseudo headers.
+fn header_fields(list: &[Header]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::with_capacity(list.len());
+    for header in list.iter().filter(|header| !header.name().starts_with(b":")) {
+        let name = HeaderName::from_bytes(header.name()).map_err(|e| Error::Http3Error(e.to_string()))?;
+        let value = HeaderValue::from_bytes(header.value()).map_err(Error::InvalidHeaderValue)?;
+        headers.append(name, value);
+    }
+
+    Ok(headers)
+}
+
+pub(crate) fn quic_error(e: quiche::Error) -> Error {
+    Error::Http3Error(e.to_string())
+}
+
+fn h3_error(e: h3::Error) -> Error {
+    Error::Http3Error(e.to_string())
+}
+
+fn closed_error(quic: &quiche::Connection) -> Error {
+    match quic.peer_error() {
+        Some(err) => Error::Http3Error(format!("connection closed by the peer with code {}", err.error_code)),
+        None => Error::Http3Error("connection closed".to_string()),
+    }
+}
@@ -0,0 +1,336 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http::uri::Scheme;
+use http::{Request, Response, Uri};
+use monoio::net::udp::UdpSocket;
+use rand::RngCore;
+
+use crate::{
+    error::{Error, Result},
+    key::PoolKey,
+    origin::ProtocolMemory,
+    response::ResponseLimits,
+};
+use self::connection::{quic_error, Connection, QuicIo, MAX_DATAGRAM_SIZE};
+
+mod connection;
+
+/// How long a resolved address of an HTTP/3 endpoint is reused before it is looked up again.
+const RESOLVED_TTL: Duration = Duration::from_secs(60);
+
+/// Settings of the HTTP/3 transport.
+/// # Examples
+/// ```
+/// let client = MonoioClient::builder()
+///     .enable_https()
+///     .http3(Http3Config::new().idle_timeout(Duration::from_secs(10)))
+///     .build();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Http3Config {
+    prior_knowledge: bool,
+    verify_peer: bool,
+    ca_file: Option<PathBuf>,
+    idle_timeout: Duration,
+    handshake_timeout: Duration,
+}
+
+impl Default for Http3Config {
+    fn default() -> Self {
+        Http3Config {
+            prior_knowledge: false,
+            verify_peer: true,
+            ca_file: None,
+            idle_timeout: Duration::from_secs(30),
+            handshake_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Http3Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses HTTP/3 for every https origin, without waiting for an `Alt-Svc` advertisement.
+    pub fn prior_knowledge(mut self) -> Self {
+        self.prior_knowledge = true;
+        self
+    }
+
+    /// Disables verification of the server certificate.
+    /// Meant for local test servers using self-signed certificates.
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.verify_peer = false;
+        self
+    }
+
+    /// Trusts the PEM encoded CA certificates in the file at `path`.
+    pub fn ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_file = Some(path.into());
+        self
+    }
+
+    /// Sets the QUIC idle timeout. Default is 30 seconds.
+    pub fn idle_timeout(mut self, val: Duration) -> Self {
+        self.idle_timeout = val;
+        self
+    }
+
+    /// Sets how long establishing a QUIC connection may take before the request falls back
+    /// to HTTP/2 or HTTP/1.1. Default is 10 seconds.
+    pub fn handshake_timeout(mut self, val: Duration) -> Self {
+        self.handshake_timeout = val;
+        self
+    }
+}
+
+/// HTTP/3 endpoint serving an origin, found by prior knowledge or an `Alt-Svc` advertisement.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Route {
+    origin: PoolKey,
+    host: String,
+    port: u16,
+}
+
+/// Failure of an HTTP/3 exchange.
+pub(crate) enum Http3Failure {
+    /// The endpoint could not be reached, nothing was sent and the request can go over TCP.
+    Connect(Error),
+    /// The request may have been processed by the server.
+    Request(Error),
+}
+
+/// Dials and reuses HTTP/3 connections.
+pub(crate) struct Http3Connector {
+    config: Http3Config,
+    quic_config: RefCell<Option<quiche::Config>>,
+    idle: RefCell<HashMap<Route, Vec<Connection>>>,
+    resolver: Resolver,
+}
+
+impl Http3Connector {
+    pub(crate) fn new(config: Http3Config) -> Self {
+        Http3Connector {
+            config,
+            quic_config: RefCell::new(None),
+            idle: RefCell::new(HashMap::new()),
+            resolver: Resolver::default(),
+        }
+    }
+
+    /// Returns the HTTP/3 endpoint to use for `uri`, if any.
+    pub(crate) fn route(&self, uri: &Uri, protocols: &ProtocolMemory) -> Option<Route> {
+        if uri.scheme() != Some(&Scheme::HTTPS) {
+            return None;
+        }
+
+        let origin = PoolKey::try_from(uri).ok()?;
+        if let Some(endpoint) = protocols.h3_endpoint(&origin) {
+            return Some(Route {
+                host: endpoint.host,
+                port: endpoint.port,
+                origin,
+            });
+        }
+
+        let prior_knowledge = self.config.prior_knowledge && !protocols.is_h3_broken(&origin);
+        prior_knowledge.then(|| Route {
+            host: origin.host.to_string(),
+            port: origin.port,
+            origin,
+        })
+    }
+
+    /// Stops using HTTP/3 for the origin of `route` for a while.
+    pub(crate) fn mark_broken(&self, route: &Route, protocols: &ProtocolMemory) {
+        self.idle.borrow_mut().remove(route);
+        protocols.mark_h3_broken(&route.origin);
+    }
+
+    /// Sends the request over an idle or new connection to the endpoint of `route`.
+    pub(crate) async fn send<B>(
+        &self,
+        route: &Route,
+        req: &Request<B>,
+        body: Option<&Bytes>,
+        limits: &ResponseLimits,
+    ) -> std::result::Result<Response<Bytes>, Http3Failure> {
+        let mut conn = match self.checkout(route) {
+            Some(conn) => conn,
+            None => self.connect(route).await.map_err(Http3Failure::Connect)?,
+        };
+
+        let response = conn
+            .request(req, body, limits)
+            .await
+            .map_err(Http3Failure::Request)?;
+        if conn.reusable(self.config.idle_timeout) {
+            self.idle
+                .borrow_mut()
+                .entry(route.clone())
+                .or_default()
+                .push(conn);
+        }
+
+        Ok(response)
+    }
+
+    fn checkout(&self, route: &Route) -> Option<Connection> {
+        let mut idle = self.idle.borrow_mut();
+        let conns = idle.get_mut(route)?;
+        while let Some(conn) = conns.pop() {
+            if conn.reusable(self.config.idle_timeout) {
+                return Some(conn);
+            }
+        }
+
+        None
+    }
+
+    async fn connect(&self, route: &Route) -> Result<Connection> {
+        let handshake_timeout = self.config.handshake_timeout;
+        monoio::time::timeout(handshake_timeout, self.dial(route))
+            .await
+            .unwrap_or_else(|_| {
+                Err(Error::Http3Error(format!(
+                    "QUIC connection to {}:{} not established within {handshake_timeout:?}",
+                    route.host, route.port
+                )))
+            })
+    }
+
+    async fn dial(&self, route: &Route) -> Result<Connection> {
+        let peer = self.resolver.resolve(&route.host, route.port).await?;
+        let bind: SocketAddr = match peer {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+
+        let socket = UdpSocket::bind(bind).map_err(Error::IoError)?;
+        socket.connect(peer).await.map_err(Error::IoError)?;
+        let local = socket.local_addr().map_err(Error::IoError)?;
+
+        let mut scid = [0u8; quiche::MAX_CONN_ID_LEN];
+        rand::thread_rng().fill_bytes(&mut scid);
+        let scid = quiche::ConnectionId::from_ref(&scid);
+
+        // The certificate is verified against the origin, even when served from an alternative host
+        let quic = {
+            let mut config = self.quic_config.borrow_mut();
+            if config.is_none() {
+                *config = Some(self.build_quic_config()?);
+            }
+            let config = config.as_mut().expect("QUIC config initialized above");
+            quiche::connect(Some(route.origin.host.as_str()), &scid, local, peer, config).map_err(quic_error)?
+        };
+
+        let mut io = QuicIo::new(socket, quic, local, peer);
+        io.handshake().await?;
+        Connection::new(io).await
+    }
+
+    fn build_quic_config(&self) -> Result<quiche::Config> {
+        let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).map_err(quic_error)?;
+        config
+            .set_application_protos(quiche::h3::APPLICATION_PROTOCOL)
+            .map_err(quic_error)?;
+        config.verify_peer(self.config.verify_peer);
+        if let Some(path) = self.config.ca_file.as_ref() {
+            let path = path
+                .to_str()
+                .ok_or_else(|| Error::Http3Error(format!("invalid CA file path {}", path.display())))?;
+            config.load_verify_locations_from_file(path).map_err(quic_error)?;
+        }
+
+        config.set_max_idle_timeout(self.config.idle_timeout.as_millis() as u64);
+        config.set_max_recv_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_max_send_udp_payload_size(MAX_DATAGRAM_SIZE);
+        config.set_initial_max_data(10 * 1024 * 1024);
+        config.set_initial_max_stream_data_bidi_local(1024 * 1024);
+        config.set_initial_max_stream_data_bidi_remote(1024 * 1024);
+        config.set_initial_max_stream_data_uni(1024 * 1024);
+        config.set_initial_max_streams_bidi(100);
+        config.set_initial_max_streams_uni(100);
+        config.set_disable_active_migration(true);
+
+        Ok(config)
+    }
+}
+
+type LookupResult = std::io::Result<Option<SocketAddr>>;
+
+enum Lookup {
+    Pending(JoinHandle<LookupResult>),
+    Resolved(SocketAddr, Instant),
+}
+
+/// Resolves the addresses of HTTP/3 endpoints, keeping them for `RESOLVED_TTL`.
+///
+/// Lookups block, so each one runs on its own thread, polled without blocking the runtime since
+/// a monoio runtime can't be woken from another thread without the sync feature. A lookup outliving
+/// the handshake timeout keeps its thread until the system resolver gives up, but at most one lookup
+/// per endpoint is in flight: later connections wait for the same thread instead of spawning another.
+#[derive(Default)]
+struct Resolver {
+    lookups: RefCell<HashMap<(String, u16), Lookup>>,
+}
+
+impl Resolver {
+    async fn resolve(&self, host: &str, port: u16) -> Result<SocketAddr> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, port));
+        }
+
+        let key = (host.to_string(), port);
+        let mut interval = Duration::from_millis(1);
+        loop {
+            if let Some(res) = self.poll_lookup(&key) {
+                return res;
+            }
+            monoio::time::sleep(interval).await;
+            interval = (interval * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    /// Returns the address of the endpoint once known, starting a lookup if none is in flight.
+    fn poll_lookup(&self, key: &(String, u16)) -> Option<Result<SocketAddr>> {
+        let mut lookups = self.lookups.borrow_mut();
+        match lookups.remove(key) {
+            Some(Lookup::Resolved(addr, at)) if at.elapsed() < RESOLVED_TTL => {
+                lookups.insert(key.clone(), Lookup::Resolved(addr, at));
+                Some(Ok(addr))
+            }
+            Some(Lookup::Pending(lookup)) if lookup.is_finished() => {
+                let host = &key.0;
+                let res = lookup
+                    .join()
+                    .map_err(|_| Error::Http3Error(format!("resolving {host} panicked")))
+                    .and_then(|res| res.map_err(Error::IoError))
+                    .and_then(|addr| addr.ok_or_else(|| Error::Http3Error(format!("no address found for {host}"))));
+                if let Ok(addr) = res {
+                    lookups.insert(key.clone(), Lookup::Resolved(addr, Instant::now()));
+                }
+                Some(res)
+            }
+            Some(pending @ Lookup::Pending(_)) => {
+                lookups.insert(key.clone(), pending);
+                None
+            }
+            _ => {
+                let (name, port) = key.clone();
+                let lookup = std::thread::spawn(move || {
+                    (name.as_str(), port).to_socket_addrs().map(|mut addrs| addrs.next())
+                });
+                lookups.insert(key.clone(), Lookup::Pending(lookup));
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use http::Method;
+
+const DEFAULT_INITIAL_DELAY: Duration = Duration::from_millis(50);
+const DEFAULT_WINDOW: usize = 100;
+// Latencies needed before the percentile is trusted over the initial delay
+const MIN_SAMPLES: usize = 10;
+
+/// When to hedge a request, sending a second copy of it if the first hasn't answered in time.
+/// The first response to arrive is returned and the other request is dropped, closing its connection
+/// or resetting its HTTP/2 stream. Only idempotent methods are hedged, and only the response head is
+/// raced, the body is read from the winner. The attempt failing first is ignored while the other one
+/// is still running, a request failing before the delay is not hedged.
+///
+/// With `percentile`, the delay follows the latencies of the earlier requests sent with the policy,
+/// clones of a policy share them so a policy is meant to be kept per service and cloned into requests.
+/// # Examples
+/// ```
+/// let hedge = HedgePolicy::percentile(0.95).min_delay(Duration::from_millis(10));
+/// for key in keys {
+///     let response = client
+///         .make_request()
+///         .set_uri(format!("http://kv.internal/get/{key}"))
+///         .hedge(hedge.clone())
+///         .send()
+///         .await?;
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct HedgePolicy {
+    percentile: Option<f64>,
+    delay: Duration,
+    min_delay: Duration,
+    window: usize,
+    latencies: Rc<RefCell<VecDeque<Duration>>>,
+}
+
+impl HedgePolicy {
+    /// Hedges requests that haven't answered after `delay`.
+    pub fn after(delay: Duration) -> Self {
+        HedgePolicy {
+            percentile: None,
+            delay,
+            min_delay: Duration::ZERO,
+            window: DEFAULT_WINDOW,
+            latencies: Rc::default(),
+        }
+    }
+
+    /// Hedges requests slower than the `percentile` of the latencies observed so far, `0.95` hedges
+    /// about one request in twenty. Until enough requests completed the initial delay is used.
+    pub fn percentile(percentile: f64) -> Self {
+        HedgePolicy {
+            percentile: Some(percentile.clamp(0.0, 1.0)),
+            ..HedgePolicy::after(DEFAULT_INITIAL_DELAY)
+        }
+    }
+
+    /// Sets the delay used before enough latencies were observed, 50ms by default.
+    pub fn initial_delay(mut self, val: Duration) -> Self {
+        self.delay = val;
+        self
+    }
+
+    /// Sets a lower bound to the percentile based delay, so fast services aren't hedged too eagerly.
+    pub fn min_delay(mut self, val: Duration) -> Self {
+        self.min_delay = val;
+        self
+    }
+
+    /// Sets the number of recent latencies the percentile is computed over, 100 by default.
+    pub fn window(mut self, val: usize) -> Self {
+        self.window = val.max(1);
+        self
+    }
+
+    /// Whether requests with `method` may be sent twice.
+    pub(crate) fn applies(&self, method: &Method) -> bool {
+        method.is_idempotent()
+    }
+
+    fn delay(&self) -> Duration {
+        let Some(percentile) = self.percentile else {
+            return self.delay;
+        };
+        let latencies = self.latencies.borrow();
+        if latencies.len() < MIN_SAMPLES {
+            return self.delay.max(self.min_delay);
+        }
+
+        let mut sorted: Vec<Duration> = latencies.iter().copied().collect();
+        sorted.sort_unstable();
+        let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+        sorted[index].max(self.min_delay)
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.borrow_mut();
+        while latencies.len() >= self.window {
+            latencies.pop_front();
+        }
+        latencies.push_back(latency);
+    }
+
+    /// Runs `attempt`, and once more if it hasn't completed after the delay,
+    /// returning the first success or the first error once both failed.
+    pub(crate) async fn send<T, E, F, Fut>(&self, attempt: F) -> Result<T, E>
+        where
+            F: Fn() -> Fut,
+            Fut: Future<Output = Result<T, E>>,
+    {
+        let first = (Instant::now(), Box::pin(attempt()));
+        let mut attempts: [Option<(Instant, Pin<Box<Fut>>)>; 2] = [Some(first), None];
+        let mut timer = Some(Box::pin(monoio::time::sleep(self.delay())));
+        let mut error = None;
+
+        poll_fn(|cx| {
+            if let Some(sleep) = timer.as_mut() {
+                if sleep.as_mut().poll(cx).is_ready() {
+                    timer = None;
+                    attempts[1] = Some((Instant::now(), Box::pin(attempt())));
+                }
+            }
+
+            for slot in attempts.iter_mut() {
+                let Some((started, fut)) = slot.as_mut() else {
+                    continue;
+                };
+                if let Poll::Ready(result) = fut.as_mut().poll(cx) {
+                    let latency = started.elapsed();
+                    *slot = None;
+                    match result {
+                        Ok(value) => {
+                            self.record(latency);
+                            return Poll::Ready(Ok(value));
+                        }
+                        Err(e) => {
+                            error.get_or_insert(e);
+                        }
+                    }
+                }
+            }
+
+            // The first attempt failed before the delay, or both failed
+            if attempts.iter().all(Option::is_none) {
+                if let Some(e) = error.take() {
+                    return Poll::Ready(Err(e));
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
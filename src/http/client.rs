@@ -1,35 +1,80 @@
+use std::future::Future;
 use std::rc::Rc;
 use std::time::Duration;
 
-use http::{HeaderMap, Request, Uri};
+use bytes::Bytes;
+use http::header::AUTHORIZATION;
+use http::{HeaderMap, Request, StatusCode, Uri, Version};
 use monoio::net::TcpStream;
-use monoio_http::common::body::HttpBody;
+use monoio_http::{common::body::{FixedBody, HttpBody}, h1::payload::Payload};
 use monoio_transports::connectors::TlsConnector;
-use monoio_transports::connectors::{Connector, TcpConnector, TlsStream};
+use monoio_transports::connectors::{Connector, TlsStream};
 use monoio_transports::http::HttpConnector;
 
+#[cfg(feature = "cache")]
+use crate::cache::{CacheStatus, HttpCache, Lookup};
+#[cfg(feature = "http3")]
+use crate::h3::{Http3Config, Http3Connector, Http3Failure};
+#[cfg(feature = "mock")]
+use crate::mock::MockTransport;
+#[cfg(feature = "vcr")]
+use crate::vcr::Cassette;
+#[cfg(feature = "websocket")]
+use crate::http::websocket::WebSocketBuilder;
 use crate::{
+    auth::{RequestAuth, TokenProvider},
+    batch::Batch,
+    circuit::CircuitBreaker,
+    rate_limit::RateLimiter,
+    sign::{RequestSigner, SignerHook},
+    propagation::{Propagation, Propagator, TraceContext},
     error::{Error, Result, TransportError},
+    http::{monoio_body::MonoioBody, raw::{self, RawResponse}, stream::{tls_connector, RawConnector}, upgrade},
     key::PoolKey,
+    origin::ProtocolMemory,
     Protocol,
-    request::HttpRequest,
-    response::Response,
+    request::{ExpectContinue, HttpRequest, RequestBody, RequestOptions},
+    sse::EventSource,
+    response::{HttpResponse, Response, ResponseHead, ResponseLimits},
+    trace::{self, Phase, PhaseConnector, RequestTrace},
     apply_parameter_from_config,
 };
 
 enum HttpConnectorType {
-    HTTP(HttpConnector<TcpConnector, PoolKey, TcpStream>),
-    HTTPS(HttpConnector<TlsConnector<TcpConnector>, PoolKey, TlsStream<TcpStream>>),
+    HTTP(HttpConnector<PhaseConnector, PoolKey, TcpStream>),
+    HTTPS(HttpConnector<TlsConnector<PhaseConnector>, PoolKey, TlsStream<TcpStream>>),
 }
 
+// Streams allowed at once on an HTTP/2 connection when not set on the builder
+const DEFAULT_MAX_CONCURRENT_STREAMS: u32 = 100;
+
 #[derive(Default, Clone, Debug)]
 struct ClientConfig {
     default_headers: Rc<HeaderMap>,
+    response_limits: ResponseLimits,
+    expect_continue: ExpectContinue,
+    auth: RequestAuth,
+    signer: Option<SignerHook>,
+    propagation: Option<Propagation>,
+    protocol: Protocol,
+    max_concurrent_streams: Option<u32>,
+    #[cfg(feature = "cache")]
+    cache: Option<HttpCache>,
 }
 
 struct ClientInner {
     config: ClientConfig,
     http_connector: HttpConnectorType,
+    raw_connector: RawConnector,
+    protocols: ProtocolMemory,
+    rate_limiter: Option<RateLimiter>,
+    circuit_breaker: Option<CircuitBreaker>,
+    #[cfg(feature = "http3")]
+    http3: Option<Http3Connector>,
+    #[cfg(feature = "mock")]
+    mock: Option<MockTransport>,
+    #[cfg(feature = "vcr")]
+    cassette: Option<Cassette>,
 }
 
 pub struct MonoioClient {
@@ -61,6 +106,23 @@ struct ClientBuilderConfig {
     initial_max_streams: Option<usize>,
     max_concurrent_streams: Option<u32>,
     default_headers: HeaderMap,
+    response_limits: ResponseLimits,
+    expect_continue: ExpectContinue,
+    auth: RequestAuth,
+    signer: Option<SignerHook>,
+    propagation: Option<Propagation>,
+    rate_limiter: Option<RateLimiter>,
+    circuit_breaker: Option<CircuitBreaker>,
+    #[cfg(feature = "cache")]
+    cache: Option<HttpCache>,
+    #[cfg(feature = "http3")]
+    http3: Option<Http3Config>,
+    #[cfg(feature = "mock")]
+    mock: Option<MockTransport>,
+    #[cfg(feature = "vcr")]
+    cassette: Option<Cassette>,
+    #[cfg(feature = "testing")]
+    root_certificates: Vec<Vec<u8>>,
 }
 
 #[derive(Default)]
@@ -142,13 +204,166 @@ impl ClientBuilder {
         self.build_config.enable_https = true;
         self
     }
+
+    /// Trusts `certificate`, DER encoded, as a root on TLS connections besides the web PKI roots,
+    /// for instance the self-signed certificate of a `TestServer`.
+    /// Available only on crate feature testing
+    #[cfg(feature = "testing")]
+    pub fn add_root_certificate(mut self, certificate: impl Into<Vec<u8>>) -> Self {
+        self.build_config.root_certificates.push(certificate.into());
+        self
+    }
+
+    /// Enables HTTP/3 for https origins advertising it through `Alt-Svc`, or for all of them
+    /// with `Http3Config::prior_knowledge`. Other requests negotiate HTTP/2 or HTTP/1.1 as in the
+    /// default mode, which is also used when the HTTP/3 endpoint can't be reached.
+    /// Available only on crate feature http3
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, config: Http3Config) -> Self {
+        self.build_config.protocol = Protocol::Http3;
+        self.build_config.http3 = Some(config);
+        self
+    }
+
+    /// Answers the requests of the client from `transport` instead of the network.
+    /// Available only on crate feature mock
+    #[cfg(feature = "mock")]
+    pub fn mock(mut self, transport: MockTransport) -> Self {
+        self.build_config.mock = Some(transport);
+        self
+    }
+
+    /// Records the exchanges of the client to `cassette`, or answers requests from it,
+    /// depending on its `VcrMode`.
+    /// Available only on crate feature vcr
+    #[cfg(feature = "vcr")]
+    pub fn cassette(mut self, cassette: Cassette) -> Self {
+        self.build_config.cassette = Some(cassette);
+        self
+    }
+
+    /// Sets the maximum size in bytes of a response body read through this client.
+    /// Responses advertising a larger `Content-Length` are rejected before reading,
+    /// streamed bodies are rejected once the limit is crossed. Can be overridden per request.
+    pub fn max_response_body_size(mut self, val: usize) -> Self {
+        self.build_config.response_limits.max_body_size = Some(val);
+        self
+    }
+
+    /// Sets the maximum number of headers accepted in a response.
+    /// The dedicated HTTP/1.1 connections stop parsing once it is exceeded, the pooled HTTP/1.1
+    /// connections check it on the head parsed within the bounds of their own parser.
+    pub fn max_response_headers(mut self, val: usize) -> Self {
+        self.build_config.response_limits.max_headers = Some(val);
+        self
+    }
+
+    /// Sets the maximum accumulated size in bytes of the response headers, counted as
+    /// `name: value\r\n` per header. The dedicated HTTP/1.1 connections stop reading a larger head,
+    /// HTTP/2 connections advertise it as `SETTINGS_MAX_HEADER_LIST_SIZE` with the per field overhead
+    /// of the setting allowed for.
+    pub fn max_response_header_size(mut self, val: usize) -> Self {
+        self.build_config.response_limits.max_header_size = Some(val);
+        self
+    }
+
+    /// Sends request bodies of at least `val` bytes only after the server answered `100 Continue`
+    /// to the request head, see `HttpRequest::expect_continue`.
+    pub fn expect_continue_threshold(mut self, val: usize) -> Self {
+        self.build_config.expect_continue.threshold = Some(val);
+        self
+    }
+
+    /// Sets how long a request with `Expect: 100-continue` waits for the server before sending
+    /// its body anyway. Default is 1 second.
+    pub fn expect_continue_timeout(mut self, val: Duration) -> Self {
+        self.build_config.expect_continue.timeout = val;
+        self
+    }
+
+    /// Sends the bearer token of `provider` with every request that doesn't set its own
+    /// `Authorization` header, a request answered with `401 Unauthorized` is retried once
+    /// with a refreshed token.
+    pub fn token_provider(mut self, provider: impl TokenProvider + 'static) -> Self {
+        self.build_config.auth.token_provider = Some(Rc::new(provider));
+        self
+    }
+
+    /// Signs every request with `signer` once its headers are final, including the computed `Host`,
+    /// right before it is sent. See `HttpRequest::sign_with` to sign a single request.
+    pub fn signer(mut self, signer: impl RequestSigner + 'static) -> Self {
+        self.build_config.signer = Some(SignerHook::new(signer));
+        self
+    }
+
+    /// Injects the current `TraceContext` into the headers of every request with `propagator`,
+    /// see `TraceContext::scope`.
+    /// # Examples
+    /// ```
+    /// let client = builder.trace_propagation(W3CTraceContext).build();
+    /// ```
+    pub fn trace_propagation(self, propagator: impl Propagator + 'static) -> Self {
+        self.trace_propagation_with(propagator, TraceContext::current)
+    }
+
+    /// Injects the trace context returned by `source` into the headers of every request with `propagator`,
+    /// for tracers keeping their own notion of the current span.
+    pub fn trace_propagation_with<F>(mut self, propagator: impl Propagator + 'static, source: F) -> Self
+        where
+            F: Fn() -> Option<TraceContext> + 'static,
+    {
+        self.build_config.propagation = Some(Propagation::new(propagator, source));
+        self
+    }
+
+    /// Delays requests going over the network so they stay under the rates of `limiter`,
+    /// requests answered from the cache or a cassette are not counted.
+    /// # Examples
+    /// ```
+    /// let client = builder.rate_limiter(RateLimiter::new().per_host(Rate::per_second(10))).build();
+    /// ```
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.build_config.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Fails requests to origins whose circuit is open in `breaker` with `Error::CircuitOpen`,
+    /// without waiting for the rate limiter or touching the network.
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.build_config.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Enables the http cache for `GET` requests made through this client.
+    /// The cache status of each response is available as `CacheStatus` in its extensions.
+    /// Available only on crate feature cache
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.build_config.cache = Some(cache);
+        self
+    }
 }
 
 impl ClientBuilder {
     pub fn build(self) -> MonoioClient {
         let build_config = self.build_config.clone();
-        let config = ClientConfig::default();
-        let tcp_connector = TcpConnector::default();
+        let config = ClientConfig {
+            default_headers: Rc::new(build_config.default_headers.clone()),
+            response_limits: build_config.response_limits,
+            expect_continue: build_config.expect_continue,
+            auth: build_config.auth.clone(),
+            signer: build_config.signer.clone(),
+            propagation: build_config.propagation.clone(),
+            protocol: build_config.protocol.clone(),
+            max_concurrent_streams: build_config.max_concurrent_streams,
+            #[cfg(feature = "cache")]
+            cache: build_config.cache.clone(),
+        };
+
+        #[cfg(feature = "testing")]
+        let root_certificates = build_config.root_certificates.clone();
+        #[cfg(not(feature = "testing"))]
+        let root_certificates = Vec::new();
 
         let mut http_connector = if build_config.enable_https {
             // TLS implemented Connector
@@ -157,9 +372,12 @@ impl ClientBuilder {
                 Protocol::Http1 => vec!["http/1.1"],
                 Protocol::Http2 => vec!["h2"],
                 Protocol::Auto => vec!["http/1.1", "h2"],
+                // Requests falling back from HTTP/3 negotiate as in Auto
+                #[cfg(feature = "http3")]
+                Protocol::Http3 => vec!["http/1.1", "h2"],
             };
 
-            let tls_connector = TlsConnector::new_with_tls_default(tcp_connector, Some(alpn));
+            let tls_connector = tls_connector(alpn, &root_certificates);
 
             #[cfg(feature = "pool")]
             let https_connector = HttpConnectorType::HTTPS(HttpConnector::new_with_pool_options(
@@ -174,10 +392,10 @@ impl ClientBuilder {
         } else {
             // Default TCP Connector without TLS support
             #[cfg(not(feature = "pool"))]
-            let mut connector = HttpConnector::new(tcp_connector);
+            let mut connector = HttpConnector::new(PhaseConnector::default());
             #[cfg(feature = "pool")]
             let mut connector = HttpConnector::new_with_pool_options(
-                tcp_connector,
+                PhaseConnector::default(),
                 build_config.max_idle_connections,
                 build_config.idle_timeout_duration,
             );
@@ -205,11 +423,25 @@ impl ClientBuilder {
             apply_parameter_from_config!(http_connector, h2_builder().max_concurrent_streams(val));
         }
 
+        if let Some(val) = build_config.response_limits.h2_max_header_list_size() {
+            apply_parameter_from_config!(http_connector, h2_builder().max_header_list_size(val));
+        }
+
         apply_parameter_from_config!(http_connector, set_read_timeout(build_config.read_timeout));
 
         let inner = Rc::new(ClientInner {
             config,
             http_connector,
+            raw_connector: RawConnector::with_root_certificates(root_certificates),
+            protocols: ProtocolMemory::default(),
+            rate_limiter: build_config.rate_limiter,
+            circuit_breaker: build_config.circuit_breaker,
+            #[cfg(feature = "http3")]
+            http3: build_config.http3.map(Http3Connector::new),
+            #[cfg(feature = "mock")]
+            mock: build_config.mock,
+            #[cfg(feature = "vcr")]
+            cassette: build_config.cassette,
         });
 
         MonoioClient { inner }
@@ -219,15 +451,447 @@ impl ClientBuilder {
 impl MonoioClient {
     /// Returns a new http request with default parameters
     pub fn make_request(&self) -> HttpRequest<MonoioClient> {
-        let mut request = HttpRequest::new(self.clone());
-        for (key, val) in self.inner.config.default_headers.iter() {
-            request = request.set_header(key, val)
+        HttpRequest::new(self.clone(), self.request_options())
+    }
+
+    /// Per request settings initialised from the client configuration.
+    pub(crate) fn request_options(&self) -> RequestOptions {
+        RequestOptions {
+            default_headers: self.inner.config.default_headers.clone(),
+            limits: self.inner.config.response_limits,
+            expect_continue: self.inner.config.expect_continue,
+            auth: self.inner.config.auth.clone(),
+            signer: self.inner.config.signer.clone(),
+            propagation: self.inner.config.propagation.clone(),
+            ..RequestOptions::default()
+        }
+    }
+
+    /// Returns a builder opening a WebSocket connection to `uri`, using the `ws` or `wss` scheme.
+    /// Default headers of the client are sent with the handshake.
+    /// Available only on crate feature websocket
+    /// # Examples
+    /// ```
+    /// let mut ws = client.websocket("wss://example.com/socket").connect().await?;
+    /// ws.send(Message::Text("hello".to_string())).await?;
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn websocket<T>(&self, uri: T) -> WebSocketBuilder
+        where
+            Uri: TryFrom<T>,
+            <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        WebSocketBuilder::new(self.clone(), Uri::try_from(uri).map_err(Into::into))
+    }
+
+    /// Opens a server-sent events stream, reconnecting with `Last-Event-ID` when the connection drops.
+    /// # Examples
+    /// ```
+    /// let mut events = client.event_source("https://example.com/events");
+    /// while let Some(event) = events.next_event().await? {
+    ///     println!("{}", event.data);
+    /// }
+    /// ```
+    pub fn event_source<T>(&self, uri: T) -> EventSource<MonoioClient>
+        where
+            Uri: TryFrom<T>,
+            <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        EventSource::new(self.clone(), Uri::try_from(uri).map_err(Into::into))
+    }
+
+    /// Collects requests to send concurrently with per origin and global limits, see `Batch`.
+    /// # Examples
+    /// ```
+    /// let responses = client
+    ///     .batch()
+    ///     .requests(uris.iter().map(|uri| client.make_request().set_uri(uri)))
+    ///     .send()
+    ///     .await;
+    /// ```
+    pub fn batch(&self) -> Batch<MonoioClient> {
+        Batch::new(self.clone())
+    }
+
+    /// Returns the protocol the next request to the origin of `uri` is expected to use,
+    /// as negotiated by earlier requests or advertised through `Alt-Svc`.
+    pub fn known_protocol(&self, uri: &Uri) -> Option<Version> {
+        let key = PoolKey::try_from(uri).ok()?;
+        self.inner.protocols.known_version(&key)
+    }
+
+    /// Returns the number of streams open at once on the HTTP/2 connection to the origin of `key`,
+    /// `None` unless the origin is known to speak HTTP/2.
+    pub(crate) fn stream_limit(&self, key: &PoolKey) -> Option<usize> {
+        let config = &self.inner.config;
+        let h2 = config.protocol.is_protocol_h2()
+            || self.inner.protocols.known_version(key) == Some(Version::HTTP_2);
+        h2.then(|| config.max_concurrent_streams.unwrap_or(DEFAULT_MAX_CONCURRENT_STREAMS) as usize)
+    }
+
+    #[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+    pub(crate) fn default_headers(&self) -> &HeaderMap {
+        &self.inner.config.default_headers
+    }
+
+    #[cfg(feature = "websocket")]
+    pub(crate) fn raw_connector(&self) -> &RawConnector {
+        &self.inner.raw_connector
+    }
+
+    /// Sends the request, authenticating it with the token provider or answering a Digest challenge.
+    pub(crate) async fn execute(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<HttpBody>> {
+        self.authenticated(req, uri, options, |req, uri| self.execute_once(req, uri, options))
+            .await
+    }
+
+    /// Sends a request whose response body is handed back unread, used by long lived streams.
+    /// The request is authenticated, signed and guarded like the others,
+    /// the cache, the cassette and hedging don't apply.
+    pub(crate) async fn execute_streaming(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<Response<HttpBody>> {
+        self.authenticated(req, uri, options, |req, uri| self.stream_once(req, uri, options))
+            .await
+    }
+
+    /// Sends an upgrade or `CONNECT` request over a dedicated HTTP/1.1 connection that can be taken over.
+    /// The request is authenticated, signed and guarded like the others,
+    /// the cache, the cassette and hedging don't apply.
+    pub(crate) async fn execute_upgrade(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<RawResponse> {
+        self.authenticated(req, uri, options, |req, uri| self.upgrade_once(req, uri, options))
+            .await
+    }
+
+    /// Injects the trace context, authorizes the request with the token provider, and sends it
+    /// once more answering a Digest challenge or with a refreshed token if it was rejected.
+    async fn authenticated<R, F, Fut>(
+        &self,
+        mut req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+        send: F,
+    ) -> Result<R>
+        where
+            R: ResponseHead,
+            F: Fn(Request<Option<Bytes>>, Uri) -> Fut,
+            Fut: Future<Output = Result<R>>,
+    {
+        if let Some(propagation) = options.propagation.as_ref() {
+            propagation.inject(req.headers_mut());
         }
 
-        request
+        if !options.auth.is_enabled() {
+            return send(req, uri).await;
+        }
+
+        let (mut parts, body) = req.into_parts();
+        options.auth.authorize(&mut parts.headers).await?;
+        let response = send(Request::from_parts(parts.clone(), body.clone()), uri.clone()).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        match options.auth.reauthorize(&parts, body.as_ref(), response.headers()).await? {
+            Some(authorization) => {
+                parts.headers.insert(AUTHORIZATION, authorization);
+                send(Request::from_parts(parts, body), uri).await
+            }
+            None => Ok(response),
+        }
+    }
+
+    async fn execute_once(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<HttpBody>> {
+        let payload = req.body().clone();
+        let req = options.sign(req, payload)?;
+
+        #[cfg(feature = "cache")]
+        if let Some(cache) = self.inner.config.cache.as_ref() {
+            return self.execute_cached(cache, req, uri, options).await;
+        }
+
+        let response = self.dispatch(req, uri, options).await?;
+        HttpResponse::new(response, options.limits, options.download_progress.clone())
+    }
+
+    #[cfg(feature = "cache")]
+    async fn execute_cached(
+        &self,
+        cache: &HttpCache,
+        mut req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<HttpBody>> {
+        let limits = options.limits;
+        let lookup = cache.lookup(&mut req);
+        if let Lookup::Fresh(response) = lookup {
+            let response = response.map(|body| HttpBody::fixed_body(Some(body)));
+            return HttpResponse::new(response, limits, options.download_progress.clone());
+        }
+
+        let request_time = HttpCache::request_time();
+        let (req_parts, body) = req.into_parts();
+        let response = self
+            .dispatch(Request::from_parts(req_parts.clone(), body), uri, options)
+            .await?;
+        let (mut parts, body) = response.into_parts();
+
+        match lookup {
+            Lookup::Revalidate(entry) if parts.status == StatusCode::NOT_MODIFIED => {
+                let response = cache
+                    .revalidated(&req_parts, entry, &parts, request_time)
+                    .map(|body| HttpBody::fixed_body(Some(body)));
+                return HttpResponse::new(response, limits, options.download_progress.clone());
+            }
+            _ => cache.invalidate(&req_parts, &parts),
+        }
+
+        parts.extensions.insert(CacheStatus::Miss);
+        if !cache.is_storable(&req_parts, &parts) {
+            return HttpResponse::new(Response::from_parts(parts, body), limits, options.download_progress.clone());
+        }
+
+        // Storable responses are buffered so they can be kept in the cache
+        let bytes = HttpResponse::new(
+            Response::from_parts(parts.clone(), body),
+            limits,
+            options.download_progress.clone(),
+        )?
+        .bytes()
+        .await?;
+        cache.store(&req_parts, &parts, &bytes, request_time);
+
+        HttpResponse::new(Response::from_parts(parts, HttpBody::fixed_body(Some(bytes))), limits, None)
+    }
+
+    /// Answers the request from the cassette of the client if it has one, recording the exchange
+    /// when it goes over the network.
+    async fn dispatch(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<Response<HttpBody>> {
+        #[cfg(feature = "vcr")]
+        if let Some(cassette) = self.inner.cassette.as_ref() {
+            if let Some(response) = cassette.replay(&req, &uri, req.body().as_ref())? {
+                return Ok(response.map(|body| HttpBody::fixed_body(Some(body))));
+            }
+
+            let recording = cassette.recording(&req, &uri, req.body().as_ref());
+            let (parts, body) = self.hedged(req, uri, options).await?.into_parts();
+            let bytes = HttpResponse::new(Response::from_parts(parts.clone(), body), options.limits, None)?
+                .bytes()
+                .await?;
+            recording.finish(parts.status, parts.version, &parts.headers, &bytes)?;
+            return Ok(Response::from_parts(parts, HttpBody::fixed_body(Some(bytes))));
+        }
+
+        self.hedged(req, uri, options).await
+    }
+
+    /// Sends the request, and a copy of it once the hedging delay of the request passed
+    /// without a response, returning the response arriving first.
+    async fn hedged(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<Response<HttpBody>> {
+        let Some(hedge) = options.hedge.as_ref().filter(|hedge| hedge.applies(req.method())) else {
+            return self.guarded(req, uri, |req, uri| self.transport(req, uri, options)).await;
+        };
+
+        let (parts, body) = req.into_parts();
+        hedge
+            .send(|| {
+                let req = Request::from_parts(parts.clone(), body.clone());
+                self.guarded(req, uri.clone(), |req, uri| self.transport(req, uri, options))
+            })
+            .await
+    }
+
+    /// Fails fast while the circuit of the origin is open, then waits for the rate limiter of the client
+    /// before sending the request. The outcome is counted by the circuit breaker, and pauses the rate
+    /// limiter when the response says the quota is spent.
+    async fn guarded<B, R, F, Fut>(&self, req: Request<B>, uri: Uri, send: F) -> Result<R>
+        where
+            R: ResponseHead,
+            F: FnOnce(Request<B>, Uri) -> Fut,
+            Fut: Future<Output = Result<R>>,
+    {
+        let permit = match self.inner.circuit_breaker.as_ref() {
+            Some(breaker) => Some(breaker.permit(&uri)?),
+            None => None,
+        };
+        let key = match self.inner.rate_limiter.as_ref() {
+            Some(limiter) => limiter.acquire(&uri, req.headers()).await,
+            None => None,
+        };
+
+        let result = send(req, uri).await;
+        if let Some(permit) = permit {
+            permit.finish(result.as_ref().ok().map(|response| response.status()));
+        }
+        let response = result?;
+        if let Some(limiter) = self.inner.rate_limiter.as_ref() {
+            limiter.observe(key.as_ref(), response.status(), response.headers());
+        }
+        Ok(response)
+    }
+
+    /// Sends the request over a dedicated HTTP/1.1 connection when the body waits for `100 Continue`,
+    /// carries trailer fields or reports the upload progress, over HTTP/3 when the origin is known to support it,
+    /// over the pooled HTTP/2 or HTTP/1.1 connections otherwise.
+    async fn transport(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<Response<HttpBody>> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.inner.mock.as_ref() {
+            let response = mock.respond(&req, req.body().as_ref())?;
+            return Ok(response.map(|body| HttpBody::fixed_body(Some(body))));
+        }
+
+        let len = req.body().as_ref().map_or(0, Bytes::len);
+        if options.needs_raw_body(len) {
+            if options.upload_progress.is_some() && self.inner.config.protocol.is_protocol_h2() {
+                // The pooled connections take the body whole, only HTTP/1.1 can report it as it is written
+                return Err(Error::HttpVersionMismatch(
+                    "upload progress is only reported over HTTP/1.1, the client uses HTTP/2 prior knowledge".to_string(),
+                ));
+            }
+            let trace = RequestTrace::new(req.method(), &uri);
+            let send = raw::send(&self.inner.raw_connector, req, uri, options.raw_body(len), &options.limits);
+            let raw = trace.raw(send).await?;
+            return Ok(Response::from_parts(raw.parts, HttpBody::fixed_body(Some(raw.body))));
+        }
+
+        let protocols = &self.inner.protocols;
+        #[cfg(feature = "http3")]
+        if let Some(http3) = self.inner.http3.as_ref() {
+            if let Some(route) = http3.route(&uri, protocols) {
+                match http3.send(&route, &req, req.body().as_ref(), &options.limits).await {
+                    Ok(response) => return Ok(response.map(|body| HttpBody::fixed_body(Some(body)))),
+                    Err(Http3Failure::Connect(_)) => http3.mark_broken(&route, protocols),
+                    Err(Http3Failure::Request(e)) => return Err(e),
+                }
+            }
+        }
+
+        let key = PoolKey::try_from(&uri).map_err(Error::UriKeyError)?;
+        let response = self
+            .send_request(req.map(MonoioBody::create_body), uri)
+            .await?;
+        // The connector picks pooled connections by itself, the version is recorded for `known_protocol`
+        protocols.record_version(&key, response.version());
+        protocols.observe_alt_svc(&key, response.headers());
+
+        Ok(response)
+    }
+
+    /// Signs and sends a request whose response body is streamed, through the circuit breaker
+    /// and the rate limiter.
+    async fn stream_once(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<Response<HttpBody>> {
+        let payload = req.body().clone();
+        let req = options.sign(req, payload)?;
+        let response = self
+            .guarded(req, uri, |req, uri| self.transport_streaming(req, uri, options))
+            .await?;
+        options.limits.check_headers(response.headers())?;
+        Ok(response)
+    }
+
+    /// Signs and sends an upgrade or `CONNECT` request through the circuit breaker and the rate limiter.
+    async fn upgrade_once(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<RawResponse> {
+        let payload = req.body().clone();
+        let req = options.sign(req, payload)?;
+        self.guarded(req, uri, |req, uri| self.transport_upgrade(req, uri, options))
+            .await
+    }
+
+    /// Sends an upgrade or `CONNECT` request over a dedicated HTTP/1.1 connection.
+    /// Mocked responses carry no connection to take over.
+    async fn transport_upgrade(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<RawResponse> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.inner.mock.as_ref() {
+            let (parts, body) = mock.respond(&req, req.body().as_ref())?.into_parts();
+            return Ok(RawResponse { parts, body, upgraded: None });
+        }
+
+        let target = options.connect_target.as_deref();
+        let trace = RequestTrace::new(req.method(), &uri);
+        trace
+            .raw(upgrade::send(&self.inner.raw_connector, req, uri, target, &options.limits))
+            .await
+    }
+
+    /// Sends a request whose response body is streamed over the pooled HTTP/2 or HTTP/1.1 connections.
+    /// The request body is the stream fed by the caller when the options carry one.
+    async fn transport_streaming(
+        &self,
+        req: Request<Option<Bytes>>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<Response<HttpBody>> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.inner.mock.as_ref() {
+            let response = mock.respond(&req, req.body().as_ref())?;
+            return Ok(response.map(|body| HttpBody::fixed_body(Some(body))));
+        }
+
+        let req = match options.request_stream.clone() {
+            Some(stream) => req.map(|_| HttpBody::H1(Payload::Stream(stream))),
+            None => req.map(MonoioBody::create_body),
+        };
+        self.send_request(req, uri).await
+    }
+
+    async fn send_request(
+        &self,
+        req: Request<HttpBody>,
+        uri: Uri,
+    ) -> Result<Response<HttpBody>> {
+        let trace = RequestTrace::new(req.method(), &uri);
+        trace.response(self.send_pooled(req, uri)).await
     }
 
-    pub(crate) async fn send_request(
+    async fn send_pooled(
         &self,
         req: Request<HttpBody>,
         uri: Uri,
@@ -237,19 +901,17 @@ impl MonoioClient {
         let (response, _) = match self.inner.http_connector
         {
             HttpConnectorType::HTTP(ref connector) => {
-                let mut conn = connector
-                    .connect(key)
+                let mut conn = trace::checkout(connector.connect(key))
                     .await
                     .map_err(|e| TransportError::HttpConnectorError(e))?;
-                conn.send_request(req).await
+                trace::phase(Phase::FirstByte, conn.send_request(req)).await
             }
 
             HttpConnectorType::HTTPS(ref connector) => {
-                let mut conn = connector
-                    .connect(key)
+                let mut conn = trace::checkout(connector.connect(key))
                     .await
                     .map_err(|e| TransportError::HttpConnectorError(e))?;
-                conn.send_request(req).await
+                trace::phase(Phase::FirstByte, conn.send_request(req)).await
             }
         };
 
@@ -1,5 +1,10 @@
 pub mod client;
 pub(crate) mod monoio_body;
+pub(crate) mod raw;
+pub mod stream;
+pub mod upgrade;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 #[macro_export]
 macro_rules! apply_parameter_from_config {
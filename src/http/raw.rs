@@ -0,0 +1,266 @@
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use http::header::{CONNECTION, CONTENT_LENGTH, EXPECT, TE, TRAILER, TRANSFER_ENCODING, UPGRADE};
+use http::uri::Scheme;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri};
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt, Splitable};
+
+use crate::{
+    error::{Error, Result},
+    http::stream::{
+        read_body, read_response_head, write_chunked_body, write_request_head, Alpn, MaybeTlsStream, RawConnector,
+    },
+    http::upgrade::Upgraded,
+    key::PoolKey,
+    progress::ProgressHook,
+    response::{ResponseHead, ResponseLimits, Trailers},
+    trace::{self, Phase},
+};
+
+/// Response of an exchange over a dedicated HTTP/1.1 connection,
+/// holding the connection if the protocol was switched.
+pub(crate) struct RawResponse {
+    pub(crate) parts: http::response::Parts,
+    pub(crate) body: Bytes,
+    pub(crate) upgraded: Option<Upgraded>,
+}
+
+impl ResponseHead for RawResponse {
+    fn status(&self) -> StatusCode {
+        self.parts.status
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        &self.parts.headers
+    }
+}
+
+/// Opens a dedicated HTTP/1.1 connection to the origin of `uri`.
+pub(crate) async fn dial(connector: &RawConnector, uri: &Uri) -> Result<MaybeTlsStream> {
+    let key = PoolKey::try_from(uri).map_err(Error::UriKeyError)?;
+    let tls = uri.scheme() == Some(&Scheme::HTTPS);
+    connector.connect(key, tls, Alpn::Http1).await
+}
+
+/// Sets the `Content-Length` of a buffered body, unless given by the caller.
+pub(crate) fn set_content_length(headers: &mut HeaderMap, body: &Bytes) {
+    headers
+        .entry(CONTENT_LENGTH)
+        .or_insert_with(|| HeaderValue::from(body.len()));
+}
+
+pub(crate) async fn write_body<S: AsyncWriteRent>(stream: &mut S, body: Bytes) -> Result<()> {
+    let (res, _) = stream.write_all(body).await;
+    res.map_err(Error::IoError)?;
+    stream.flush().await.map_err(Error::IoError)
+}
+
+/// Reads the body of a final response, responses to `HEAD` and `204`/`304` responses have none.
+/// The trailer fields are stored in the response extensions.
+pub(crate) async fn read_response_body<S: AsyncReadRent>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    method: &Method,
+    parts: &mut http::response::Parts,
+    limits: &ResponseLimits,
+) -> Result<Bytes> {
+    let bodiless = *method == Method::HEAD
+        || matches!(parts.status, StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED);
+    let (body, trailers) = if bodiless {
+        (Bytes::new(), HeaderMap::new())
+    } else {
+        read_body(stream, buf, &parts.headers, limits).await?
+    };
+
+    parts.extensions.insert(Trailers(trailers));
+    Ok(body)
+}
+
+/// Reads response heads until a final one, skipping interim responses.
+async fn read_final_head<S: AsyncReadRent>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    limits: &ResponseLimits,
+) -> Result<Response<()>> {
+    loop {
+        let response = read_response_head(stream, buf, limits).await?;
+        if !response.status().is_informational() {
+            return Ok(response);
+        }
+    }
+}
+
+// Size of the slices a body is written in when its progress is reported
+const UPLOAD_SLICE: usize = 64 * 1024;
+
+/// How the body of a request sent by `send` is framed and when it is written.
+#[derive(Default)]
+pub(crate) struct BodyOptions {
+    /// Sends `Expect: 100-continue` and holds the body back until the server answers
+    /// `100 Continue`, or stays silent for this long.
+    pub(crate) expect_continue: Option<Duration>,
+    /// Trailer fields sent after a chunked body.
+    pub(crate) trailers: HeaderMap,
+    /// Asks the server for response trailers with `TE: trailers`.
+    pub(crate) accept_trailers: bool,
+    /// Reports the body bytes written, the body is then written in slices of `UPLOAD_SLICE` bytes.
+    pub(crate) progress: Option<ProgressHook>,
+}
+
+/// Sends a request over a dedicated HTTP/1.1 connection, for the body framings the pooled
+/// connections can't produce. A final status received while waiting for `100 Continue`
+/// is returned without sending the body.
+pub(crate) async fn send(
+    connector: &RawConnector,
+    req: Request<Option<Bytes>>,
+    uri: Uri,
+    options: BodyOptions,
+    limits: &ResponseLimits,
+) -> Result<RawResponse> {
+    let mut stream = dial(connector, &uri).await?;
+
+    let (parts, body) = req.into_parts();
+    let body = body.unwrap_or_default();
+    let mut headers = parts.headers;
+    // The h2c upgrade offered by default would switch the dedicated connection away from HTTP/1.1
+    for name in [UPGRADE, CONNECTION, HeaderName::from_static("http2-settings")] {
+        headers.remove(name);
+    }
+    if options.expect_continue.is_some() {
+        headers.insert(EXPECT, HeaderValue::from_static("100-continue"));
+    }
+    if options.accept_trailers {
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+        headers.insert(CONNECTION, HeaderValue::from_static("TE"));
+    }
+    if options.trailers.is_empty() {
+        set_content_length(&mut headers, &body);
+    } else {
+        headers.remove(CONTENT_LENGTH);
+        headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        headers.remove(TRAILER);
+        for name in options.trailers.keys() {
+            headers.append(TRAILER, HeaderValue::from(name.clone()));
+        }
+    }
+
+    let target = uri.path_and_query().map_or("/", |p| p.as_str());
+    trace::phase(Phase::RequestWrite, write_request_head(&mut stream, &parts.method, target, &headers)).await?;
+
+    let mut buf = BytesMut::new();
+    let response = match options.expect_continue {
+        Some(timeout) => {
+            let (mut reader, mut writer) = stream.into_split();
+            let exchange = send_after_continue(&mut reader, &mut writer, &mut buf, timeout, body, &options, limits);
+            let response = exchange.await;
+            stream = reader
+                .reunite(writer)
+                .unwrap_or_else(|_| unreachable!("halves of the same connection"));
+            response?
+        }
+        None => {
+            trace::phase(Phase::RequestWrite, write_framed_body(&mut stream, body, &options)).await?;
+            trace::phase(Phase::FirstByte, read_final_head(&mut stream, &mut buf, limits)).await?
+        }
+    };
+    limits.check_headers(response.headers())?;
+
+    let (mut response_parts, ()) = response.into_parts();
+    let read = read_response_body(&mut stream, &mut buf, &parts.method, &mut response_parts, limits);
+    let body = trace::phase(Phase::BodyRead, read).await?;
+    Ok(RawResponse {
+        parts: response_parts,
+        body,
+        upgraded: None,
+    })
+}
+
+/// Writes the body, chunked with the trailer fields if there are any.
+/// With a progress hook the body is written slice by slice, each reported once written.
+async fn write_framed_body<S: AsyncWriteRent>(stream: &mut S, body: Bytes, options: &BodyOptions) -> Result<()> {
+    let trailers = &options.trailers;
+    let Some(progress) = options.progress.as_ref() else {
+        if trailers.is_empty() {
+            return write_body(stream, body).await;
+        }
+        return write_chunked_body(stream, body, trailers).await;
+    };
+
+    let total = body.len();
+    let mut sent = 0;
+    while sent < total {
+        let slice = body.slice(sent..total.min(sent + UPLOAD_SLICE));
+        let len = slice.len();
+        if !trailers.is_empty() {
+            let (res, _) = stream.write_all(format!("{len:x}\r\n").into_bytes()).await;
+            res.map_err(Error::IoError)?;
+        }
+        let (res, _) = stream.write_all(slice).await;
+        res.map_err(Error::IoError)?;
+        if !trailers.is_empty() {
+            let (res, _) = stream.write_all(&b"\r\n"[..]).await;
+            res.map_err(Error::IoError)?;
+        }
+
+        sent += len;
+        progress.report(sent as u64, Some(total as u64));
+    }
+
+    if trailers.is_empty() {
+        return stream.flush().await.map_err(Error::IoError);
+    }
+    // Last chunk and trailer section
+    write_chunked_body(stream, Bytes::new(), trailers).await
+}
+
+/// Writes the body once the server answers `Expect: 100-continue`, and reads the final response head.
+/// A final status received before `100 Continue` is returned without sending the body.
+/// The read of the answer is never dropped while pending, as an interrupted read on io_uring
+/// loses the bytes it received. If the server stays silent for `timeout` the body is written
+/// from the write half of the connection while the read goes on.
+async fn send_after_continue<R: AsyncReadRent, W: AsyncWriteRent>(
+    reader: &mut R,
+    writer: &mut W,
+    buf: &mut BytesMut,
+    timeout: Duration,
+    body: Bytes,
+    options: &BodyOptions,
+    limits: &ResponseLimits,
+) -> Result<Response<()>> {
+    let mut deadline = pin!(monoio::time::sleep(timeout));
+    loop {
+        let response = {
+            let mut read = Box::pin(read_response_head(reader, buf, limits));
+            let answer = poll_fn(|cx| match read.as_mut().poll(cx) {
+                Poll::Ready(response) => Poll::Ready(Some(response)),
+                Poll::Pending => deadline.as_mut().poll(cx).map(|()| None),
+            })
+            .await;
+
+            match answer {
+                Some(response) => response?,
+                None => {
+                    // No answer in time, the body is sent anyway as allowed by RFC 9110 section 10.1.1
+                    trace::phase(Phase::RequestWrite, write_framed_body(writer, body, options)).await?;
+                    let response = trace::phase(Phase::FirstByte, read).await?;
+                    if !response.status().is_informational() {
+                        return Ok(response);
+                    }
+                    return trace::phase(Phase::FirstByte, read_final_head(reader, buf, limits)).await;
+                }
+            }
+        };
+
+        if response.status() == StatusCode::CONTINUE {
+            trace::phase(Phase::RequestWrite, write_framed_body(writer, body, options)).await?;
+            return trace::phase(Phase::FirstByte, read_final_head(reader, buf, limits)).await;
+        }
+        if !response.status().is_informational() {
+            return Ok(response);
+        }
+    }
+}
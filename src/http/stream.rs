@@ -0,0 +1,378 @@
+use std::cell::OnceCell;
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Version};
+use monoio::buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut};
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt, Split};
+use monoio::net::TcpStream;
+use monoio::BufResult;
+use monoio_transports::connectors::{Connector, TlsConnector, TlsStream};
+
+use crate::{
+    error::{Error, Result, TransportError},
+    key::PoolKey,
+    response::ResponseLimits,
+    trace::{self, PhaseConnector},
+};
+
+const READ_CHUNK: usize = 8 * 1024;
+
+/// A plain TCP or TLS connection owned by the caller instead of the connection pool.
+pub enum MaybeTlsStream {
+    Tcp(TcpStream),
+    // Boxed, the TLS state is much larger than a TCP stream
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+// Safety: TCP streams and the TLS streams over them can be read and written concurrently
+unsafe impl Split for MaybeTlsStream {}
+
+impl AsyncReadRent for MaybeTlsStream {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        match self {
+            MaybeTlsStream::Tcp(s) => s.read(buf).await,
+            MaybeTlsStream::Tls(s) => s.read(buf).await,
+        }
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        match self {
+            MaybeTlsStream::Tcp(s) => s.readv(buf).await,
+            MaybeTlsStream::Tls(s) => s.readv(buf).await,
+        }
+    }
+}
+
+impl AsyncWriteRent for MaybeTlsStream {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        match self {
+            MaybeTlsStream::Tcp(s) => s.write(buf).await,
+            MaybeTlsStream::Tls(s) => s.write(buf).await,
+        }
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        match self {
+            MaybeTlsStream::Tcp(s) => s.writev(buf_vec).await,
+            MaybeTlsStream::Tls(s) => s.writev(buf_vec).await,
+        }
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            MaybeTlsStream::Tcp(s) => s.flush().await,
+            MaybeTlsStream::Tls(s) => s.flush().await,
+        }
+    }
+
+    async fn shutdown(&mut self) -> std::io::Result<()> {
+        match self {
+            MaybeTlsStream::Tcp(s) => s.shutdown().await,
+            MaybeTlsStream::Tls(s) => s.shutdown().await,
+        }
+    }
+}
+
+/// Application protocol requested through ALPN on raw TLS connections.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Alpn {
+    Http1,
+    #[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+    Http2,
+}
+
+/// Dials connections outside of the connection pool, used when a connection
+/// has to be taken over after an upgrade or driven by hand.
+/// TLS connectors are created on first use.
+#[derive(Default)]
+pub(crate) struct RawConnector {
+    tcp: PhaseConnector,
+    tls_h1: OnceCell<TlsConnector<PhaseConnector>>,
+    tls_h2: OnceCell<TlsConnector<PhaseConnector>>,
+    root_certificates: Vec<Vec<u8>>,
+}
+
+impl RawConnector {
+    /// Connector trusting `root_certificates` on TLS connections besides the default roots.
+    pub(crate) fn with_root_certificates(root_certificates: Vec<Vec<u8>>) -> Self {
+        RawConnector {
+            root_certificates,
+            ..RawConnector::default()
+        }
+    }
+
+    pub(crate) async fn connect(&self, key: PoolKey, tls: bool, alpn: Alpn) -> Result<MaybeTlsStream> {
+        if !tls {
+            let stream = self
+                .tcp
+                .connect(key)
+                .await
+                .map_err(|e| TransportError::ConnectError(e.to_string()))?;
+            return Ok(MaybeTlsStream::Tcp(stream));
+        }
+
+        let connector = match alpn {
+            Alpn::Http1 => self
+                .tls_h1
+                .get_or_init(|| tls_connector(vec!["http/1.1"], &self.root_certificates)),
+            Alpn::Http2 => self
+                .tls_h2
+                .get_or_init(|| tls_connector(vec!["h2"], &self.root_certificates)),
+        };
+
+        let stream = connector
+            .connect(key)
+            .await
+            .map_err(|e| TransportError::ConnectError(e.to_string()))?;
+        trace::finish_handshake();
+
+        Ok(MaybeTlsStream::Tls(Box::new(stream)))
+    }
+}
+
+/// Creates a TLS connector negotiating `alpn`, trusting `root_certificates` besides the default roots.
+/// Extra roots are only supported on crate feature testing and ignored otherwise.
+#[cfg_attr(not(feature = "testing"), allow(unused_variables))]
+pub(crate) fn tls_connector(alpn: Vec<&str>, root_certificates: &[Vec<u8>]) -> TlsConnector<PhaseConnector> {
+    #[cfg(feature = "testing")]
+    if !root_certificates.is_empty() {
+        return crate::testing::tls::connector(alpn, root_certificates);
+    }
+    TlsConnector::new_with_tls_default(PhaseConnector::for_tls(), Some(alpn))
+}
+
+/// Writes an HTTP/1.1 request head.
+pub(crate) async fn write_request_head<S: AsyncWriteRent>(
+    stream: &mut S,
+    method: &http::Method,
+    path: &str,
+    headers: &HeaderMap,
+) -> Result<()> {
+    let mut head = Vec::with_capacity(256);
+    head.extend_from_slice(format!("{method} {path} HTTP/1.1\r\n").as_bytes());
+    for (name, value) in headers.iter() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+
+    let (res, _) = stream.write_all(head).await;
+    res.map_err(Error::IoError)?;
+    stream.flush().await.map_err(Error::IoError)
+}
+
+/// Reads an HTTP/1.1 response head from the stream.
+/// Bytes received past the head are left in `buf`.
+/// Parsing stops once the head exceeds the header count or size limits.
+pub(crate) async fn read_response_head<S: AsyncReadRent>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    limits: &ResponseLimits,
+) -> Result<Response<()>> {
+    let max_headers = limits.parser_max_headers();
+    let max_head_size = limits.parser_max_head_size();
+    loop {
+        let mut headers = vec![httparse::EMPTY_HEADER; max_headers];
+        let mut parsed = httparse::Response::new(&mut headers);
+        let status = parsed.parse(buf).map_err(|e| match e {
+            httparse::Error::TooManyHeaders => {
+                Error::ResponseHeadersTooLarge(format!("more than {max_headers} headers received"))
+            }
+            e => Error::HttpResponseHeadError(e.to_string()),
+        })?;
+
+        if let httparse::Status::Complete(head_len) = status {
+            let response = build_response_head(&parsed)?;
+            let _ = buf.split_to(head_len);
+            return Ok(response);
+        }
+
+        if buf.len() >= max_head_size {
+            return Err(Error::ResponseHeadersTooLarge(format!(
+                "response head exceeds {max_head_size} bytes"
+            )));
+        }
+        read_more(stream, buf).await?;
+    }
+}
+
+/// Reads the next chunk of data from the stream into `buf`.
+/// Fails with `UnexpectedEof` if the peer closed the connection.
+pub(crate) async fn read_more<S: AsyncReadRent>(stream: &mut S, buf: &mut BytesMut) -> Result<()> {
+    let (res, chunk) = stream.read(Vec::with_capacity(READ_CHUNK)).await;
+    match res.map_err(Error::IoError)? {
+        0 => Err(Error::IoError(std::io::ErrorKind::UnexpectedEof.into())),
+        _ => {
+            buf.extend_from_slice(&chunk);
+            Ok(())
+        }
+    }
+}
+
+/// Reads a complete HTTP/1.1 response body, framed by chunked encoding, `Content-Length`
+/// or the end of the connection. Bytes already received are taken from `buf`.
+/// Returns the body along with the trailer fields of a chunked body, which are bounded by the header limits.
+pub(crate) async fn read_body<S: AsyncReadRent>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    headers: &HeaderMap,
+    limits: &ResponseLimits,
+) -> Result<(Bytes, HeaderMap)> {
+    let check_limit = |len: usize| match limits.max_body_size {
+        Some(max) if len > max => Err(Error::ResponseBodyTooLarge(max)),
+        _ => Ok(()),
+    };
+
+    let chunked = headers
+        .get(TRANSFER_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().to_ascii_lowercase().ends_with("chunked"));
+    if chunked {
+        return read_chunked(stream, buf, limits, check_limit).await;
+    }
+
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    match content_length {
+        Some(len) => {
+            check_limit(len)?;
+            while buf.len() < len {
+                read_more(stream, buf).await?;
+            }
+            Ok((buf.split_to(len).freeze(), HeaderMap::new()))
+        }
+        None => loop {
+            check_limit(buf.len())?;
+            match read_more(stream, buf).await {
+                Ok(()) => {}
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok((buf.split().freeze(), HeaderMap::new()));
+                }
+                Err(e) => return Err(e),
+            }
+        },
+    }
+}
+
+async fn read_chunked<S: AsyncReadRent>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    limits: &ResponseLimits,
+    check_limit: impl Fn(usize) -> Result<()>,
+) -> Result<(Bytes, HeaderMap)> {
+    let mut body = BytesMut::new();
+    loop {
+        let (consumed, size) = loop {
+            match httparse::parse_chunk_size(buf) {
+                Ok(httparse::Status::Complete(parsed)) => break parsed,
+                Ok(httparse::Status::Partial) => read_more(stream, buf).await?,
+                Err(_) => return Err(Error::HttpResponseHeadError("invalid chunk size".to_string())),
+            }
+        };
+        buf.advance(consumed);
+
+        if size == 0 {
+            let trailers = read_trailer_section(stream, buf, limits).await?;
+            return Ok((body.freeze(), trailers));
+        }
+
+        let size = size as usize;
+        check_limit(body.len() + size)?;
+        while buf.len() < size + 2 {
+            read_more(stream, buf).await?;
+        }
+        body.extend_from_slice(&buf[..size]);
+        buf.advance(size + 2);
+    }
+}
+
+/// Reads the trailer fields following the last chunk, up to the terminating empty line.
+async fn read_trailer_section<S: AsyncReadRent>(
+    stream: &mut S,
+    buf: &mut BytesMut,
+    limits: &ResponseLimits,
+) -> Result<HeaderMap> {
+    let max_size = limits.parser_max_head_size();
+    loop {
+        let mut headers = vec![httparse::EMPTY_HEADER; limits.parser_max_headers()];
+        match httparse::parse_headers(buf, &mut headers) {
+            Ok(httparse::Status::Complete((len, parsed))) => {
+                let trailers = header_map(parsed)?;
+                buf.advance(len);
+                return Ok(trailers);
+            }
+            Ok(httparse::Status::Partial) => {
+                if buf.len() >= max_size {
+                    return Err(Error::ResponseHeadersTooLarge(format!(
+                        "trailer section exceeds {max_size} bytes"
+                    )));
+                }
+                read_more(stream, buf).await?;
+            }
+            Err(httparse::Error::TooManyHeaders) => {
+                return Err(Error::ResponseHeadersTooLarge("too many trailer fields received".to_string()))
+            }
+            Err(e) => return Err(Error::HttpResponseHeadError(e.to_string())),
+        }
+    }
+}
+
+/// Writes a body in a single chunk followed by the trailer fields, for requests sent with
+/// `Transfer-Encoding: chunked`.
+pub(crate) async fn write_chunked_body<S: AsyncWriteRent>(
+    stream: &mut S,
+    body: Bytes,
+    trailers: &HeaderMap,
+) -> Result<()> {
+    let mut out = Vec::with_capacity(body.len() + 128);
+    if !body.is_empty() {
+        out.extend_from_slice(format!("{:x}\r\n", body.len()).as_bytes());
+        out.extend_from_slice(&body);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"0\r\n");
+    for (name, value) in trailers.iter() {
+        out.extend_from_slice(name.as_str().as_bytes());
+        out.extend_from_slice(b": ");
+        out.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(b"\r\n");
+
+    let (res, _) = stream.write_all(out).await;
+    res.map_err(Error::IoError)?;
+    stream.flush().await.map_err(Error::IoError)
+}
+
+fn build_response_head(parsed: &httparse::Response) -> Result<Response<()>> {
+    let mut response = Response::new(());
+    *response.status_mut() = parsed
+        .code
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| Error::HttpResponseHeadError("invalid status code".to_string()))?;
+    *response.version_mut() = match parsed.version {
+        Some(0) => Version::HTTP_10,
+        _ => Version::HTTP_11,
+    };
+
+    *response.headers_mut() = header_map(parsed.headers)?;
+
+    Ok(response)
+}
+
+fn header_map(parsed: &[httparse::Header]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::with_capacity(parsed.len());
+    for header in parsed.iter() {
+        let name = HeaderName::from_bytes(header.name.as_bytes())
+            .map_err(|e| Error::HttpResponseHeadError(e.to_string()))?;
+        let value = HeaderValue::from_bytes(header.value).map_err(Error::InvalidHeaderValue)?;
+        headers.append(name, value);
+    }
+
+    Ok(headers)
+}
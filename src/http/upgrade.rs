@@ -0,0 +1,162 @@
+use std::fmt;
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::header::HOST;
+use http::uri::Authority;
+use http::{HeaderValue, Method, Request, StatusCode, Uri};
+use monoio::buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut, IoVecWrapperMut};
+use monoio::io::{AsyncReadRent, AsyncWriteRent};
+use monoio::BufResult;
+
+use crate::{
+    error::{Error, Result},
+    http::raw::{dial, read_response_body, set_content_length, write_body, RawResponse},
+    http::stream::{read_response_head, write_request_head, MaybeTlsStream, RawConnector},
+    response::ResponseLimits,
+    trace::{self, Phase},
+};
+
+/// Connection taken over after a `101 Switching Protocols` response or a successful `CONNECT`.
+/// Bytes the server sent right after the response head are returned by the first reads.
+pub struct Upgraded {
+    stream: MaybeTlsStream,
+    read_buf: BytesMut,
+}
+
+impl Upgraded {
+    /// Returns the underlying connection and the bytes already read from it.
+    pub fn into_parts(self) -> (MaybeTlsStream, Bytes) {
+        (self.stream, self.read_buf.freeze())
+    }
+
+    fn copy_buffered<T: IoBufMut>(&mut self, mut buf: T) -> (usize, T) {
+        let n = self.read_buf.len().min(buf.bytes_total());
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.read_buf.as_ptr(), buf.write_ptr(), n);
+            buf.set_init(n);
+        }
+        self.read_buf.advance(n);
+        (n, buf)
+    }
+}
+
+impl fmt::Debug for Upgraded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgraded")
+            .field("buffered", &self.read_buf.len())
+            .finish()
+    }
+}
+
+impl AsyncReadRent for Upgraded {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        if self.read_buf.is_empty() {
+            return self.stream.read(buf).await;
+        }
+
+        let (n, buf) = self.copy_buffered(buf);
+        (Ok(n), buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        if self.read_buf.is_empty() {
+            return self.stream.readv(buf).await;
+        }
+
+        // Buffered bytes are copied into the first slice only
+        match IoVecWrapperMut::new(buf) {
+            Ok(wrapper) => {
+                let (n, wrapper) = self.copy_buffered(wrapper);
+                (Ok(n), wrapper.into_inner())
+            }
+            Err(buf) => (Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty io vector")), buf),
+        }
+    }
+}
+
+impl AsyncWriteRent for Upgraded {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        self.stream.write(buf).await
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        self.stream.writev(buf_vec).await
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush().await
+    }
+
+    async fn shutdown(&mut self) -> std::io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
+
+/// Sends an upgrade or `CONNECT` request over a dedicated HTTP/1.1 connection.
+/// The connection is dialed to `uri`, a `CONNECT` asks for a tunnel to `connect_target`,
+/// or to the authority of `uri` if none is given.
+pub(crate) async fn send(
+    connector: &RawConnector,
+    req: Request<Option<Bytes>>,
+    uri: Uri,
+    connect_target: Option<&str>,
+    limits: &ResponseLimits,
+) -> Result<RawResponse> {
+    let mut stream = dial(connector, &uri).await?;
+
+    let (parts, body) = req.into_parts();
+    let connect = parts.method == Method::CONNECT;
+    let mut headers = parts.headers;
+    let target = if connect {
+        let authority = match connect_target {
+            Some(target) => Authority::try_from(target)
+                .map_err(|e| Error::UpgradeError(format!("invalid CONNECT target {target}: {e}")))?,
+            None => uri
+                .authority()
+                .cloned()
+                .ok_or_else(|| Error::UpgradeError(format!("missing CONNECT target for {uri}")))?,
+        };
+        let host = HeaderValue::try_from(authority.as_str()).map_err(Error::InvalidHeaderValue)?;
+        headers.insert(HOST, host);
+        authority.to_string()
+    } else {
+        uri.path_and_query().map_or("/", |p| p.as_str()).to_string()
+    };
+    if let Some(body) = body.as_ref() {
+        set_content_length(&mut headers, body);
+    }
+
+    let write = async {
+        write_request_head(&mut stream, &parts.method, &target, &headers).await?;
+        match body {
+            Some(body) => write_body(&mut stream, body).await,
+            None => Ok(()),
+        }
+    };
+    trace::phase(Phase::RequestWrite, write).await?;
+
+    let mut buf = BytesMut::new();
+    let mut response = trace::phase(Phase::FirstByte, read_response_head(&mut stream, &mut buf, limits)).await?;
+    // Interim responses such as 100 Continue are skipped
+    while response.status().is_informational() && response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        response = read_response_head(&mut stream, &mut buf, limits).await?;
+    }
+    limits.check_headers(response.headers())?;
+
+    let (mut response_parts, ()) = response.into_parts();
+    let status = response_parts.status;
+    if status == StatusCode::SWITCHING_PROTOCOLS || (connect && status.is_success()) {
+        return Ok(RawResponse {
+            parts: response_parts,
+            body: Bytes::new(),
+            upgraded: Some(Upgraded { stream, read_buf: buf }),
+        });
+    }
+
+    let body = read_response_body(&mut stream, &mut buf, &parts.method, &mut response_parts, limits).await?;
+    Ok(RawResponse {
+        parts: response_parts,
+        body,
+        upgraded: None,
+    })
+}
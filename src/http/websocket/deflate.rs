@@ -0,0 +1,188 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+
+use crate::error::{Error, Result};
+
+// Trailer removed from every compressed message, RFC 7692 section 7.2.1
+const SYNC_TRAILER: [u8; 4] = [0x00, 0x00, 0xFF, 0xFF];
+
+/// Extension offer sent in the handshake.
+/// Window bits are not negotiated since the compressor always uses a 32KiB window.
+pub(crate) const PERMESSAGE_DEFLATE_OFFER: &str = "permessage-deflate";
+
+/// permessage-deflate state of a connection, RFC 7692.
+pub(crate) struct Deflate {
+    compress: Compress,
+    decompress: Decompress,
+    client_no_context_takeover: bool,
+    server_no_context_takeover: bool,
+}
+
+impl Deflate {
+    /// Builds the compression state from the `Sec-WebSocket-Extensions` response header.
+    /// Returns `None` if the server did not accept the extension.
+    pub(crate) fn negotiate(extensions: Option<&str>) -> Result<Option<Self>> {
+        let Some(extension) = extensions
+            .into_iter()
+            .flat_map(|v| v.split(','))
+            .find(|ext| ext.trim_start().starts_with("permessage-deflate"))
+        else {
+            return Ok(None);
+        };
+
+        let mut deflate = Deflate {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            client_no_context_takeover: false,
+            server_no_context_takeover: false,
+        };
+
+        for param in extension.split(';').skip(1) {
+            let name = param.split('=').next().unwrap_or_default().trim();
+            match name {
+                "client_no_context_takeover" => deflate.client_no_context_takeover = true,
+                "server_no_context_takeover" => deflate.server_no_context_takeover = true,
+                // The server may only use a smaller window than the one we can inflate
+                "server_max_window_bits" => {}
+                _ => {
+                    return Err(Error::WebSocketError(format!(
+                        "unsupported permessage-deflate parameter: {name}"
+                    )))
+                }
+            }
+        }
+
+        Ok(Some(deflate))
+    }
+
+    pub(crate) fn compress(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        let start = self.compress.total_in();
+
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            if out.capacity() - out.len() < 64 {
+                out.reserve(data.len().max(1024));
+            }
+
+            self.compress
+                .compress_vec(&data[consumed..], &mut out, FlushCompress::Sync)
+                .map_err(|e| Error::WebSocketError(e.to_string()))?;
+
+            // The sync flush is complete once all input is consumed and the output was not filled up
+            let consumed = (self.compress.total_in() - start) as usize;
+            if consumed == data.len() && out.len() < out.capacity() {
+                break;
+            }
+        }
+
+        if out.ends_with(&SYNC_TRAILER) {
+            out.truncate(out.len() - SYNC_TRAILER.len());
+        }
+        if self.client_no_context_takeover {
+            self.compress.reset();
+        }
+
+        Ok(out)
+    }
+
+    pub(crate) fn decompress(&mut self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let mut input = Vec::with_capacity(data.len() + SYNC_TRAILER.len());
+        input.extend_from_slice(data);
+        input.extend_from_slice(&SYNC_TRAILER);
+
+        let mut out = Vec::with_capacity(data.len() * 2 + 64);
+        let start = self.decompress.total_in();
+
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if out.capacity() - out.len() < 64 {
+                out.reserve(data.len().max(1024));
+            }
+
+            self.decompress
+                .decompress_vec(&input[consumed..], &mut out, FlushDecompress::Sync)
+                .map_err(|e| Error::WebSocketError(e.to_string()))?;
+
+            if out.len() > max_size {
+                return Err(Error::WebSocketError(format!(
+                    "inflated message exceeds the limit of {max_size} bytes"
+                )));
+            }
+
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if consumed == input.len() && out.len() < out.capacity() {
+                break;
+            }
+        }
+
+        if self.server_no_context_takeover {
+            self.decompress.reset(false);
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"hello hello hello hello hello hello hello";
+
+    #[test]
+    fn negotiates_the_accepted_parameters() {
+        assert!(Deflate::negotiate(None).unwrap().is_none());
+        assert!(Deflate::negotiate(Some("x-webkit-deflate-frame")).unwrap().is_none());
+
+        let deflate = Deflate::negotiate(Some("foo, permessage-deflate; client_no_context_takeover; server_max_window_bits=10"))
+            .unwrap()
+            .unwrap();
+        assert!(deflate.client_no_context_takeover);
+        assert!(!deflate.server_no_context_takeover);
+
+        let result = Deflate::negotiate(Some("permessage-deflate; client_max_window_bits=8"));
+        assert!(matches!(result, Err(Error::WebSocketError(_))));
+    }
+
+    #[test]
+    fn messages_roundtrip_without_the_sync_trailer() {
+        let mut client = Deflate::negotiate(Some("permessage-deflate")).unwrap().unwrap();
+        let mut server = Deflate::negotiate(Some("permessage-deflate")).unwrap().unwrap();
+
+        let first = client.compress(MESSAGE).unwrap();
+        assert!(!first.ends_with(&SYNC_TRAILER));
+        assert!(first.len() < MESSAGE.len());
+        assert_eq!(server.decompress(&first, 1024).unwrap(), MESSAGE);
+
+        // The second message refers back to the first one through the shared window
+        let second = client.compress(MESSAGE).unwrap();
+        assert!(second.len() < first.len());
+        assert_eq!(server.decompress(&second, 1024).unwrap(), MESSAGE);
+
+        assert_eq!(client.compress(b"").map(|empty| server.decompress(&empty, 1024).unwrap()).unwrap(), b"");
+    }
+
+    #[test]
+    fn no_context_takeover_resets_the_window() {
+        let offer = "permessage-deflate; client_no_context_takeover; server_no_context_takeover";
+        let mut client = Deflate::negotiate(Some(offer)).unwrap().unwrap();
+        let mut server = Deflate::negotiate(Some("permessage-deflate; client_no_context_takeover")).unwrap().unwrap();
+
+        // Every message is compressed and inflated on its own
+        let first = client.compress(MESSAGE).unwrap();
+        assert_eq!(client.compress(MESSAGE).unwrap(), first);
+        for _ in 0..2 {
+            let message = server.compress(MESSAGE).unwrap();
+            assert_eq!(client.decompress(&message, 1024).unwrap(), MESSAGE);
+        }
+    }
+
+    #[test]
+    fn inflated_size_is_limited() {
+        let mut deflate = Deflate::negotiate(Some("permessage-deflate")).unwrap().unwrap();
+        let compressed = deflate.compress(&[0; 64 * 1024]).unwrap();
+
+        let result = deflate.decompress(&compressed, 1024);
+        assert!(matches!(result, Err(Error::WebSocketError(_))));
+    }
+}
@@ -0,0 +1,248 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::error::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OpCode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl OpCode {
+    fn from_u8(val: u8) -> Result<Self> {
+        match val {
+            0x0 => Ok(OpCode::Continuation),
+            0x1 => Ok(OpCode::Text),
+            0x2 => Ok(OpCode::Binary),
+            0x8 => Ok(OpCode::Close),
+            0x9 => Ok(OpCode::Ping),
+            0xA => Ok(OpCode::Pong),
+            op => Err(Error::WebSocketError(format!("reserved opcode {op:#x}"))),
+        }
+    }
+
+    pub(crate) fn as_u8(self) -> u8 {
+        match self {
+            OpCode::Continuation => 0x0,
+            OpCode::Text => 0x1,
+            OpCode::Binary => 0x2,
+            OpCode::Close => 0x8,
+            OpCode::Ping => 0x9,
+            OpCode::Pong => 0xA,
+        }
+    }
+
+    pub(crate) fn is_control(self) -> bool {
+        matches!(self, OpCode::Close | OpCode::Ping | OpCode::Pong)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub(crate) fin: bool,
+    pub(crate) rsv1: bool,
+    pub(crate) opcode: OpCode,
+    pub(crate) payload: Bytes,
+}
+
+/// Encodes a client frame, the payload is always masked as required by RFC 6455 section 5.3.
+pub(crate) fn encode(buf: &mut BytesMut, fin: bool, rsv1: bool, opcode: OpCode, payload: &[u8]) {
+    let mut first = opcode.as_u8();
+    if fin {
+        first |= 0x80;
+    }
+    if rsv1 {
+        first |= 0x40;
+    }
+    buf.put_u8(first);
+
+    let len = payload.len();
+    if len < 126 {
+        buf.put_u8(0x80 | len as u8);
+    } else if len <= u16::MAX as usize {
+        buf.put_u8(0x80 | 126);
+        buf.put_u16(len as u16);
+    } else {
+        buf.put_u8(0x80 | 127);
+        buf.put_u64(len as u64);
+    }
+
+    let mask: [u8; 4] = rand::random();
+    buf.put_slice(&mask);
+    buf.reserve(len);
+    buf.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+}
+
+/// Decodes the next server frame from `buf`, returning `None` if more data is needed.
+/// Masked frames are rejected, a server never masks what it sends (RFC 6455 section 5.1).
+pub(crate) fn decode(buf: &mut BytesMut, max_frame_size: usize) -> Result<Option<Frame>> {
+    decode_frame(buf, max_frame_size, false)
+}
+
+/// Decodes the next client frame from `buf`, which must be masked, as read by the test server.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn decode_client(buf: &mut BytesMut, max_frame_size: usize) -> Result<Option<Frame>> {
+    decode_frame(buf, max_frame_size, true)
+}
+
+fn decode_frame(buf: &mut BytesMut, max_frame_size: usize, from_client: bool) -> Result<Option<Frame>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+
+    let first = buf[0];
+    let second = buf[1];
+    if first & 0x30 != 0 {
+        return Err(Error::WebSocketError("reserved bits RSV2/RSV3 set".to_string()));
+    }
+
+    let fin = first & 0x80 != 0;
+    let rsv1 = first & 0x40 != 0;
+    let opcode = OpCode::from_u8(first & 0x0F)?;
+    let masked = second & 0x80 != 0;
+    if masked != from_client {
+        let reason = if masked { "masked server frame" } else { "unmasked client frame" };
+        return Err(Error::WebSocketError(reason.to_string()));
+    }
+
+    let (len, header_len) = match second & 0x7F {
+        126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+        127 if buf.len() >= 10 => {
+            let mut len = [0u8; 8];
+            len.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(len), 10)
+        }
+        126 | 127 => return Ok(None),
+        len => (len as u64, 2),
+    };
+
+    if opcode.is_control() && (!fin || len > 125) {
+        return Err(Error::WebSocketError("invalid control frame".to_string()));
+    }
+    if len > max_frame_size as u64 {
+        return Err(Error::WebSocketError(format!("frame of {len} bytes exceeds the message size limit")));
+    }
+
+    let mask_len = if masked { 4 } else { 0 };
+    let frame_len = header_len + mask_len + len as usize;
+    if buf.len() < frame_len {
+        buf.reserve(frame_len - buf.len());
+        return Ok(None);
+    }
+
+    buf.advance(header_len);
+    let mask = masked.then(|| {
+        let mut mask = [0u8; 4];
+        mask.copy_from_slice(&buf[..4]);
+        buf.advance(4);
+        mask
+    });
+
+    let mut payload = buf.split_to(len as usize);
+    if let Some(mask) = mask {
+        payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+
+    Ok(Some(Frame {
+        fin,
+        rsv1,
+        opcode,
+        payload: payload.freeze(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(len: usize) {
+        let payload: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        let mut buf = BytesMut::new();
+        encode(&mut buf, true, false, OpCode::Binary, &payload);
+
+        let frame = decode_client(&mut buf, usize::MAX).unwrap().unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OpCode::Binary);
+        assert_eq!(frame.payload.as_ref(), payload.as_slice());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn frames_roundtrip_with_every_length_encoding() {
+        roundtrip(0);
+        roundtrip(125);
+        roundtrip(126);
+        roundtrip(u16::MAX as usize);
+        roundtrip(u16::MAX as usize + 1);
+    }
+
+    #[test]
+    fn client_frames_are_masked() {
+        let mut buf = BytesMut::new();
+        encode(&mut buf, false, true, OpCode::Text, b"hello");
+
+        assert_eq!(buf[0], 0x41);
+        assert_eq!(buf[1], 0x80 | 5);
+        let mask = &buf[2..6];
+        let masked: Vec<u8> = b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]).collect();
+        assert_eq!(&buf[6..], masked.as_slice());
+
+        // A server reading the frame unmasks the payload
+        let frame = decode_client(&mut buf.clone(), 125).unwrap().unwrap();
+        assert!(!frame.fin);
+        assert!(frame.rsv1);
+        assert_eq!(frame.payload.as_ref(), b"hello");
+
+        // The client never accepts masked frames
+        assert!(matches!(decode(&mut buf, 125), Err(Error::WebSocketError(_))));
+    }
+
+    #[test]
+    fn partial_frames_wait_for_more_data() {
+        let mut encoded = BytesMut::new();
+        encode(&mut encoded, true, false, OpCode::Binary, &[7; 300]);
+
+        let mut buf = BytesMut::new();
+        for byte in encoded.iter().take(encoded.len() - 1) {
+            buf.put_u8(*byte);
+            assert!(decode_client(&mut buf, usize::MAX).unwrap().is_none());
+        }
+        buf.put_u8(encoded[encoded.len() - 1]);
+        assert_eq!(decode_client(&mut buf, usize::MAX).unwrap().unwrap().payload.len(), 300);
+    }
+
+    #[test]
+    fn unmasked_server_frames_are_decoded() {
+        let mut buf = BytesMut::from(&b"\x81\x02hi\x8a\x00"[..]);
+
+        let frame = decode(&mut buf, 125).unwrap().unwrap();
+        assert_eq!(frame.opcode, OpCode::Text);
+        assert_eq!(frame.payload.as_ref(), b"hi");
+        let frame = decode(&mut buf, 125).unwrap().unwrap();
+        assert_eq!(frame.opcode, OpCode::Pong);
+        assert!(frame.payload.is_empty());
+
+        // A server requires its clients to mask their frames
+        let mut buf = BytesMut::from(&b"\x81\x02hi"[..]);
+        assert!(matches!(decode_client(&mut buf, 125), Err(Error::WebSocketError(_))));
+    }
+
+    #[test]
+    fn invalid_frames_are_rejected() {
+        let invalid: [&[u8]; 5] = [
+            b"\xa1\x00",       // RSV2 set
+            b"\x83\x00",       // reserved opcode
+            b"\x09\x00",       // fragmented ping
+            b"\x88\x7e\x00\x7e", // close frame over 125 bytes
+            b"\x82\x7e\x01\x00", // 256 bytes over the limit
+        ];
+        for frame in invalid {
+            let mut buf = BytesMut::from(frame);
+            assert!(matches!(decode(&mut buf, 255), Err(Error::WebSocketError(_))), "{frame:?}");
+        }
+    }
+}
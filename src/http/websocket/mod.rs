@@ -0,0 +1,618 @@
+mod deflate;
+pub(crate) mod frame;
+
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bytes::{Bytes, BytesMut};
+use http::header::{
+    CONNECTION, HOST, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use http::uri::{Authority, Scheme};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode, Uri, Version};
+use monoio::io::{AsyncWriteRent, AsyncWriteRentExt};
+use monoio_http::h2::{client as h2_client, ext::Protocol as H2Protocol, RecvStream, SendStream};
+use sha1::{Digest, Sha1};
+
+use deflate::{Deflate, PERMESSAGE_DEFLATE_OFFER};
+use frame::{Frame, OpCode};
+
+use crate::{
+    error::{Error, Result},
+    http::client::MonoioClient,
+    http::stream::{self, Alpn, MaybeTlsStream},
+    key::PoolKey,
+    response::ResponseLimits,
+};
+
+// Appended to the handshake key to compute Sec-WebSocket-Accept, RFC 6455 section 1.3
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A WebSocket message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Bytes),
+    Ping(Bytes),
+    Pong(Bytes),
+    Close(Option<CloseFrame>),
+}
+
+/// Status code and reason carried by a close frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+impl CloseFrame {
+    /// Normal closure, the purpose of the connection has been fulfilled.
+    pub fn normal() -> Self {
+        CloseFrame { code: 1000, reason: String::new() }
+    }
+}
+
+/// Configures and opens a WebSocket connection, created by `MonoioClient::websocket`.
+pub struct WebSocketBuilder {
+    client: MonoioClient,
+    uri: std::result::Result<Uri, http::Error>,
+    headers: std::result::Result<HeaderMap, http::Error>,
+    protocols: Vec<String>,
+    permessage_deflate: bool,
+    http2: bool,
+    max_message_size: usize,
+    close_timeout: Duration,
+}
+
+impl WebSocketBuilder {
+    pub(crate) fn new(client: MonoioClient, uri: std::result::Result<Uri, http::Error>) -> Self {
+        WebSocketBuilder {
+            client,
+            uri,
+            headers: Ok(HeaderMap::new()),
+            protocols: vec![],
+            permessage_deflate: false,
+            http2: false,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+        }
+    }
+
+    /// Sets a header sent with the opening handshake.
+    /// # Examples
+    /// ```
+    /// client.websocket("wss://example.com/ws").set_header("authorization", "Bearer token");
+    /// ```
+    pub fn set_header<K, T>(mut self, key: K, value: T) -> Self
+        where
+            HeaderName: TryFrom<K>,
+            <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+            HeaderValue: TryFrom<T>,
+            <HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        self.headers = self.headers.and_then(|mut headers| {
+            let name = HeaderName::try_from(key).map_err(Into::into)?;
+            let value = HeaderValue::try_from(value).map_err(Into::into)?;
+            headers.append(name, value);
+            Ok(headers)
+        });
+        self
+    }
+
+    /// Offers a subprotocol through `Sec-WebSocket-Protocol`, can be called multiple times.
+    /// The protocol selected by the server is available with `WebSocket::protocol`.
+    pub fn protocol(mut self, protocol: impl Into<String>) -> Self {
+        self.protocols.push(protocol.into());
+        self
+    }
+
+    /// Offers the permessage-deflate extension, messages are compressed if the server accepts it.
+    pub fn permessage_deflate(mut self) -> Self {
+        self.permessage_deflate = true;
+        self
+    }
+
+    /// Opens the WebSocket over an HTTP/2 stream with an extended CONNECT request (RFC 8441)
+    /// instead of an HTTP/1.1 Upgrade. The server has to advertise `SETTINGS_ENABLE_CONNECT_PROTOCOL`.
+    pub fn http2(mut self) -> Self {
+        self.http2 = true;
+        self
+    }
+
+    /// Sets the maximum size in bytes of a received message, after reassembly and decompression.
+    /// Default is 64MiB.
+    pub fn max_message_size(mut self, val: usize) -> Self {
+        self.max_message_size = val;
+        self
+    }
+
+    /// Sets how long `WebSocket::close` waits for the server to acknowledge the close frame.
+    /// Default is 5 seconds.
+    pub fn close_timeout(mut self, val: Duration) -> Self {
+        self.close_timeout = val;
+        self
+    }
+
+    /// Performs the opening handshake and returns the established connection.
+    pub async fn connect(self) -> Result<WebSocket> {
+        let uri = self.uri.map_err(Error::HttpRequestBuilder)?;
+        let user_headers = self.headers.map_err(Error::HttpRequestBuilder)?;
+
+        let tls = match uri.scheme_str() {
+            Some("wss") | Some("https") => true,
+            Some("ws") | Some("http") => false,
+            _ => return Err(Error::WebSocketError(format!("unsupported websocket scheme in {uri}"))),
+        };
+        let authority = uri
+            .authority()
+            .cloned()
+            .ok_or_else(|| Error::WebSocketError(format!("missing authority in {uri}")))?;
+        let path = uri.path_and_query().map_or("/", |p| p.as_str()).to_string();
+        let key = http_key(&authority, tls)?;
+
+        let mut headers = self.client.default_headers().clone();
+        for (name, value) in user_headers.iter() {
+            headers.insert(name, value.clone());
+        }
+        if !self.protocols.is_empty() {
+            let protocols = HeaderValue::try_from(self.protocols.join(", ")).map_err(Error::InvalidHeaderValue)?;
+            headers.insert(SEC_WEBSOCKET_PROTOCOL, protocols);
+        }
+        if self.permessage_deflate {
+            headers.insert(SEC_WEBSOCKET_EXTENSIONS, HeaderValue::from_static(PERMESSAGE_DEFLATE_OFFER));
+        }
+        headers.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+
+        let handshake = Handshake { key, tls, authority, path, headers };
+        let (transport, read_buf, response_headers) = if self.http2 {
+            handshake.h2(&self.client).await?
+        } else {
+            handshake.h1(&self.client).await?
+        };
+
+        let extensions = response_headers
+            .get(SEC_WEBSOCKET_EXTENSIONS)
+            .and_then(|v| v.to_str().ok());
+        if extensions.is_some() && !self.permessage_deflate {
+            return Err(Error::WebSocketError("server enabled an extension that was not offered".to_string()));
+        }
+        let deflate = Deflate::negotiate(extensions)?;
+
+        let protocol = response_headers
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        if matches!(protocol.as_ref(), Some(p) if !self.protocols.contains(p)) {
+            return Err(Error::WebSocketError("server selected a subprotocol that was not offered".to_string()));
+        }
+
+        Ok(WebSocket {
+            transport,
+            read_buf,
+            deflate,
+            protocol,
+            max_message_size: self.max_message_size,
+            close_timeout: self.close_timeout,
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        })
+    }
+}
+
+fn http_key(authority: &Authority, tls: bool) -> Result<PoolKey> {
+    let scheme = if tls { Scheme::HTTPS } else { Scheme::HTTP };
+    let uri = Uri::builder()
+        .scheme(scheme)
+        .authority(authority.clone())
+        .path_and_query("/")
+        .build()
+        .map_err(Error::HttpRequestBuilder)?;
+
+    PoolKey::try_from(&uri).map_err(Error::UriKeyError)
+}
+
+struct Handshake {
+    key: PoolKey,
+    tls: bool,
+    authority: Authority,
+    path: String,
+    headers: HeaderMap,
+}
+
+impl Handshake {
+    /// HTTP/1.1 Upgrade handshake, RFC 6455 section 4.
+    async fn h1(self, client: &MonoioClient) -> Result<(Transport, BytesMut, HeaderMap)> {
+        let mut stream = client.raw_connector().connect(self.key, self.tls, Alpn::Http1).await?;
+
+        let nonce = BASE64.encode(rand::random::<[u8; 16]>());
+        let mut headers = self.headers;
+        let host = HeaderValue::try_from(self.authority.as_str()).map_err(Error::InvalidHeaderValue)?;
+        headers.insert(HOST, host);
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(SEC_WEBSOCKET_KEY, HeaderValue::try_from(nonce.as_str()).map_err(Error::InvalidHeaderValue)?);
+
+        stream::write_request_head(&mut stream, &Method::GET, &self.path, &headers).await?;
+        let mut buf = BytesMut::new();
+        let response = stream::read_response_head(&mut stream, &mut buf, &ResponseLimits::default()).await?;
+
+        if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Error::UnexpectedStatus(response.status()));
+        }
+
+        let response_headers = response.into_parts().0.headers;
+        let upgraded = header_contains(&response_headers, UPGRADE, "websocket")
+            && header_contains(&response_headers, CONNECTION, "upgrade");
+        if !upgraded {
+            return Err(Error::WebSocketError("server did not upgrade the connection".to_string()));
+        }
+
+        let accept = response_headers.get(SEC_WEBSOCKET_ACCEPT).map(|v| v.as_bytes());
+        if accept != Some(accept_key(&nonce).as_bytes()) {
+            return Err(Error::WebSocketError("invalid Sec-WebSocket-Accept".to_string()));
+        }
+
+        Ok((Transport::H1(stream), buf, response_headers))
+    }
+
+    /// Extended CONNECT handshake over HTTP/2, RFC 8441 section 4.
+    async fn h2(self, client: &MonoioClient) -> Result<(Transport, BytesMut, HeaderMap)> {
+        let stream = client.raw_connector().connect(self.key, self.tls, Alpn::Http2).await?;
+        let (send_request, connection) = h2_client::handshake(stream)
+            .await
+            .map_err(|e| Error::WebSocketError(e.to_string()))?;
+        monoio::spawn(async move {
+            let _ = connection.await;
+        });
+
+        let mut send_request = send_request
+            .ready()
+            .await
+            .map_err(|e| Error::WebSocketError(e.to_string()))?;
+        if !send_request.is_extended_connect_protocol_enabled() {
+            return Err(Error::WebSocketError("server does not support extended CONNECT".to_string()));
+        }
+
+        let scheme = if self.tls { "https" } else { "http" };
+        let mut request = Request::builder()
+            .method(Method::CONNECT)
+            .version(Version::HTTP_2)
+            .uri(format!("{scheme}://{}{}", self.authority, self.path))
+            .body(())
+            .map_err(Error::HttpRequestBuilder)?;
+        request.extensions_mut().insert(H2Protocol::from_static("websocket"));
+        *request.headers_mut() = self.headers;
+        for header in crate::request::PROHIBITED_HEADERS.iter() {
+            request.headers_mut().remove(header);
+        }
+
+        let (response, send) = send_request
+            .send_request(request, false)
+            .map_err(|e| Error::WebSocketError(e.to_string()))?;
+        let response = response.await.map_err(|e| Error::WebSocketError(e.to_string()))?;
+        if response.status() != StatusCode::OK {
+            return Err(Error::UnexpectedStatus(response.status()));
+        }
+
+        let (parts, recv) = response.into_parts();
+        Ok((Transport::H2 { send, recv }, BytesMut::new(), parts.headers))
+    }
+}
+
+fn header_contains(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+pub(crate) fn accept_key(nonce: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(nonce.as_bytes());
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+    BASE64.encode(sha1.finalize())
+}
+
+/// Byte stream the frames are exchanged over.
+enum Transport {
+    H1(MaybeTlsStream),
+    H2 {
+        send: SendStream<Bytes>,
+        recv: RecvStream,
+    },
+}
+
+impl Transport {
+    async fn read_into(&mut self, buf: &mut BytesMut) -> Result<()> {
+        match self {
+            Transport::H1(stream) => stream::read_more(stream, buf).await,
+            Transport::H2 { recv, .. } => match recv.data().await {
+                Some(Ok(data)) => {
+                    let _ = recv.flow_control().release_capacity(data.len());
+                    buf.extend_from_slice(&data);
+                    Ok(())
+                }
+                Some(Err(e)) => Err(Error::WebSocketError(e.to_string())),
+                None => Err(Error::IoError(std::io::ErrorKind::UnexpectedEof.into())),
+            },
+        }
+    }
+
+    async fn write_all(&mut self, data: BytesMut) -> Result<()> {
+        match self {
+            Transport::H1(stream) => {
+                let (res, _) = stream.write_all(data).await;
+                res.map_err(Error::IoError)?;
+                stream.flush().await.map_err(Error::IoError)
+            }
+            Transport::H2 { send, .. } => send
+                .send_data(data.freeze(), false)
+                .map_err(|e| Error::WebSocketError(e.to_string())),
+        }
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        match self {
+            Transport::H1(stream) => stream.shutdown().await.map_err(Error::IoError),
+            Transport::H2 { send, .. } => send
+                .send_data(Bytes::new(), true)
+                .map_err(|e| Error::WebSocketError(e.to_string())),
+        }
+    }
+}
+
+/// An established WebSocket connection.
+/// Pings are answered automatically, received messages are reassembled from their fragments.
+pub struct WebSocket {
+    transport: Transport,
+    read_buf: BytesMut,
+    deflate: Option<Deflate>,
+    protocol: Option<String>,
+    max_message_size: usize,
+    close_timeout: Duration,
+    fragments: Option<(OpCode, bool, BytesMut)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl WebSocket {
+    /// Returns the subprotocol selected by the server, if any.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Returns true if the permessage-deflate extension is in use.
+    pub fn is_compressed(&self) -> bool {
+        self.deflate.is_some()
+    }
+
+    /// Sends a message as a single frame.
+    pub async fn send(&mut self, message: Message) -> Result<()> {
+        self.send_fragmented(message, usize::MAX).await
+    }
+
+    /// Sends a message, splitting data messages in frames carrying at most `fragment_size` bytes.
+    /// Control messages are never fragmented.
+    pub async fn send_fragmented(&mut self, message: Message, fragment_size: usize) -> Result<()> {
+        if self.close_sent {
+            return Err(Error::WebSocketError("close frame already sent".to_string()));
+        }
+
+        let (opcode, payload) = match message {
+            Message::Text(text) => (OpCode::Text, Bytes::from(text)),
+            Message::Binary(data) => (OpCode::Binary, data),
+            Message::Ping(data) => return self.send_control(OpCode::Ping, &data).await,
+            Message::Pong(data) => return self.send_control(OpCode::Pong, &data).await,
+            Message::Close(frame) => return self.send_close(frame).await,
+        };
+
+        let (payload, compressed) = match self.deflate.as_mut() {
+            Some(deflate) => (Bytes::from(deflate.compress(&payload)?), true),
+            None => (payload, false),
+        };
+
+        let fragment_size = fragment_size.max(1);
+        let mut buf = BytesMut::with_capacity(payload.len() + 14);
+        let mut chunks = payload.chunks(fragment_size).peekable();
+        let mut first = true;
+
+        // An empty message is still sent as one final frame
+        if chunks.peek().is_none() {
+            frame::encode(&mut buf, true, compressed, opcode, &[]);
+        }
+        while let Some(chunk) = chunks.next() {
+            let fin = chunks.peek().is_none();
+            let frame_opcode = if first { opcode } else { OpCode::Continuation };
+            frame::encode(&mut buf, fin, compressed && first, frame_opcode, chunk);
+            first = false;
+        }
+
+        self.transport.write_all(buf).await
+    }
+
+    /// Sends a ping, the matching pong is returned by `recv`.
+    pub async fn ping(&mut self, payload: Bytes) -> Result<()> {
+        self.send_control(OpCode::Ping, &payload).await
+    }
+
+    async fn send_control(&mut self, opcode: OpCode, payload: &[u8]) -> Result<()> {
+        if payload.len() > 125 {
+            return Err(Error::WebSocketError("control frame payload exceeds 125 bytes".to_string()));
+        }
+
+        let mut buf = BytesMut::with_capacity(payload.len() + 6);
+        frame::encode(&mut buf, true, false, opcode, payload);
+        self.transport.write_all(buf).await
+    }
+
+    async fn send_close(&mut self, frame: Option<CloseFrame>) -> Result<()> {
+        let mut payload = Vec::new();
+        if let Some(frame) = frame {
+            payload.extend_from_slice(&frame.code.to_be_bytes());
+            payload.extend_from_slice(frame.reason.as_bytes());
+        }
+
+        self.send_control(OpCode::Close, &payload).await?;
+        self.close_sent = true;
+        Ok(())
+    }
+
+    /// Receives the next message.
+    /// Returns `None` once the close handshake completed.
+    pub async fn recv(&mut self) -> Result<Option<Message>> {
+        loop {
+            if self.close_received {
+                return Ok(None);
+            }
+
+            let frame = match frame::decode(&mut self.read_buf, self.max_message_size)? {
+                Some(frame) => frame,
+                None => {
+                    self.transport.read_into(&mut self.read_buf).await?;
+                    continue;
+                }
+            };
+
+            if let Some(message) = self.handle_frame(frame).await? {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    async fn handle_frame(&mut self, frame: Frame) -> Result<Option<Message>> {
+        match frame.opcode {
+            OpCode::Ping => {
+                if !self.close_sent {
+                    self.send_control(OpCode::Pong, &frame.payload).await?;
+                }
+                Ok(Some(Message::Ping(frame.payload)))
+            }
+            OpCode::Pong => Ok(Some(Message::Pong(frame.payload))),
+            OpCode::Close => {
+                self.close_received = true;
+                let close = parse_close(&frame.payload)?;
+                if !self.close_sent {
+                    // Echo the status code to complete the close handshake
+                    let echo = close.as_ref().map(|c| CloseFrame { code: c.code, reason: String::new() });
+                    self.send_close(echo).await?;
+                }
+                let _ = self.transport.shutdown().await;
+                Ok(Some(Message::Close(close)))
+            }
+            OpCode::Text | OpCode::Binary => {
+                if self.fragments.is_some() {
+                    return Err(Error::WebSocketError("new message started before the previous one ended".to_string()));
+                }
+                if frame.rsv1 && self.deflate.is_none() {
+                    return Err(Error::WebSocketError("compressed frame without permessage-deflate".to_string()));
+                }
+
+                let payload = BytesMut::from(&frame.payload[..]);
+                if frame.fin {
+                    return self.finish_message(frame.opcode, frame.rsv1, payload).map(Some);
+                }
+                self.fragments = Some((frame.opcode, frame.rsv1, payload));
+                Ok(None)
+            }
+            OpCode::Continuation => {
+                let Some((opcode, compressed, mut payload)) = self.fragments.take() else {
+                    return Err(Error::WebSocketError("continuation frame without a message".to_string()));
+                };
+                if payload.len() + frame.payload.len() > self.max_message_size {
+                    return Err(Error::WebSocketError(format!(
+                        "message exceeds the limit of {} bytes",
+                        self.max_message_size
+                    )));
+                }
+
+                payload.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    return self.finish_message(opcode, compressed, payload).map(Some);
+                }
+                self.fragments = Some((opcode, compressed, payload));
+                Ok(None)
+            }
+        }
+    }
+
+    fn finish_message(&mut self, opcode: OpCode, compressed: bool, payload: BytesMut) -> Result<Message> {
+        let payload = match (compressed, self.deflate.as_mut()) {
+            (true, Some(deflate)) => Bytes::from(deflate.decompress(&payload, self.max_message_size)?),
+            _ => payload.freeze(),
+        };
+
+        match opcode {
+            OpCode::Text => String::from_utf8(payload.to_vec())
+                .map(Message::Text)
+                .map_err(|_| Error::WebSocketError("text message is not valid UTF-8".to_string())),
+            _ => Ok(Message::Binary(payload)),
+        }
+    }
+
+    /// Starts the close handshake and waits for the server to acknowledge it, at most for the
+    /// close timeout of the builder. Messages received in the meantime are discarded.
+    /// The connection is shut down with an error if the server does not answer in time.
+    pub async fn close(mut self, frame: Option<CloseFrame>) -> Result<()> {
+        if !self.close_sent {
+            self.send_close(frame).await?;
+        }
+
+        let close_timeout = self.close_timeout;
+        let acknowledged = monoio::time::timeout(close_timeout, async {
+            while self.recv().await?.is_some() {}
+            Ok(())
+        })
+        .await;
+
+        match acknowledged {
+            Ok(result) => result,
+            Err(_) => {
+                let _ = self.transport.shutdown().await;
+                Err(Error::WebSocketError(format!(
+                    "close frame not acknowledged within {close_timeout:?}"
+                )))
+            }
+        }
+    }
+}
+
+fn parse_close(payload: &[u8]) -> Result<Option<CloseFrame>> {
+    match payload.len() {
+        0 => Ok(None),
+        1 => Err(Error::WebSocketError("invalid close frame payload".to_string())),
+        _ => {
+            let code = u16::from_be_bytes([payload[0], payload[1]]);
+            let reason = String::from_utf8(payload[2..].to_vec())
+                .map_err(|_| Error::WebSocketError("close reason is not valid UTF-8".to_string()))?;
+            Ok(Some(CloseFrame { code, reason }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn close_payloads_are_parsed() {
+        assert_eq!(parse_close(b"").unwrap(), None);
+        assert_eq!(
+            parse_close(b"\x03\xe9going away").unwrap(),
+            Some(CloseFrame { code: 1001, reason: "going away".to_string() })
+        );
+        assert!(matches!(parse_close(b"\x03"), Err(Error::WebSocketError(_))));
+        assert!(matches!(parse_close(b"\x03\xe8\xff"), Err(Error::WebSocketError(_))));
+    }
+}
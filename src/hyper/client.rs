@@ -1,21 +1,44 @@
+use std::future::Future;
 use std::rc::Rc;
 use std::time::Duration;
 
-use http::{HeaderMap, HeaderValue, Request, Uri};
-use http::header::{CONNECTION, UPGRADE};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue, Request, StatusCode, Uri, Version};
+use http::header::{AUTHORIZATION, CONNECTION, UPGRADE};
+use http_body_util::{Either, Full};
 use hyper::body::Incoming;
 use hyper::client::conn::{http1::Builder as H1Builder, http2::Builder as H2Builder};
-use monoio_transports::connectors::{Connector, TcpConnector};
+use monoio_transports::connectors::Connector;
 use monoio_transports::connectors::pollio::PollIo;
 use monoio_transports::http::hyper::{HyperH1Connection, HyperH1Connector, HyperH2Connection, HyperH2Connector, MonoioExecutor};
 use monoio_transports::pool::ConnectionPool;
 #[cfg(feature = "hyper-tls")]
 use monoio_transports::http::hyper::HyperTlsConnector;
 
+#[cfg(feature = "cache")]
+use crate::cache::{CacheStatus, HttpCache, Lookup};
+#[cfg(feature = "http3")]
+use crate::h3::{Http3Config, Http3Connector, Http3Failure};
+#[cfg(feature = "mock")]
+use crate::mock::MockTransport;
+#[cfg(feature = "vcr")]
+use crate::vcr::Cassette;
+#[cfg(not(feature = "hyper-tls"))]
+use crate::http::{raw::{self, RawResponse}, stream::RawConnector, upgrade};
 use crate::{
+    auth::{RequestAuth, TokenProvider},
+    batch::Batch,
+    circuit::CircuitBreaker,
+    rate_limit::RateLimiter,
+    sign::{RequestSigner, SignerHook},
+    propagation::{Propagation, Propagator, TraceContext},
     hyper::hyper_body::HyperBody,
+    origin::{ProtocolMemory, TcpProtocol},
     error::{Error, TransportError},
-    request::HttpRequest,
+    request::{ExpectContinue, HttpRequest, RequestOptions},
+    sse::EventSource,
+    response::{HttpResponse, ResponseHead, ResponseLimits},
+    trace::{self, Phase, PhaseConnector, RequestTrace},
     key::PoolKey,
     Protocol,
     build_connection_pool,
@@ -24,25 +47,39 @@ use crate::{
 
 enum HyperH1ConnectorType {
     #[allow(dead_code)]
-    HTTP(HyperH1Connector<PollIo<TcpConnector>, PoolKey, HyperBody>),
+    HTTP(HyperH1Connector<PollIo<PhaseConnector>, PoolKey, HyperBody>),
     #[cfg(feature = "hyper-tls")]
-    HTTPS(HyperH1Connector<HyperTlsConnector<TcpConnector>, PoolKey, HyperBody>)
+    HTTPS(HyperH1Connector<HyperTlsConnector<PhaseConnector>, PoolKey, HyperBody>)
 }
 
 enum HyperH2ConnectorType {
     #[allow(dead_code)]
-    HTTP(HyperH2Connector<PollIo<TcpConnector>, PoolKey, HyperBody>),
+    HTTP(HyperH2Connector<PollIo<PhaseConnector>, PoolKey, HyperBody>),
     #[cfg(feature = "hyper-tls")]
-    HTTPS(HyperH2Connector<HyperTlsConnector<TcpConnector>, PoolKey, HyperBody>)
+    HTTPS(HyperH2Connector<HyperTlsConnector<PhaseConnector>, PoolKey, HyperBody>)
 }
 
+const HTTP2_SETTINGS: HeaderName = HeaderName::from_static("http2-settings");
+
+/// Body of a streamed response, read from the connection or answered by the mock transport.
+pub(crate) type StreamingBody = Either<Incoming, Full<Bytes>>;
+
 #[derive(Default, Clone, Debug)]
 struct HyperClientConfig {
     default_headers: Rc<HeaderMap>,
+    response_limits: ResponseLimits,
+    expect_continue: ExpectContinue,
+    auth: RequestAuth,
+    signer: Option<SignerHook>,
+    propagation: Option<Propagation>,
+    #[cfg(feature = "cache")]
+    cache: Option<HttpCache>,
 }
 
 impl HyperClientConfig {
-    pub fn new(header_map: HeaderMap) -> Self { HyperClientConfig { default_headers: Rc::new(header_map) } }
+    pub fn new(header_map: HeaderMap) -> Self {
+        HyperClientConfig { default_headers: Rc::new(header_map), ..HyperClientConfig::default() }
+    }
 }
 
 struct HyperClientInner {
@@ -50,6 +87,17 @@ struct HyperClientInner {
     protocol: Protocol,
     h1_connector: Option<HyperH1ConnectorType>,
     h2_connector: Option<HyperH2ConnectorType>,
+    #[cfg(not(feature = "hyper-tls"))]
+    raw_connector: RawConnector,
+    protocols: ProtocolMemory,
+    rate_limiter: Option<RateLimiter>,
+    circuit_breaker: Option<CircuitBreaker>,
+    #[cfg(feature = "http3")]
+    http3: Option<Http3Connector>,
+    #[cfg(feature = "mock")]
+    mock: Option<MockTransport>,
+    #[cfg(feature = "vcr")]
+    cassette: Option<Cassette>,
 }
 
 pub struct MonoioHyperClient {
@@ -80,6 +128,21 @@ struct HyperClientBuilderConfig {
     idle_timeout_duration: Option<Duration>,
     h1_builder: Option<H1Builder>,
     h2_builder: Option<H2Builder<MonoioExecutor>>,
+    response_limits: ResponseLimits,
+    expect_continue: ExpectContinue,
+    auth: RequestAuth,
+    signer: Option<SignerHook>,
+    propagation: Option<Propagation>,
+    rate_limiter: Option<RateLimiter>,
+    circuit_breaker: Option<CircuitBreaker>,
+    #[cfg(feature = "cache")]
+    cache: Option<HttpCache>,
+    #[cfg(feature = "http3")]
+    http3: Option<Http3Config>,
+    #[cfg(feature = "mock")]
+    mock: Option<MockTransport>,
+    #[cfg(feature = "vcr")]
+    cassette: Option<Cassette>,
 }
 
 #[derive(Default)]
@@ -141,6 +204,34 @@ impl HyperClientBuilder {
         self
     }
 
+    /// Enables HTTP/3 for https origins advertising it through `Alt-Svc`, or for all of them
+    /// with `Http3Config::prior_knowledge`. Other requests negotiate HTTP/2 or HTTP/1.1 as in the
+    /// default mode, which is also used when the HTTP/3 endpoint can't be reached.
+    /// Available only on crate feature http3
+    #[cfg(feature = "http3")]
+    pub fn http3(mut self, config: Http3Config) -> Self {
+        self.build_config.protocol = Protocol::Http3;
+        self.build_config.http3 = Some(config);
+        self
+    }
+
+    /// Answers the requests of the client from `transport` instead of the network.
+    /// Available only on crate feature mock
+    #[cfg(feature = "mock")]
+    pub fn mock(mut self, transport: MockTransport) -> Self {
+        self.build_config.mock = Some(transport);
+        self
+    }
+
+    /// Records the exchanges of the client to `cassette`, or answers requests from it,
+    /// depending on its `VcrMode`.
+    /// Available only on crate feature vcr
+    #[cfg(feature = "vcr")]
+    pub fn cassette(mut self, cassette: Cassette) -> Self {
+        self.build_config.cassette = Some(cassette);
+        self
+    }
+
     /// Replaces the default HTTP/1.1 builder with a custom configured one.
     /// Useful when you need fine-grained control over HTTP/1.1 connection settings.
     pub fn with_h1_builder(mut self, builder: H1Builder) -> Self {
@@ -154,17 +245,128 @@ impl HyperClientBuilder {
         self.build_config.h2_builder = Some(builder);
         self
     }
+
+    /// Sets the maximum size in bytes of a response body collected by this client.
+    /// Responses advertising a larger `Content-Length` are rejected before reading,
+    /// streamed bodies are rejected once the limit is crossed. Can be overridden per request.
+    pub fn max_response_body_size(mut self, val: usize) -> Self {
+        self.build_config.response_limits.max_body_size = Some(val);
+        self
+    }
+
+    /// Sets the maximum number of headers accepted in a response, the HTTP/1.1 parser stops once
+    /// it is exceeded. Applied on top of a builder set with `with_h1_builder`.
+    pub fn max_response_headers(mut self, val: usize) -> Self {
+        self.build_config.response_limits.max_headers = Some(val);
+        self
+    }
+
+    /// Sets the maximum accumulated size in bytes of the response headers, counted as
+    /// `name: value\r\n` per header. HTTP/2 connections advertise it as `SETTINGS_MAX_HEADER_LIST_SIZE`
+    /// with the per field overhead of the setting allowed for, on top of a builder set with `with_h2_builder`.
+    pub fn max_response_header_size(mut self, val: usize) -> Self {
+        self.build_config.response_limits.max_header_size = Some(val);
+        self
+    }
+
+    /// Sends request bodies of at least `val` bytes only after the server answered `100 Continue`
+    /// to the request head, see `HttpRequest::expect_continue`.
+    pub fn expect_continue_threshold(mut self, val: usize) -> Self {
+        self.build_config.expect_continue.threshold = Some(val);
+        self
+    }
+
+    /// Sets how long a request with `Expect: 100-continue` waits for the server before sending
+    /// its body anyway. Default is 1 second.
+    pub fn expect_continue_timeout(mut self, val: Duration) -> Self {
+        self.build_config.expect_continue.timeout = val;
+        self
+    }
+
+    /// Sends the bearer token of `provider` with every request that doesn't set its own
+    /// `Authorization` header, a request answered with `401 Unauthorized` is retried once
+    /// with a refreshed token.
+    pub fn token_provider(mut self, provider: impl TokenProvider + 'static) -> Self {
+        self.build_config.auth.token_provider = Some(Rc::new(provider));
+        self
+    }
+
+    /// Signs every request with `signer` once its headers are final, including the computed `Host`,
+    /// right before it is sent. See `HttpRequest::sign_with` to sign a single request.
+    pub fn signer(mut self, signer: impl RequestSigner + 'static) -> Self {
+        self.build_config.signer = Some(SignerHook::new(signer));
+        self
+    }
+
+    /// Injects the current `TraceContext` into the headers of every request with `propagator`,
+    /// see `TraceContext::scope`.
+    /// # Examples
+    /// ```
+    /// let client = builder.trace_propagation(W3CTraceContext).build();
+    /// ```
+    pub fn trace_propagation(self, propagator: impl Propagator + 'static) -> Self {
+        self.trace_propagation_with(propagator, TraceContext::current)
+    }
+
+    /// Injects the trace context returned by `source` into the headers of every request with `propagator`,
+    /// for tracers keeping their own notion of the current span.
+    pub fn trace_propagation_with<F>(mut self, propagator: impl Propagator + 'static, source: F) -> Self
+        where
+            F: Fn() -> Option<TraceContext> + 'static,
+    {
+        self.build_config.propagation = Some(Propagation::new(propagator, source));
+        self
+    }
+
+    /// Delays requests going over the network so they stay under the rates of `limiter`,
+    /// requests answered from the cache or a cassette are not counted.
+    /// # Examples
+    /// ```
+    /// let client = builder.rate_limiter(RateLimiter::new().per_host(Rate::per_second(10))).build();
+    /// ```
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.build_config.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Fails requests to origins whose circuit is open in `breaker` with `Error::CircuitOpen`,
+    /// without waiting for the rate limiter or touching the network.
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.build_config.circuit_breaker = Some(breaker);
+        self
+    }
+
+    /// Enables the http cache for `GET` requests made through this client.
+    /// The cache status of each response is available as `CacheStatus` in its extensions.
+    /// Available only on crate feature cache
+    #[cfg(feature = "cache")]
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.build_config.cache = Some(cache);
+        self
+    }
 }
 
 impl HyperClientBuilder {
     pub fn build(&self) -> MonoioHyperClient {
-        let build_config = self.build_config.clone();
-        let tcp_connector = TcpConnector::default();
-        let protocol_h1 = build_config.protocol.is_protocol_h1();
-        let protocol_h2 = build_config.protocol.is_protocol_h2();
-        let protocol_auto = build_config.protocol.is_protocol_auto();
+        let mut build_config = self.build_config.clone();
+        // The parsers stop reading a response head once it exceeds the header limits
+        let limits = build_config.response_limits;
+        if let Some(max) = limits.max_headers {
+            build_config.h1_builder.get_or_insert_with(H1Builder::new).max_headers(max);
+        }
+        if let Some(max) = limits.h2_max_header_list_size() {
+            build_config
+                .h2_builder
+                .get_or_insert_with(|| H2Builder::new(MonoioExecutor))
+                .max_header_list_size(max);
+        }
+        let tcp_connector = PhaseConnector::default();
+        let protocol = build_config.protocol.tcp_protocol();
+        let protocol_h1 = protocol.is_protocol_h1();
+        let protocol_h2 = protocol.is_protocol_h2();
+        let protocol_auto = protocol.is_protocol_auto();
 
-        let config = if protocol_auto {
+        let mut config = if protocol_auto {
             // If protocol is Auto, add connection upgrade headers to default headers for every request
             let mut default_headers = build_config.default_headers.clone();
             default_headers.insert(UPGRADE, HeaderValue::from_static("h2c"));
@@ -172,14 +374,30 @@ impl HyperClientBuilder {
                 CONNECTION,
                 HeaderValue::from_static("Upgrade, HTTP2-Settings"),
             );
-            default_headers.insert("HTTP2-Settings", HeaderValue::from_static("AAMAAABkAAQAAP__"));
+            default_headers.insert(HTTP2_SETTINGS, HeaderValue::from_static("AAMAAABkAAQAAP__"));
 
             HyperClientConfig::new(default_headers)
         } else {
-            HyperClientConfig::default()
+            HyperClientConfig::new(build_config.default_headers.clone())
         };
+        config.response_limits = build_config.response_limits;
+        config.expect_continue = build_config.expect_continue;
+        config.auth = build_config.auth.clone();
+        config.signer = build_config.signer.clone();
+        config.propagation = build_config.propagation.clone();
+        #[cfg(feature = "cache")]
+        {
+            config.cache = build_config.cache.clone();
+        }
 
-        let protocol = build_config.protocol.clone();
+        #[cfg(feature = "http3")]
+        let http3 = build_config.http3.clone().map(Http3Connector::new);
+        #[cfg(feature = "mock")]
+        let mock = build_config.mock.clone();
+        #[cfg(feature = "vcr")]
+        let cassette = build_config.cassette.clone();
+        let rate_limiter = build_config.rate_limiter.clone();
+        let circuit_breaker = build_config.circuit_breaker.clone();
 
         // Build H1 connector with connection pool
         let h1_connector = if protocol_h1 || protocol_auto {
@@ -206,13 +424,24 @@ impl HyperClientBuilder {
             protocol,
             h1_connector,
             h2_connector,
+            #[cfg(not(feature = "hyper-tls"))]
+            raw_connector: RawConnector::default(),
+            protocols: ProtocolMemory::default(),
+            rate_limiter,
+            circuit_breaker,
+            #[cfg(feature = "http3")]
+            http3,
+            #[cfg(feature = "mock")]
+            mock,
+            #[cfg(feature = "vcr")]
+            cassette,
         });
 
         MonoioHyperClient { inner }
     }
 
     fn build_h1_connector(
-        connector: TcpConnector,
+        connector: PhaseConnector,
         alpn: Option<Vec<&str>>,
         pool: ConnectionPool<PoolKey, HyperH1Connection<HyperBody>>,
         build_config: HyperClientBuilderConfig
@@ -221,7 +450,7 @@ impl HyperClientBuilder {
             Some(_) => {
                 #[cfg(feature = "hyper-tls")]
                 {
-                    let connector = HyperTlsConnector::new_with_tls_default(connector, alpn);
+                    let connector = HyperTlsConnector::new_with_tls_default(PhaseConnector::for_tls(), alpn);
                     let mut h1 = HyperH1Connector::new_with_pool(connector, pool);
                     if let Some(builder) = build_config.h1_builder {
                         h1 = h1.with_hyper_builder(builder);
@@ -242,7 +471,7 @@ impl HyperClientBuilder {
     }
 
     fn build_h2_connector(
-        connector: TcpConnector,
+        connector: PhaseConnector,
         alpn: Option<Vec<&str>>,
         pool: ConnectionPool<PoolKey, HyperH2Connection<HyperBody>>,
         build_config: HyperClientBuilderConfig
@@ -251,7 +480,7 @@ impl HyperClientBuilder {
             Some(_) => {
                 #[cfg(feature = "hyper-tls")]
                 {
-                    let connector = HyperTlsConnector::new_with_tls_default(connector, alpn);
+                    let connector = HyperTlsConnector::new_with_tls_default(PhaseConnector::for_tls(), alpn);
                     let mut h2 = HyperH2Connector::new_with_pool(connector, pool);
                     if let Some(builder) = build_config.h2_builder {
                         h2 = h2.with_hyper_builder(builder);
@@ -274,20 +503,429 @@ impl HyperClientBuilder {
 
 impl MonoioHyperClient {
     pub fn new_request(&self) -> HttpRequest<MonoioHyperClient> {
-        let mut request = HttpRequest::new(self.clone());
-        for (key, val) in self.inner.config.default_headers.iter() {
-            request = request.set_header(key, val)
+        HttpRequest::new(self.clone(), self.request_options())
+    }
+
+    /// Per request settings initialised from the client configuration.
+    pub(crate) fn request_options(&self) -> RequestOptions {
+        RequestOptions {
+            default_headers: self.inner.config.default_headers.clone(),
+            limits: self.inner.config.response_limits,
+            expect_continue: self.inner.config.expect_continue,
+            auth: self.inner.config.auth.clone(),
+            signer: self.inner.config.signer.clone(),
+            propagation: self.inner.config.propagation.clone(),
+            ..RequestOptions::default()
         }
+    }
+
+    /// Opens a server-sent events stream, reconnecting with `Last-Event-ID` when the connection drops.
+    /// # Examples
+    /// ```
+    /// let mut events = client.event_source("https://example.com/events");
+    /// while let Some(event) = events.next_event().await? {
+    ///     println!("{}", event.data);
+    /// }
+    /// ```
+    pub fn event_source<T>(&self, uri: T) -> EventSource<MonoioHyperClient>
+        where
+            Uri: TryFrom<T>,
+            <Uri as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        EventSource::new(self.clone(), Uri::try_from(uri).map_err(Into::into))
+    }
 
-        request
+    /// Collects requests to send concurrently with per origin and global limits, see `Batch`.
+    /// The HTTP/2 stream limit of the Hyper client is negotiated with the server, only `max_per_host`
+    /// applies to each origin.
+    pub fn batch(&self) -> Batch<MonoioHyperClient> {
+        Batch::new(self.clone())
     }
 
-    pub(crate) async fn send_request(
+    /// Returns the protocol the next request to the origin of `uri` is expected to use,
+    /// as negotiated by earlier requests or advertised through `Alt-Svc`.
+    pub fn known_protocol(&self, uri: &Uri) -> Option<Version> {
+        let key = PoolKey::try_from(uri).ok()?;
+        self.inner.protocols.known_version(&key)
+    }
+
+    /// Sends the request, authenticating it with the token provider or answering a Digest challenge.
+    pub(crate) async fn execute(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<Bytes>, Error> {
+        self.authenticated(req, uri, options, |req, uri| self.execute_once(req, uri, options))
+            .await
+    }
+
+    /// Sends a request whose response body is handed back unread, used by long lived streams.
+    /// The request is authenticated, signed and guarded like the others,
+    /// the cache, the cassette and hedging don't apply.
+    pub(crate) async fn execute_streaming(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<http::Response<StreamingBody>, Error> {
+        self.authenticated(req, uri, options, |req, uri| self.stream_once(req, uri, options))
+            .await
+    }
+
+    /// Sends an upgrade or `CONNECT` request over a dedicated HTTP/1.1 connection that can be taken over.
+    /// The request is authenticated, signed and guarded like the others,
+    /// the cache, the cassette and hedging don't apply.
+    #[cfg(not(feature = "hyper-tls"))]
+    pub(crate) async fn execute_upgrade(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<RawResponse, Error> {
+        self.authenticated(req, uri, options, |req, uri| self.upgrade_once(req, uri, options))
+            .await
+    }
+
+    /// Injects the trace context, authorizes the request with the token provider, and sends it
+    /// once more answering a Digest challenge or with a refreshed token if it was rejected.
+    async fn authenticated<R, F, Fut>(
+        &self,
+        mut req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+        send: F,
+    ) -> Result<R, Error>
+        where
+            R: ResponseHead,
+            F: Fn(Request<HyperBody>, Uri) -> Fut,
+            Fut: Future<Output = Result<R, Error>>,
+    {
+        if let Some(propagation) = options.propagation.as_ref() {
+            propagation.inject(req.headers_mut());
+        }
+
+        if !options.auth.is_enabled() {
+            return send(req, uri).await;
+        }
+
+        let (mut parts, body) = req.into_parts();
+        options.auth.authorize(&mut parts.headers).await?;
+        let response = send(Request::from_parts(parts.clone(), body.clone()), uri.clone()).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        match options.auth.reauthorize(&parts, body.bytes(), response.headers()).await? {
+            Some(authorization) => {
+                parts.headers.insert(AUTHORIZATION, authorization);
+                send(Request::from_parts(parts, body), uri).await
+            }
+            None => Ok(response),
+        }
+    }
+
+    async fn execute_once(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<Bytes>, Error> {
+        let payload = req.body().bytes().cloned();
+        let req = options.sign(req, payload)?;
+
+        #[cfg(feature = "cache")]
+        if let Some(cache) = self.inner.config.cache.as_ref() {
+            return self.execute_cached(cache, req, uri, options).await;
+        }
+
+        self.dispatch(req, uri, options).await
+    }
+
+    #[cfg(feature = "cache")]
+    async fn execute_cached(
+        &self,
+        cache: &HttpCache,
+        mut req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<Bytes>, Error> {
+        let limits = options.limits;
+        let lookup = cache.lookup(&mut req);
+        if let Lookup::Fresh(response) = lookup {
+            let (parts, body) = response.into_parts();
+            return Ok(HttpResponse::from_parts(parts, body, limits, None));
+        }
+
+        let request_time = HttpCache::request_time();
+        let (req_parts, body) = req.into_parts();
+        let response = self
+            .dispatch(Request::from_parts(req_parts.clone(), body), uri, options)
+            .await?;
+        let (mut parts, body) = response.into_parts();
+
+        match lookup {
+            Lookup::Revalidate(entry) if parts.status == StatusCode::NOT_MODIFIED => {
+                let (parts, body) = cache
+                    .revalidated(&req_parts, entry, &parts, request_time)
+                    .into_parts();
+                return Ok(HttpResponse::from_parts(parts, body, limits, None));
+            }
+            _ => cache.invalidate(&req_parts, &parts),
+        }
+
+        parts.extensions.insert(CacheStatus::Miss);
+        if cache.is_storable(&req_parts, &parts) {
+            cache.store(&req_parts, &parts, &body, request_time);
+        }
+
+        Ok(HttpResponse::from_parts(parts, body, limits, options.download_progress.clone()))
+    }
+
+    /// Answers the request from the cassette of the client if it has one, recording the exchange
+    /// when it goes over the network.
+    async fn dispatch(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<Bytes>, Error> {
+        #[cfg(feature = "vcr")]
+        if let Some(cassette) = self.inner.cassette.as_ref() {
+            if let Some(response) = cassette.replay(&req, &uri, req.body().bytes())? {
+                let (parts, body) = response.into_parts();
+                return Ok(HttpResponse::from_parts(parts, body, options.limits, None));
+            }
+
+            let recording = cassette.recording(&req, &uri, req.body().bytes());
+            let response = self.hedged(req, uri, options).await?;
+            recording.finish(response.status(), response.version(), response.headers(), response.body())?;
+            return Ok(response);
+        }
+
+        self.hedged(req, uri, options).await
+    }
+
+    /// Sends the request, and a copy of it once the hedging delay of the request passed
+    /// without a response, returning the response arriving first.
+    async fn hedged(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<Bytes>, Error> {
+        let Some(hedge) = options.hedge.as_ref().filter(|hedge| hedge.applies(req.method())) else {
+            return self.guarded(req, uri, |req, uri| self.transport(req, uri, options)).await;
+        };
+
+        let (parts, body) = req.into_parts();
+        hedge
+            .send(|| {
+                let req = Request::from_parts(parts.clone(), body.clone());
+                self.guarded(req, uri.clone(), |req, uri| self.transport(req, uri, options))
+            })
+            .await
+    }
+
+    /// Fails fast while the circuit of the origin is open, then waits for the rate limiter of the client
+    /// before sending the request. The outcome is counted by the circuit breaker, and pauses the rate
+    /// limiter when the response says the quota is spent.
+    async fn guarded<R, F, Fut>(&self, req: Request<HyperBody>, uri: Uri, send: F) -> Result<R, Error>
+        where
+            R: ResponseHead,
+            F: FnOnce(Request<HyperBody>, Uri) -> Fut,
+            Fut: Future<Output = Result<R, Error>>,
+    {
+        let permit = match self.inner.circuit_breaker.as_ref() {
+            Some(breaker) => Some(breaker.permit(&uri)?),
+            None => None,
+        };
+        let key = match self.inner.rate_limiter.as_ref() {
+            Some(limiter) => limiter.acquire(&uri, req.headers()).await,
+            None => None,
+        };
+
+        let result = send(req, uri).await;
+        if let Some(permit) = permit {
+            permit.finish(result.as_ref().ok().map(|response| response.status()));
+        }
+        let response = result?;
+        if let Some(limiter) = self.inner.rate_limiter.as_ref() {
+            limiter.observe(key.as_ref(), response.status(), response.headers());
+        }
+        Ok(response)
+    }
+
+    /// Sends the request over a dedicated HTTP/1.1 connection when the body waits for `100 Continue`,
+    /// over HTTP/3 when the origin is known to support it, over the pooled HTTP/2 or HTTP/1.1
+    /// connections otherwise, and collects the response.
+    async fn transport(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<HttpResponse<Bytes>, Error> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.inner.mock.as_ref() {
+            let (parts, body) = mock.respond(&req, req.body().bytes())?.into_parts();
+            return Ok(HttpResponse::from_parts(parts, body, options.limits, None));
+        }
+
+        #[cfg(not(feature = "hyper-tls"))]
+        {
+            let len = req.body().bytes().map_or(0, Bytes::len);
+            if options.expect_continue.applies(len) {
+                let req = req.map(|body| body.bytes().cloned());
+                let trace = RequestTrace::new(req.method(), &uri);
+                let send = raw::send(&self.inner.raw_connector, req, uri, options.raw_body(len), &options.limits);
+                let raw = trace.raw(send).await?;
+                let progress = options.download_progress.clone();
+                return Ok(HttpResponse::from_parts(raw.parts, raw.body, options.limits, progress));
+            }
+        }
+
+        let protocols = &self.inner.protocols;
+        #[cfg(feature = "http3")]
+        if let Some(http3) = self.inner.http3.as_ref() {
+            // Request trailers are only sent over TCP
+            let route = http3.route(&uri, protocols).filter(|_| !req.body().has_trailers());
+            if let Some(route) = route {
+                match http3.send(&route, &req, req.body().bytes(), &options.limits).await {
+                    Ok(response) => {
+                        let (parts, body) = response.into_parts();
+                        if let Some(progress) = options.download_progress.as_ref() {
+                            progress.report(body.len() as u64, Some(body.len() as u64));
+                        }
+                        let progress = options.download_progress.clone();
+                        return Ok(HttpResponse::from_parts(parts, body, options.limits, progress));
+                    }
+                    Err(Http3Failure::Connect(_)) => http3.mark_broken(&route, protocols),
+                    Err(Http3Failure::Request(e)) => return Err(e),
+                }
+            }
+        }
+
+        let key = PoolKey::try_from(&uri).map_err(Error::UriKeyError)?;
+        let response = self.send_request(req, uri).await?;
+        protocols.observe_alt_svc(&key, response.headers());
+
+        HttpResponse::hyper_new(response, options.limits, options.download_progress.clone()).await
+    }
+
+    /// Signs and sends a request whose response body is streamed, through the circuit breaker
+    /// and the rate limiter.
+    async fn stream_once(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<http::Response<StreamingBody>, Error> {
+        let payload = req.body().bytes().cloned();
+        let req = options.sign(req, payload)?;
+        let response = self
+            .guarded(req, uri, |req, uri| self.transport_streaming(req, uri))
+            .await?;
+        options.limits.check_headers(response.headers())?;
+        Ok(response)
+    }
+
+    /// Signs and sends an upgrade or `CONNECT` request through the circuit breaker and the rate limiter.
+    #[cfg(not(feature = "hyper-tls"))]
+    async fn upgrade_once(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<RawResponse, Error> {
+        let payload = req.body().bytes().cloned();
+        let req = options.sign(req, payload)?;
+        self.guarded(req, uri, |req, uri| self.transport_upgrade(req, uri, options))
+            .await
+    }
+
+    /// Sends an upgrade or `CONNECT` request over a dedicated HTTP/1.1 connection.
+    /// Mocked responses carry no connection to take over.
+    #[cfg(not(feature = "hyper-tls"))]
+    async fn transport_upgrade(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+        options: &RequestOptions,
+    ) -> Result<RawResponse, Error> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.inner.mock.as_ref() {
+            let (parts, body) = mock.respond(&req, req.body().bytes())?.into_parts();
+            return Ok(RawResponse { parts, body, upgraded: None });
+        }
+
+        let req = req.map(|body| body.bytes().cloned());
+        let target = options.connect_target.as_deref();
+        let trace = RequestTrace::new(req.method(), &uri);
+        trace
+            .raw(upgrade::send(&self.inner.raw_connector, req, uri, target, &options.limits))
+            .await
+    }
+
+    /// Sends a request whose response body is streamed over the pooled HTTP/2 or HTTP/1.1 connections,
+    /// over the HTTP/2 connections whatever the protocol of the client for requests asking for HTTP/2.
+    async fn transport_streaming(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+    ) -> Result<http::Response<StreamingBody>, Error> {
+        #[cfg(feature = "mock")]
+        if let Some(mock) = self.inner.mock.as_ref() {
+            let response = mock.respond(&req, req.body().bytes())?;
+            return Ok(response.map(|body| Either::Right(Full::new(body))));
+        }
+
+        // Requests for HTTP/2, such as gRPC calls, skip the protocol negotiation
+        let response = if req.version() == Version::HTTP_2 && self.inner.h2_connector.is_some() {
+            self.send_h2(req, uri).await?
+        } else {
+            self.send_request(req, uri).await?
+        };
+        Ok(response.map(Either::Left))
+    }
+
+    /// Sends the request over the pooled HTTP/2 connections, without protocol negotiation.
+    async fn send_h2(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+    ) -> Result<http::Response<Incoming>, Error> {
+        let key = PoolKey::try_from(&uri).map_err(Error::UriKeyError)?;
+        let connector = self
+            .inner
+            .h2_connector
+            .as_ref()
+            .ok_or(Error::ConnectorNotInitialized)?;
+        let trace = RequestTrace::new(req.method(), &uri);
+        let send = async {
+            let mut conn = get_connection_from_connector!(h2 connector, key)?;
+            trace::phase(Phase::FirstByte, conn.send_request(req))
+                .await
+                .map_err(response_error)
+        };
+        trace.response(send).await
+    }
+
+    async fn send_request(
+        &self,
+        req: Request<HyperBody>,
+        uri: Uri,
+    ) -> Result<http::Response<Incoming>, Error> {
+        let trace = RequestTrace::new(req.method(), &uri);
+        trace.response(self.send_pooled(req, uri)).await
+    }
+
+    async fn send_pooled(
         &self,
         req: Request<HyperBody>,
         uri: Uri,
     ) -> Result<http::Response<Incoming>, Error> {
-        let key = uri.try_into().map_err(|e| Error::UriKeyError(e))?;
+        let key: PoolKey = uri.try_into().map_err(|e| Error::UriKeyError(e))?;
+        let protocols = &self.inner.protocols;
 
         let response = match self.inner.protocol
         {
@@ -297,7 +935,7 @@ impl MonoioHyperClient {
                     key
                 )?;
 
-                conn.send_request(req).await
+                trace::phase(Phase::FirstByte, conn.send_request(req)).await
             }
             Protocol::Http2 => {
                 let mut conn = get_connection_from_connector!(
@@ -305,19 +943,41 @@ impl MonoioHyperClient {
                     key
                 )?;
 
-                conn.send_request(req).await
+                trace::phase(Phase::FirstByte, conn.send_request(req)).await
+            }
+            Protocol::Auto if protocols.tcp_protocol(&key) == Some(TcpProtocol::Http2) => {
+                // The origin accepted h2c before, the upgrade round trip is skipped
+                let mut conn = get_connection_from_connector!(
+                    h2 self.inner.h2_connector.as_ref().unwrap(),
+                    key.clone()
+                )
+                .inspect_err(|_| protocols.forget_tcp(&key))?;
+
+                let response = trace::phase(Phase::FirstByte, conn.send_request(req)).await;
+                if response.is_err() {
+                    protocols.forget_tcp(&key);
+                }
+                response
             }
             Protocol::Auto => {
+                let mut req = req;
+                if protocols.tcp_protocol(&key) == Some(TcpProtocol::Http1) {
+                    // The origin declined h2c before, the upgrade is not offered again
+                    let headers = req.headers_mut();
+                    headers.remove(UPGRADE);
+                    headers.remove(CONNECTION);
+                    headers.remove(HTTP2_SETTINGS);
+                }
+
                 // First create Http/1.1 connection with upgrade headers set
                 let mut conn = get_connection_from_connector!(
                     self.inner.h1_connector.as_ref().unwrap(),
                     key.clone()
                 )?;
 
-                let maybe_response = conn
-                    .send_request(req.clone())
+                let maybe_response = trace::phase(Phase::FirstByte, conn.send_request(req.clone()))
                     .await
-                    .map_err(|e| Error::HyperResponseError(e))?;
+                    .map_err(response_error)?;
 
                 // Check if server response contains the upgrade header
                 let should_upgrade_to_h2 = maybe_response
@@ -328,20 +988,36 @@ impl MonoioHyperClient {
                     .unwrap_or(false);
 
                 if should_upgrade_to_h2 {
+                    protocols.record_tcp(&key, TcpProtocol::Http2);
+
                     // Switching to H2 connection
                     let mut conn = get_connection_from_connector!(
                         h2 self.inner.h2_connector.as_ref().unwrap(),
                         key
                     )?;
 
-                    conn.send_request(req).await
+                    trace::phase(Phase::FirstByte, conn.send_request(req)).await
                 } else {
+                    protocols.record_tcp(&key, TcpProtocol::Http1);
+
                     // Return the original H1 response
                     Ok(maybe_response)
                 }
             }
+            // Connections over TCP use the Auto protocol when HTTP/3 is enabled
+            #[cfg(feature = "http3")]
+            Protocol::Http3 => unreachable!("HTTP/3 is stored as its TCP fallback protocol"),
         };
 
-        response.map_err(|e| Error::HyperResponseError(e))
+        response.map_err(response_error)
     }
-}
\ No newline at end of file
+}
+/// Reports a response head the parser stopped reading at the header limits as too large.
+fn response_error(e: hyper::Error) -> Error {
+    // `is_parse_too_large` is only available to servers, the kind is told by its description
+    if e.is_parse() && e.to_string().contains("too large") {
+        Error::ResponseHeadersTooLarge(e.to_string())
+    } else {
+        Error::HyperResponseError(e)
+    }
+}
@@ -1,17 +1,105 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::marker::PhantomData;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
 
 use bytes::Bytes;
+use http::HeaderMap;
 use hyper::body::{Body as HttpBody, Frame};
 use hyper::Error;
 
+use crate::progress::ProgressHook;
 use crate::request::RequestBody;
 
+// Bodies with upload progress tracking are yielded in frames of this size
+const UPLOAD_FRAME_SIZE: usize = 64 * 1024;
+
+/// Frames fed by a `BodySender` and not yet taken by the connection.
+#[derive(Debug, Default)]
+struct FrameQueue {
+    frames: VecDeque<Bytes>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl FrameQueue {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Feeds the frames of a body created with `HyperBody::channel`, the body ends once it is dropped.
+pub(crate) struct BodySender(Rc<RefCell<FrameQueue>>);
+
+impl BodySender {
+    pub(crate) fn send(&self, frame: Bytes) {
+        let mut queue = self.0.borrow_mut();
+        queue.frames.push_back(frame);
+        queue.wake();
+    }
+}
+
+impl Drop for BodySender {
+    fn drop(&mut self) {
+        let mut queue = self.0.borrow_mut();
+        queue.closed = true;
+        queue.wake();
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HyperBody {
     _marker: PhantomData<*const ()>,
     data: Option<Bytes>,
+    stream: Option<Rc<RefCell<FrameQueue>>>,
+    progress: Option<ProgressHook>,
+    trailers: Option<HeaderMap>,
+    total: u64,
+    sent: u64,
+}
+
+impl HyperBody {
+    /// Creates a body whose frames are fed by the returned sender while the request is in flight.
+    pub(crate) fn channel() -> (BodySender, Self) {
+        let queue = Rc::new(RefCell::new(FrameQueue::default()));
+        let body = HyperBody {
+            _marker: PhantomData,
+            data: None,
+            stream: Some(queue.clone()),
+            progress: None,
+            trailers: None,
+            total: 0,
+            sent: 0,
+        };
+        (BodySender(queue), body)
+    }
+
+    /// Reports upload progress to `hook` as the body frames are handed to the connection.
+    pub(crate) fn with_progress(mut self, hook: ProgressHook) -> Self {
+        self.total = self.data.as_ref().map_or(0, |d| d.len() as u64);
+        self.progress = Some(hook);
+        self
+    }
+
+    /// Sends `trailers` in a trailers frame after the body data.
+    pub(crate) fn with_trailers(mut self, trailers: HeaderMap) -> Self {
+        self.trailers = Some(trailers);
+        self
+    }
+
+    #[cfg(feature = "http3")]
+    pub(crate) fn has_trailers(&self) -> bool {
+        self.trailers.is_some()
+    }
+
+    /// Returns the body data that was not yielded yet.
+    pub(crate) fn bytes(&self) -> Option<&Bytes> {
+        self.data.as_ref()
+    }
 }
 
 impl From<Bytes> for HyperBody {
@@ -19,6 +107,11 @@ impl From<Bytes> for HyperBody {
         HyperBody {
             _marker: PhantomData,
             data: Some(a.into()),
+            stream: None,
+            progress: None,
+            trailers: None,
+            total: 0,
+            sent: 0,
         }
     }
 }
@@ -29,9 +122,35 @@ impl HttpBody for HyperBody {
 
     fn poll_frame(
         self: Pin<&mut Self>,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
-        Poll::Ready(self.get_mut().data.take().map(|d| Ok(Frame::data(d))))
+        let this = self.get_mut();
+        if let Some(queue) = this.stream.as_ref() {
+            let mut queue = queue.borrow_mut();
+            return match queue.frames.pop_front() {
+                Some(frame) => Poll::Ready(Some(Ok(Frame::data(frame)))),
+                None if queue.closed => Poll::Ready(None),
+                None => {
+                    queue.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            };
+        }
+
+        let chunk = match (this.data.as_mut(), this.progress.as_ref()) {
+            (Some(data), Some(_)) if data.len() > UPLOAD_FRAME_SIZE => Some(data.split_to(UPLOAD_FRAME_SIZE)),
+            _ => this.data.take(),
+        };
+
+        if let (Some(chunk), Some(progress)) = (chunk.as_ref(), this.progress.as_ref()) {
+            this.sent += chunk.len() as u64;
+            progress.report(this.sent, Some(this.total));
+        }
+
+        match chunk {
+            Some(chunk) => Poll::Ready(Some(Ok(Frame::data(chunk)))),
+            None => Poll::Ready(this.trailers.take().map(|t| Ok(Frame::trailers(t)))),
+        }
     }
 }
 
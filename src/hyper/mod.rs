@@ -20,15 +20,13 @@ macro_rules! get_connection_from_connector {
     ($connector:expr, $key:expr) => {{
         match $connector {
             HyperH1ConnectorType::HTTP(connector) => {
-                connector
-                    .connect($key)
+                $crate::trace::checkout(connector.connect($key))
                     .await
                     .map_err(|e| TransportError::HyperPollError(e))
             },
             #[cfg(feature = "hyper-tls")]
             HyperH1ConnectorType::HTTPS(connector) => {
-                connector
-                    .connect($key)
+                $crate::trace::checkout(connector.connect($key))
                     .await
                     .map_err(|e| TransportError::TlsStreamError(e))
             }
@@ -38,15 +36,13 @@ macro_rules! get_connection_from_connector {
     (h2 $connector:expr, $key:expr) => {{
         match $connector {
             HyperH2ConnectorType::HTTP(connector) => {
-                connector
-                    .connect($key)
+                $crate::trace::checkout(connector.connect($key))
                     .await
                     .map_err(|e| TransportError::HyperPollError(e))
             },
             #[cfg(feature = "hyper-tls")]
             HyperH2ConnectorType::HTTPS(connector) => {
-                connector
-                    .connect($key)
+                $crate::trace::checkout(connector.connect($key))
                     .await
                     .map_err(|e| TransportError::TlsStreamError(e))
             }
@@ -6,6 +6,33 @@ pub mod error;
 #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
 pub mod hyper;
 pub mod key;
+pub mod download;
+pub mod auth;
+pub mod sign;
+pub mod propagation;
+pub(crate) mod progress;
+pub(crate) mod origin;
+pub(crate) mod trace;
+pub mod timings;
+pub mod sse;
+pub mod batch;
+pub mod rate_limit;
+pub mod circuit;
+pub mod hedge;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "http3")]
+pub mod h3;
+#[cfg(feature = "http3")]
+pub(crate) mod alt_svc;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "vcr")]
+pub mod vcr;
+#[cfg(all(feature = "testing", not(feature = "hyper-tls")))]
+pub mod testing;
 
 #[derive(Default, Clone, PartialEq, Debug)]
 enum Protocol {
@@ -13,6 +40,8 @@ enum Protocol {
     Http2,
     #[default]
     Auto,
+    #[cfg(feature = "http3")]
+    Http3,
 }
 
 impl Protocol {
@@ -28,4 +57,14 @@ impl Protocol {
     pub(crate) fn is_protocol_auto(&self) -> bool {
         self.eq(&Protocol::Auto)
     }
+
+    /// Protocol used for connections over TCP, HTTP/3 falls back to protocol negotiation.
+    #[cfg_attr(not(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls")), allow(dead_code))]
+    pub(crate) fn tcp_protocol(&self) -> Protocol {
+        match self {
+            #[cfg(feature = "http3")]
+            Protocol::Http3 => Protocol::Auto,
+            protocol => protocol.clone(),
+        }
+    }
 }
\ No newline at end of file
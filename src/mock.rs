@@ -0,0 +1,341 @@
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri, Version};
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// Transport answering requests from registered expectations instead of the network.
+/// Requests sent by a client built with `mock` are matched against the expectations
+/// in registration order, the first match answers. This covers upgrades, `Expect: 100-continue`,
+/// request trailers, event streams and gRPC calls, a mocked upgrade has no connection to take over.
+/// WebSocket connections are not mocked.
+/// Available only on crate feature mock
+/// # Examples
+/// ```
+/// let mock = MockTransport::new();
+/// mock.register(
+///     Expectation::new(Method::GET, "/users/1")
+///         .match_header(ACCEPT, "application/json")
+///         .respond_with(MockResponse::new(StatusCode::OK).json(&user)?)
+///         .times(1),
+/// );
+///
+/// let client = MonoioClient::builder().mock(mock.clone()).build();
+/// let user: User = client.make_request().set_uri("http://api.local/users/1").send().await?.json().await?;
+/// mock.verify();
+/// ```
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Rc<RefCell<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    expectations: Vec<Expectation>,
+    received: Vec<RecordedRequest>,
+    unmatched: Vec<RecordedRequest>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    pub fn register(&self, expectation: Expectation) {
+        self.state.borrow_mut().expectations.push(expectation);
+    }
+
+    /// Returns every request the transport received, matched or not.
+    pub fn received_requests(&self) -> Vec<RecordedRequest> {
+        self.state.borrow().received.clone()
+    }
+
+    /// Panics if a request matched no expectation, or an expectation with an expected
+    /// number of calls was matched a different number of times.
+    pub fn verify(&self) {
+        let state = self.state.borrow();
+        let mut failures = Vec::new();
+
+        for expectation in state.expectations.iter() {
+            if let Some(expected) = expectation.times {
+                if expectation.calls != expected {
+                    failures.push(format!(
+                        "{} {} expected {expected} calls, received {}",
+                        expectation.method.as_ref().map_or("*", |m| m.as_str()),
+                        expectation.path,
+                        expectation.calls
+                    ));
+                }
+            }
+        }
+        for request in state.unmatched.iter() {
+            failures.push(format!("unexpected request {} {}", request.method, request.uri));
+        }
+
+        if !failures.is_empty() {
+            panic!("mock verification failed:\n{}", failures.join("\n"));
+        }
+    }
+
+    /// Answers `req` with the response of the first matching expectation.
+    pub(crate) fn respond<B>(&self, req: &Request<B>, body: Option<&Bytes>) -> Result<Response<Bytes>, Error> {
+        let request = RecordedRequest {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers: req.headers().clone(),
+            body: body.cloned().unwrap_or_default(),
+        };
+
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        state.received.push(request.clone());
+
+        let matched = state
+            .expectations
+            .iter_mut()
+            .find(|expectation| expectation.matches(&request));
+        match matched {
+            Some(expectation) => {
+                expectation.calls += 1;
+                Ok(expectation.response.to_response(req.version()))
+            }
+            None => {
+                let message = format!("no expectation matches {} {}", request.method, request.uri);
+                state.unmatched.push(request);
+                Err(Error::MockError(message))
+            }
+        }
+    }
+}
+
+impl Debug for MockTransport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MockTransport")
+    }
+}
+
+/// A request received by a `MockTransport`.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+type BodyPredicate = Rc<dyn Fn(&[u8]) -> bool>;
+
+enum BodyMatcher {
+    Exact(Bytes),
+    Json(serde_json::Value),
+    Contains(Bytes),
+    Custom(BodyPredicate),
+}
+
+impl BodyMatcher {
+    fn matches(&self, body: &[u8]) -> bool {
+        match self {
+            BodyMatcher::Exact(expected) => body == expected.as_ref(),
+            BodyMatcher::Json(expected) => {
+                serde_json::from_slice::<serde_json::Value>(body).is_ok_and(|value| value == *expected)
+            }
+            BodyMatcher::Contains(part) => part.is_empty() || body.windows(part.len()).any(|w| w == part.as_ref()),
+            BodyMatcher::Custom(matcher) => matcher(body),
+        }
+    }
+}
+
+/// A request a `MockTransport` expects, along with the response it answers with.
+/// Requests match on the method and path, and on every header, query and body matcher added.
+pub struct Expectation {
+    method: Option<Method>,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Vec<BodyMatcher>,
+    response: MockResponse,
+    times: Option<usize>,
+    calls: usize,
+    invalid: bool,
+}
+
+impl Expectation {
+    /// Expects `method` requests to `path`, answered with an empty `200 OK` unless set otherwise.
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Expectation {
+            method: Some(method),
+            path: path.into(),
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            response: MockResponse::new(StatusCode::OK),
+            times: None,
+            calls: 0,
+            invalid: false,
+        }
+    }
+
+    /// Expects requests of any method to `path`.
+    pub fn any_method(path: impl Into<String>) -> Self {
+        Expectation {
+            method: None,
+            ..Expectation::new(Method::GET, path)
+        }
+    }
+
+    /// Requires the query parameter `name` with `value`.
+    pub fn match_query(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((name.into(), value.into()));
+        self
+    }
+
+    /// Requires a `name` header with `value`, among the values of the header.
+    /// Invalid header names or values never match.
+    pub fn match_header<K, V>(mut self, name: K, value: V) -> Self
+        where
+            HeaderName: TryFrom<K>,
+            HeaderValue: TryFrom<V>,
+    {
+        match (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            (Ok(name), Ok(value)) => self.headers.push((name, value)),
+            _ => self.invalid = true,
+        }
+        self
+    }
+
+    /// Requires the body to be exactly `body`.
+    pub fn match_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body.push(BodyMatcher::Exact(body.into()));
+        self
+    }
+
+    /// Requires the body to be JSON equal to `value`, regardless of formatting and key order.
+    pub fn match_json<T: Serialize>(mut self, value: &T) -> Result<Self, Error> {
+        let value = serde_json::to_value(value).map_err(Error::SerdeSerializeError)?;
+        self.body.push(BodyMatcher::Json(value));
+        Ok(self)
+    }
+
+    /// Requires the body to contain `part`.
+    pub fn match_body_contains(mut self, part: impl Into<Bytes>) -> Self {
+        self.body.push(BodyMatcher::Contains(part.into()));
+        self
+    }
+
+    /// Requires `matcher` to accept the body.
+    pub fn match_body_with<F>(mut self, matcher: F) -> Self
+        where
+            F: Fn(&[u8]) -> bool + 'static,
+    {
+        self.body.push(BodyMatcher::Custom(Rc::new(matcher)));
+        self
+    }
+
+    pub fn respond_with(mut self, response: MockResponse) -> Self {
+        self.response = response;
+        self
+    }
+
+    /// Expects exactly `times` matching requests, checked by `MockTransport::verify`.
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    fn matches(&self, request: &RecordedRequest) -> bool {
+        if self.invalid {
+            return false;
+        }
+        if self.method.as_ref().is_some_and(|method| *method != request.method) {
+            return false;
+        }
+        if request.uri.path() != self.path {
+            return false;
+        }
+
+        let query = query_pairs(request.uri.query().unwrap_or(""));
+        let query_matches = self.query.iter().all(|expected| query.contains(expected));
+        let headers_match = self
+            .headers
+            .iter()
+            .all(|(name, value)| request.headers.get_all(name).iter().any(|v| v == value));
+        let body_matches = self.body.iter().all(|matcher| matcher.matches(&request.body));
+
+        query_matches && headers_match && body_matches
+    }
+}
+
+/// A canned response answered by a `MockTransport`.
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: StatusCode,
+    version: Option<Version>,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl MockResponse {
+    pub fn new(status: StatusCode) -> Self {
+        MockResponse {
+            status,
+            version: None,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    /// Adds a response header, invalid header names or values are skipped.
+    pub fn header<K, V>(mut self, name: K, value: V) -> Self
+        where
+            HeaderName: TryFrom<K>,
+            HeaderValue: TryFrom<V>,
+    {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            self.headers.append(name, value);
+        }
+        self
+    }
+
+    /// Sets the protocol version of the response, the version of the request by default.
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets `value` serialized as the JSON body, with an `application/json` content type.
+    pub fn json<T: Serialize>(mut self, value: &T) -> Result<Self, Error> {
+        let body = serde_json::to_vec(value).map_err(Error::SerdeSerializeError)?;
+        self.headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        self.body = Bytes::from(body);
+        Ok(self)
+    }
+
+    fn to_response(&self, request_version: Version) -> Response<Bytes> {
+        let mut response = Response::new(self.body.clone());
+        *response.status_mut() = self.status;
+        *response.version_mut() = self.version.unwrap_or(request_version);
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_string(), value.to_string())
+        })
+        .collect()
+}
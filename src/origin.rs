@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "http3")]
+use http::header::ALT_SVC;
+use http::{HeaderMap, Version};
+
+#[cfg(feature = "http3")]
+use crate::alt_svc::{self, AltSvc};
+use crate::key::PoolKey;
+
+// How long a negotiated protocol is trusted before the origin is probed again
+const TCP_PROTOCOL_TTL: Duration = Duration::from_secs(10 * 60);
+// Origins whose HTTP/3 endpoint could not be reached are served over TCP for this long
+#[cfg(feature = "http3")]
+const H3_BROKEN_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// Protocol an origin was found to speak over TCP, through ALPN or the h2c upgrade.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum TcpProtocol {
+    Http1,
+    Http2,
+}
+
+/// Alternative endpoint serving an origin over HTTP/3.
+#[cfg(feature = "http3")]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct AltEndpoint {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+#[derive(Default)]
+struct OriginRecord {
+    tcp: Option<(TcpProtocol, Instant)>,
+    #[cfg(feature = "http3")]
+    h3: Option<(AltEndpoint, Instant)>,
+    #[cfg(feature = "http3")]
+    h3_broken_until: Option<Instant>,
+}
+
+/// Per origin record of the protocols negotiated or advertised, with their expiry,
+/// so later requests to the same origin go to the right connector without probing again.
+///
+/// The TCP protocol steers only the hyper client, which otherwise offers the h2c upgrade on every
+/// request. The connector of the default client already reuses pooled HTTP/2 connections before
+/// negotiating with ALPN and never offers h2c, so it records the protocol for `known_protocol`
+/// and the HTTP/2 stream limit, and consults the memory only for the HTTP/3 endpoint.
+#[derive(Default)]
+pub(crate) struct ProtocolMemory {
+    origins: RefCell<HashMap<PoolKey, OriginRecord>>,
+}
+
+impl ProtocolMemory {
+    /// Returns the protocol known to be spoken by the origin over TCP.
+    pub(crate) fn tcp_protocol(&self, key: &PoolKey) -> Option<TcpProtocol> {
+        let origins = self.origins.borrow();
+        let (protocol, expires) = origins.get(key)?.tcp?;
+        (expires > Instant::now()).then_some(protocol)
+    }
+
+    pub(crate) fn record_tcp(&self, key: &PoolKey, protocol: TcpProtocol) {
+        let mut origins = self.origins.borrow_mut();
+        let record = origins.entry(key.clone()).or_default();
+        record.tcp = Some((protocol, Instant::now() + TCP_PROTOCOL_TTL));
+    }
+
+    /// Records the protocol of a response received over TCP.
+    pub(crate) fn record_version(&self, key: &PoolKey, version: Version) {
+        match version {
+            Version::HTTP_2 => self.record_tcp(key, TcpProtocol::Http2),
+            Version::HTTP_10 | Version::HTTP_11 => self.record_tcp(key, TcpProtocol::Http1),
+            _ => {}
+        }
+    }
+
+    /// Forgets the TCP protocol of the origin, after a failure on the remembered protocol.
+    #[cfg_attr(not(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls")), allow(dead_code))]
+    pub(crate) fn forget_tcp(&self, key: &PoolKey) {
+        if let Some(record) = self.origins.borrow_mut().get_mut(key) {
+            record.tcp = None;
+        }
+    }
+
+    /// Returns the protocol the next request to the origin is expected to use.
+    pub(crate) fn known_version(&self, key: &PoolKey) -> Option<Version> {
+        #[cfg(feature = "http3")]
+        if self.h3_endpoint(key).is_some() {
+            return Some(Version::HTTP_3);
+        }
+
+        self.tcp_protocol(key).map(|protocol| match protocol {
+            TcpProtocol::Http1 => Version::HTTP_11,
+            TcpProtocol::Http2 => Version::HTTP_2,
+        })
+    }
+
+    /// Records the HTTP/3 alternative advertised in the `Alt-Svc` header of a response from the origin.
+    #[cfg(feature = "http3")]
+    pub(crate) fn observe_alt_svc(&self, key: &PoolKey, headers: &HeaderMap) {
+        let Some(value) = headers.get(ALT_SVC).and_then(|v| v.to_str().ok()) else {
+            return;
+        };
+
+        let mut origins = self.origins.borrow_mut();
+        match alt_svc::parse(value) {
+            AltSvc::Clear => {
+                if let Some(record) = origins.get_mut(key) {
+                    record.h3 = None;
+                }
+            }
+            AltSvc::Services(services) => {
+                if let Some(service) = services.into_iter().find(|s| s.protocol == "h3") {
+                    let endpoint = AltEndpoint {
+                        host: service.host.unwrap_or_else(|| key.host.to_string()),
+                        port: service.port,
+                    };
+                    let record = origins.entry(key.clone()).or_default();
+                    record.h3 = Some((endpoint, Instant::now() + service.max_age));
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "http3"))]
+    pub(crate) fn observe_alt_svc(&self, _key: &PoolKey, _headers: &HeaderMap) {}
+
+    /// Returns the advertised HTTP/3 endpoint of the origin,
+    /// unless it expired or recently failed.
+    #[cfg(feature = "http3")]
+    pub(crate) fn h3_endpoint(&self, key: &PoolKey) -> Option<AltEndpoint> {
+        let origins = self.origins.borrow();
+        let record = origins.get(key)?;
+        let now = Instant::now();
+        if matches!(record.h3_broken_until, Some(until) if until > now) {
+            return None;
+        }
+
+        match record.h3.as_ref() {
+            Some((endpoint, expires)) if *expires > now => Some(endpoint.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether HTTP/3 to the origin failed recently.
+    #[cfg(feature = "http3")]
+    pub(crate) fn is_h3_broken(&self, key: &PoolKey) -> bool {
+        let origins = self.origins.borrow();
+        matches!(origins.get(key).and_then(|r| r.h3_broken_until), Some(until) if until > Instant::now())
+    }
+
+    /// Stops using HTTP/3 for the origin for a while.
+    #[cfg(feature = "http3")]
+    pub(crate) fn mark_h3_broken(&self, key: &PoolKey) {
+        let mut origins = self.origins.borrow_mut();
+        let record = origins.entry(key.clone()).or_default();
+        record.h3_broken_until = Some(Instant::now() + H3_BROKEN_DURATION);
+    }
+}
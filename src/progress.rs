@@ -0,0 +1,26 @@
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+/// Callback reporting the bytes transferred so far and the expected total when known.
+#[derive(Clone)]
+pub(crate) struct ProgressHook(Rc<dyn Fn(u64, Option<u64>)>);
+
+impl ProgressHook {
+    pub(crate) fn new<F>(callback: F) -> Self
+        where
+            F: Fn(u64, Option<u64>) + 'static,
+    {
+        ProgressHook(Rc::new(callback))
+    }
+
+    #[inline]
+    pub(crate) fn report(&self, transferred: u64, total: Option<u64>) {
+        (self.0)(transferred, total)
+    }
+}
+
+impl Debug for ProgressHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::rc::Rc;
+
+use http::{HeaderMap, HeaderName, HeaderValue};
+
+const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+const TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+const B3: HeaderName = HeaderName::from_static("b3");
+const X_B3_TRACE_ID: HeaderName = HeaderName::from_static("x-b3-traceid");
+const X_B3_SPAN_ID: HeaderName = HeaderName::from_static("x-b3-spanid");
+const X_B3_SAMPLED: HeaderName = HeaderName::from_static("x-b3-sampled");
+
+thread_local! {
+    static CURRENT: RefCell<Option<TraceContext>> = const { RefCell::new(None) };
+}
+
+/// Identifiers of the span a request is made under, propagated to the server.
+/// # Examples
+/// ```
+/// let context = TraceContext::new(0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7, true);
+/// let response = context.scope(client.make_request().set_uri(uri).send()).await?;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+    sampled: bool,
+    trace_state: Option<String>,
+}
+
+impl TraceContext {
+    pub fn new(trace_id: u128, span_id: u64, sampled: bool) -> Self {
+        TraceContext {
+            trace_id,
+            span_id,
+            sampled,
+            trace_state: None,
+        }
+    }
+
+    /// Sets the vendor specific `tracestate` sent along with a W3C `traceparent`.
+    pub fn with_trace_state(mut self, trace_state: impl Into<String>) -> Self {
+        self.trace_state = Some(trace_state.into());
+        self
+    }
+
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    pub fn sampled(&self) -> bool {
+        self.sampled
+    }
+
+    pub fn trace_state(&self) -> Option<&str> {
+        self.trace_state.as_deref()
+    }
+
+    /// Returns the context set by the enclosing `TraceContext::scope`, if any.
+    pub fn current() -> Option<TraceContext> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Polls `fut` with this context as the current one, requests built within it carry the context
+    /// on clients propagating the current context.
+    pub async fn scope<F: Future>(self, fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        poll_fn(|cx| {
+            let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+            let poll = fut.as_mut().poll(cx);
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+            poll
+        })
+        .await
+    }
+
+    /// All zero identifiers are invalid and never propagated.
+    fn is_valid(&self) -> bool {
+        self.trace_id != 0 && self.span_id != 0
+    }
+}
+
+/// Writes a trace context into the headers of an outbound request.
+pub trait Propagator {
+    fn inject(&self, context: &TraceContext, headers: &mut HeaderMap);
+}
+
+/// W3C Trace Context propagation, with the `traceparent` and `tracestate` headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct W3CTraceContext;
+
+impl Propagator for W3CTraceContext {
+    fn inject(&self, context: &TraceContext, headers: &mut HeaderMap) {
+        let traceparent = format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            context.trace_id,
+            context.span_id,
+            u8::from(context.sampled)
+        );
+        if let Ok(value) = HeaderValue::try_from(traceparent) {
+            headers.insert(TRACEPARENT, value);
+        }
+
+        let trace_state = context
+            .trace_state
+            .as_deref()
+            .and_then(|state| HeaderValue::try_from(state).ok());
+        match trace_state {
+            Some(value) => headers.insert(TRACESTATE, value),
+            None => headers.remove(TRACESTATE),
+        };
+    }
+}
+
+/// Zipkin B3 propagation, with the multiple `X-B3-*` headers by default
+/// or the single `b3` header.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct B3Propagator {
+    single_header: bool,
+}
+
+impl B3Propagator {
+    pub fn multi_header() -> Self {
+        B3Propagator { single_header: false }
+    }
+
+    pub fn single_header() -> Self {
+        B3Propagator { single_header: true }
+    }
+}
+
+impl Propagator for B3Propagator {
+    fn inject(&self, context: &TraceContext, headers: &mut HeaderMap) {
+        let trace_id = format!("{:032x}", context.trace_id);
+        let span_id = format!("{:016x}", context.span_id);
+        let sampled = if context.sampled { "1" } else { "0" };
+
+        if self.single_header {
+            if let Ok(value) = HeaderValue::try_from(format!("{trace_id}-{span_id}-{sampled}")) {
+                headers.insert(B3, value);
+            }
+            return;
+        }
+
+        for (name, value) in [(X_B3_TRACE_ID, trace_id), (X_B3_SPAN_ID, span_id)] {
+            if let Ok(value) = HeaderValue::try_from(value) {
+                headers.insert(name, value);
+            }
+        }
+        headers.insert(X_B3_SAMPLED, HeaderValue::from_static(sampled));
+    }
+}
+
+/// Propagation configured on a client, injecting the context given by `source`.
+#[derive(Clone)]
+pub(crate) struct Propagation {
+    propagator: Rc<dyn Propagator>,
+    source: Rc<dyn Fn() -> Option<TraceContext>>,
+}
+
+impl Propagation {
+    pub(crate) fn new(
+        propagator: impl Propagator + 'static,
+        source: impl Fn() -> Option<TraceContext> + 'static,
+    ) -> Self {
+        Propagation {
+            propagator: Rc::new(propagator),
+            source: Rc::new(source),
+        }
+    }
+
+    pub(crate) fn inject(&self, headers: &mut HeaderMap) {
+        if let Some(context) = (self.source)().filter(TraceContext::is_valid) {
+            self.propagator.inject(&context, headers);
+        }
+    }
+}
+
+impl Debug for Propagation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Propagation")
+    }
+}
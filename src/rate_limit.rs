@@ -0,0 +1,299 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use http::header::RETRY_AFTER;
+use http::{HeaderMap, HeaderName, StatusCode, Uri};
+
+use crate::key::PoolKey;
+
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+// Longest pause a response can impose, longer ones are most likely bogus
+const MAX_PAUSE: Duration = Duration::from_secs(60 * 60);
+
+/// Sustained rate of a token bucket, `permits` requests every `period`, with bursts of up to
+/// `permits` requests by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rate {
+    permits: u32,
+    period: Duration,
+    burst: u32,
+}
+
+impl Rate {
+    /// Allows `permits` requests every `period`, at least one.
+    pub fn new(permits: u32, period: Duration) -> Self {
+        let permits = permits.max(1);
+        Rate { permits, period, burst: permits }
+    }
+
+    pub fn per_second(permits: u32) -> Self {
+        Rate::new(permits, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(permits: u32) -> Self {
+        Rate::new(permits, Duration::from_secs(60))
+    }
+
+    /// Sets the number of requests sent at once after the bucket was idle, `1` spaces all requests evenly.
+    pub fn burst(mut self, val: u32) -> Self {
+        self.burst = val.max(1);
+        self
+    }
+
+    fn interval(&self) -> Duration {
+        self.period / self.permits
+    }
+
+    fn tolerance(&self) -> Duration {
+        self.interval() * (self.burst - 1)
+    }
+}
+
+/// Bucket requests are counted in, the origin of the request unless a custom key was returned for it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum LimitKey {
+    Origin(PoolKey),
+    Custom(String),
+}
+
+impl LimitKey {
+    fn name(&self) -> &str {
+        match self {
+            LimitKey::Origin(key) => key.host.as_str(),
+            LimitKey::Custom(name) => name,
+        }
+    }
+}
+
+/// Token buckets delaying requests so they stay under a global rate and a rate per host,
+/// requests are never rejected. Waiting requests are sent in the order they were made.
+/// A limiter can be shared by several clients by cloning it.
+/// Waiting relies on the monoio timer, which must be enabled on the runtime.
+/// # Examples
+/// ```
+/// let limiter = RateLimiter::new()
+///     .global(Rate::per_second(100))
+///     .per_host(Rate::per_second(10).burst(5))
+///     .host("api.example.com", Rate::per_minute(60))
+///     .adaptive();
+///
+/// let client = MonoioClient::builder().enable_https().rate_limiter(limiter).build();
+/// ```
+#[derive(Clone, Default)]
+pub struct RateLimiter {
+    state: Rc<RefCell<LimiterState>>,
+}
+
+type KeyFn = Rc<dyn Fn(&Uri, &HeaderMap) -> Option<String>>;
+
+#[derive(Default)]
+struct LimiterState {
+    global: Option<Rate>,
+    per_host: Option<Rate>,
+    hosts: HashMap<String, Rate>,
+    key: Option<KeyFn>,
+    adaptive: bool,
+    global_bucket: Bucket,
+    buckets: HashMap<LimitKey, Bucket>,
+}
+
+impl RateLimiter {
+    /// Returns a limiter without limits, set them with `global`, `per_host` and `host`.
+    pub fn new() -> Self {
+        RateLimiter::default()
+    }
+
+    /// Limits the requests sent through the limiter as a whole.
+    pub fn global(self, rate: Rate) -> Self {
+        self.state.borrow_mut().global = Some(rate);
+        self
+    }
+
+    /// Limits the requests sent to each host, or with each custom key.
+    pub fn per_host(self, rate: Rate) -> Self {
+        self.state.borrow_mut().per_host = Some(rate);
+        self
+    }
+
+    /// Limits the requests sent to `host`, or with the custom key `host`, instead of the `per_host` rate.
+    pub fn host(self, host: impl Into<String>, rate: Rate) -> Self {
+        self.state.borrow_mut().hosts.insert(host.into(), rate);
+        self
+    }
+
+    /// Counts requests in the bucket named by `key` instead of the bucket of their origin,
+    /// for quotas shared by several hosts or applying per API key. Requests `key` returns `None`
+    /// for are counted per origin.
+    /// # Examples
+    /// ```
+    /// let limiter = RateLimiter::new()
+    ///     .per_host(Rate::per_second(5))
+    ///     .key_by(|_, headers| headers.get("x-tenant")?.to_str().ok().map(String::from));
+    /// ```
+    pub fn key_by<F>(self, key: F) -> Self
+        where
+            F: Fn(&Uri, &HeaderMap) -> Option<String> + 'static,
+    {
+        self.state.borrow_mut().key = Some(Rc::new(key));
+        self
+    }
+
+    /// Pauses the bucket of a host when its responses say the quota is spent, until the time given
+    /// by `Retry-After` on `429 Too Many Requests` and `503 Service Unavailable` responses,
+    /// or by `RateLimit-Reset` once `RateLimit-Remaining` reaches 0. `Retry-After` is read as seconds
+    /// or an HTTP-date, `RateLimit-Reset` as seconds. Pauses are capped at one hour.
+    pub fn adaptive(self) -> Self {
+        self.state.borrow_mut().adaptive = true;
+        self
+    }
+
+    /// Waits until the request may be sent, returning the bucket to report its response to.
+    pub(crate) async fn acquire(&self, uri: &Uri, headers: &HeaderMap) -> Option<LimitKey> {
+        let (key, at) = self.reserve(uri, headers, Instant::now());
+        let wait = at.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            monoio::time::sleep(wait).await;
+        }
+        key
+    }
+
+    /// Takes a permit from the global bucket and the bucket of the request, returning when
+    /// both have one available. Permits are taken right away so later requests queue behind.
+    fn reserve(&self, uri: &Uri, headers: &HeaderMap, now: Instant) -> (Option<LimitKey>, Instant) {
+        let state = &mut *self.state.borrow_mut();
+        let custom = state.key.as_ref().and_then(|key| key(uri, headers));
+        let key = match custom {
+            Some(name) => Some(LimitKey::Custom(name)),
+            None => PoolKey::try_from(uri).ok().map(LimitKey::Origin),
+        };
+
+        let mut at = now;
+        if let Some(rate) = state.global {
+            at = at.max(state.global_bucket.reserve(Some(rate), now));
+        }
+        if let Some(key) = key.as_ref() {
+            let rate = state.hosts.get(key.name()).copied().or(state.per_host);
+            if rate.is_some() || state.adaptive {
+                let bucket = state.buckets.entry(key.clone()).or_default();
+                at = at.max(bucket.reserve(rate, now));
+            }
+        }
+        (key, at)
+    }
+
+    /// Pauses the bucket of the request when the response says its quota is spent.
+    pub(crate) fn observe(&self, key: Option<&LimitKey>, status: StatusCode, headers: &HeaderMap) {
+        let state = &mut *self.state.borrow_mut();
+        let Some(key) = key.filter(|_| state.adaptive) else {
+            return;
+        };
+        let Some(pause) = quota_pause(status, headers, SystemTime::now()) else {
+            return;
+        };
+
+        let until = Instant::now() + pause;
+        let bucket = state.buckets.entry(key.clone()).or_default();
+        bucket.paused_until = bucket.paused_until.max(Some(until));
+    }
+}
+
+/// How long the server asks to wait before the next request, if it does, at most `MAX_PAUSE`.
+fn quota_pause(status: StatusCode, headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = |name: &HeaderName| headers.get(name)?.to_str().ok().map(str::trim);
+    let seconds = |name: &HeaderName| value(name)?.parse::<u64>().ok().map(Duration::from_secs);
+
+    let pause = if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        // Retry-After is either a number of seconds or an HTTP-date
+        seconds(&RETRY_AFTER).or_else(|| {
+            let date = httpdate::parse_http_date(value(&RETRY_AFTER)?).ok()?;
+            Some(date.duration_since(now).unwrap_or_default())
+        })
+    } else {
+        None
+    };
+    let spent = || {
+        headers
+            .get(RATELIMIT_REMAINING)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim() == "0")
+    };
+
+    pause
+        .or_else(|| spent().then(|| seconds(&RATELIMIT_RESET)).flatten())
+        .map(|pause| pause.min(MAX_PAUSE))
+}
+
+/// Token bucket tracked as the theoretical arrival time of the next request (GCRA),
+/// the bucket is full once it lies `tolerance` in the past.
+struct Bucket {
+    next: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Bucket { next: Instant::now(), paused_until: None }
+    }
+}
+
+impl Bucket {
+    /// Returns when the next request may be sent, buckets without a rate are only paused.
+    fn reserve(&mut self, rate: Option<Rate>, now: Instant) -> Instant {
+        let earliest = self.paused_until.map_or(now, |until| until.max(now));
+        let Some(rate) = rate else {
+            return earliest;
+        };
+
+        let next = self.next.max(earliest);
+        let at = next.checked_sub(rate.tolerance()).map_or(earliest, |at| at.max(earliest));
+        self.next = next + rate.interval();
+        at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (HeaderName::from_static(name), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn retry_after_seconds_and_dates() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_445_412_480);
+        let limited = StatusCode::TOO_MANY_REQUESTS;
+
+        let pause = quota_pause(limited, &headers(&[("retry-after", "120")]), now);
+        assert_eq!(pause, Some(Duration::from_secs(120)));
+        let pause = quota_pause(limited, &headers(&[("retry-after", "Wed, 21 Oct 2015 07:30:00 GMT")]), now);
+        assert_eq!(pause, Some(Duration::from_secs(120)));
+        let pause = quota_pause(limited, &headers(&[("retry-after", "Wed, 21 Oct 2015 07:00:00 GMT")]), now);
+        assert_eq!(pause, Some(Duration::ZERO));
+
+        assert_eq!(quota_pause(StatusCode::OK, &headers(&[("retry-after", "120")]), now), None);
+        let spent = headers(&[("ratelimit-remaining", "0"), ("ratelimit-reset", "30")]);
+        assert_eq!(quota_pause(StatusCode::OK, &spent, now), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn huge_pauses_are_capped() {
+        let huge = headers(&[("retry-after", "18446744073709551615")]);
+        let pause = quota_pause(StatusCode::TOO_MANY_REQUESTS, &huge, SystemTime::now());
+        assert_eq!(pause, Some(MAX_PAUSE));
+
+        let limiter = RateLimiter::new().adaptive();
+        let uri = Uri::from_static("http://api.local/");
+        let (key, _) = limiter.reserve(&uri, &HeaderMap::new(), Instant::now());
+        limiter.observe(key.as_ref(), StatusCode::TOO_MANY_REQUESTS, &huge);
+
+        let (_, at) = limiter.reserve(&uri, &HeaderMap::new(), Instant::now());
+        assert!(at <= Instant::now() + MAX_PAUSE);
+    }
+}
@@ -1,21 +1,37 @@
 use std::any::Any;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
 
 use bytes::Bytes;
-use http::{HeaderName, HeaderValue, Method, Request, Uri, Version};
-use http::header::{CONNECTION, HOST, TE, TRANSFER_ENCODING, UPGRADE};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Uri, Version};
+use http::header::{AUTHORIZATION, CONNECTION, HOST, IF_RANGE, RANGE, TE, TRANSFER_ENCODING, UPGRADE};
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use http::header::TRAILER;
 use http::request::Builder;
 #[cfg(not(feature = "hyper-tls"))]
-use monoio_http::common::body::HttpBody;
+use monoio_http::{common::body::{FixedBody, HttpBody}, h1::payload::StreamPayload};
 
 #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
-use crate::hyper::client::MonoioHyperClient;
+use crate::hyper::client::{MonoioHyperClient, StreamingBody};
 #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
 use crate::hyper::hyper_body::HyperBody;
 #[cfg(not(feature = "hyper-tls"))]
-use super::http::{client::MonoioClient, monoio_body::MonoioBody};
-use super::{response::HttpResponse, error::Error};
+use super::http::client::MonoioClient;
+#[cfg(not(feature = "hyper-tls"))]
+use super::http::raw;
+use super::{
+    auth::{self, DigestCredentials, RequestAuth},
+    download::{self, DownloadOptions},
+    hedge::HedgePolicy,
+    response::{HttpResponse, ResponseLimits},
+    error::Error,
+    progress::ProgressHook,
+    sign::{RequestSigner, SignerHook},
+    propagation::Propagation,
+};
 
-const PROHIBITED_HEADERS: [HeaderName; 5] = [
+pub(crate) const PROHIBITED_HEADERS: [HeaderName; 5] = [
     CONNECTION,
     HeaderName::from_static("keep-alive"),
     TE,
@@ -29,16 +45,113 @@ pub trait RequestBody {
     fn create_body(bytes: Option<Bytes>) -> Self::Body;
 }
 
+/// Request body kept in memory, for transports that write it by hand or may have to resend it.
+pub(crate) struct BufferedBody;
+
+impl RequestBody for BufferedBody {
+    type Body = Option<Bytes>;
+
+    fn create_body(bytes: Option<Bytes>) -> Self::Body {
+        bytes
+    }
+}
+
+/// When to send a request body only after the server accepted the request head,
+/// with an `Expect: 100-continue` header.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "hyper-tls", allow(dead_code))]
+pub(crate) struct ExpectContinue {
+    pub(crate) enabled: bool,
+    /// Bodies of at least this many bytes are always sent after a `100 Continue`.
+    pub(crate) threshold: Option<usize>,
+    /// How long to wait for the server before sending the body anyway.
+    pub(crate) timeout: Duration,
+}
+
+impl Default for ExpectContinue {
+    fn default() -> Self {
+        ExpectContinue {
+            enabled: false,
+            threshold: None,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ExpectContinue {
+    /// Whether a body of `len` bytes waits for a `100 Continue`.
+    #[cfg_attr(feature = "hyper-tls", allow(dead_code))]
+    pub(crate) fn applies(&self, len: usize) -> bool {
+        len > 0 && (self.enabled || self.threshold.is_some_and(|threshold| len >= threshold))
+    }
+}
+
+/// Per request settings, initialised from the client configuration.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct RequestOptions {
+    /// Headers of the client, sent unless the request sets the same name.
+    pub(crate) default_headers: Rc<HeaderMap>,
+    pub(crate) limits: ResponseLimits,
+    pub(crate) upload_progress: Option<ProgressHook>,
+    pub(crate) download_progress: Option<ProgressHook>,
+    pub(crate) connect_target: Option<String>,
+    pub(crate) expect_continue: ExpectContinue,
+    pub(crate) trailers: HeaderMap,
+    pub(crate) accept_trailers: bool,
+    pub(crate) auth: RequestAuth,
+    pub(crate) signer: Option<SignerHook>,
+    pub(crate) propagation: Option<Propagation>,
+    pub(crate) hedge: Option<HedgePolicy>,
+    /// Body fed by the caller while a streamed request is in flight, sent by the default client
+    /// in place of the buffered body.
+    #[cfg(not(feature = "hyper-tls"))]
+    pub(crate) request_stream: Option<StreamPayload>,
+}
+
+impl RequestOptions {
+    /// Runs the signer, if any, over the final request and its payload.
+    pub(crate) fn sign<B>(&self, req: Request<B>, payload: Option<Bytes>) -> Result<Request<B>, Error> {
+        match self.signer.as_ref() {
+            Some(signer) => signer.sign(req, payload),
+            None => Ok(req),
+        }
+    }
+
+    /// Whether a body of `len` bytes is sent over a dedicated HTTP/1.1 connection by the default client,
+    /// to wait for `100 Continue`, to carry trailer fields or to report the upload progress
+    /// as the body is written, which the pooled connections can't do.
+    #[cfg(not(feature = "hyper-tls"))]
+    pub(crate) fn needs_raw_body(&self, len: usize) -> bool {
+        self.expect_continue.applies(len)
+            || !self.trailers.is_empty()
+            || self.accept_trailers
+            || (self.upload_progress.is_some() && len > 0)
+    }
+
+    /// Framing of a body of `len` bytes sent over a dedicated HTTP/1.1 connection.
+    #[cfg(not(feature = "hyper-tls"))]
+    pub(crate) fn raw_body(&self, len: usize) -> raw::BodyOptions {
+        raw::BodyOptions {
+            expect_continue: self.expect_continue.applies(len).then_some(self.expect_continue.timeout),
+            trailers: self.trailers.clone(),
+            accept_trailers: self.accept_trailers,
+            progress: self.upload_progress.clone(),
+        }
+    }
+}
+
 pub struct HttpRequest<C> {
     client: C,
     builder: Builder,
+    options: RequestOptions,
 }
 
 impl<C> HttpRequest<C> {
-    pub(crate) fn new(client: C) -> HttpRequest<C> {
+    pub(crate) fn new(client: C, options: RequestOptions) -> HttpRequest<C> {
         HttpRequest {
             client,
             builder: Builder::default(),
+            options,
         }
     }
 
@@ -58,6 +171,10 @@ impl<C> HttpRequest<C> {
         self
     }
 
+    pub(crate) fn uri(&self) -> Option<&Uri> {
+        self.builder.uri_ref()
+    }
+
     /// Sets the HTTP method for the request (GET, POST, PUT, etc.).
     /// Accepts any type that can be converted into a `Method`.
     /// # Examples
@@ -93,6 +210,56 @@ impl<C> HttpRequest<C> {
         self
     }
 
+    /// Sets `Authorization: Basic` credentials.
+    /// # Examples
+    /// ```
+    /// request.basic_auth("user", Some("password"));
+    /// ```
+    pub fn basic_auth(mut self, username: &str, password: Option<&str>) -> Self {
+        self.builder = self.builder.header(AUTHORIZATION, auth::basic(username, password));
+        self
+    }
+
+    /// Sets `Authorization: Bearer` credentials, replacing the token of the client's `TokenProvider`.
+    /// # Examples
+    /// ```
+    /// request.bearer_auth("eyJhbGciOiJIUzI1NiJ9...");
+    /// ```
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.builder = match auth::bearer(token) {
+            Ok(value) => self.builder.header(AUTHORIZATION, value),
+            // An invalid token fails the request when it is built
+            Err(_) => self.builder.header(AUTHORIZATION, format!("Bearer {token}")),
+        };
+        self
+    }
+
+    /// Answers a `401 Unauthorized` Digest challenge (RFC 7616) with these credentials,
+    /// the request is sent again once with the computed `Authorization` header.
+    /// MD5 and SHA-256, their session variants and the `auth` and `auth-int` qop are supported.
+    /// # Examples
+    /// ```
+    /// let response = request.digest_auth("user", "password").send().await?;
+    /// ```
+    pub fn digest_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.options.auth.digest = Some(DigestCredentials {
+            username: username.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Signs the request with `signer` instead of the signer of the client, once its headers are final.
+    /// # Examples
+    /// ```
+    /// let signer = SigV4Signer::new(access_key, secret_key, "eu-west-1", "execute-api");
+    /// let response = request.sign_with(signer).send_body(payload).await?;
+    /// ```
+    pub fn sign_with(mut self, signer: impl RequestSigner + 'static) -> Self {
+        self.options.signer = Some(SignerHook::new(signer));
+        self
+    }
+
     /// Sets the HTTP version for the request.
     /// Default version is HTTP/1.1 if not specified.
     /// # Examples
@@ -116,9 +283,157 @@ impl<C> HttpRequest<C> {
         self
     }
 
+    /// Sets the maximum size in bytes of the response body for this request,
+    /// overriding the limit configured on the client builder.
+    /// Reading a larger body fails with `Error::ResponseBodyTooLarge`.
+    /// # Examples
+    /// ```
+    /// request.set_max_response_body_size(10 * 1024 * 1024);
+    /// ```
+    pub fn set_max_response_body_size(mut self, val: usize) -> Self {
+        self.options.limits.max_body_size = Some(val);
+        self
+    }
+
+    /// Registers a callback reporting the request body bytes sent so far and the body size.
+    /// The Hyper client reports per body frame of 64 KiB over the pooled HTTP/1.1 and HTTP/2 connections.
+    /// The default client sends such request bodies over a dedicated HTTP/1.1 connection, and reports
+    /// every slice of 64 KiB once written. Its requests fail with `Error::HttpVersionMismatch` when
+    /// the client is built with `http2_prior_knowledge`.
+    /// # Examples
+    /// ```
+    /// request.on_upload_progress(|sent, total| println!("uploaded {sent}/{total:?}"));
+    /// ```
+    pub fn on_upload_progress<F>(mut self, callback: F) -> Self
+        where
+            F: Fn(u64, Option<u64>) + 'static,
+    {
+        self.options.upload_progress = Some(ProgressHook::new(callback));
+        self
+    }
+
+    /// Registers a callback reporting the response body bytes received so far
+    /// and the `Content-Length` of the response when known.
+    /// It is invoked by the response readers, including the download helpers.
+    /// # Examples
+    /// ```
+    /// request.on_download_progress(|received, total| println!("downloaded {received}/{total:?}"));
+    /// ```
+    pub fn on_download_progress<F>(mut self, callback: F) -> Self
+        where
+            F: Fn(u64, Option<u64>) + 'static,
+    {
+        self.options.download_progress = Some(ProgressHook::new(callback));
+        self
+    }
+
+    /// Sends a second copy of the request when the first hasn't answered within the delay of `policy`,
+    /// returning whichever response arrives first. Ignored for methods that aren't idempotent.
+    /// # Examples
+    /// ```
+    /// request.hedge(HedgePolicy::after(Duration::from_millis(20)));
+    /// ```
+    pub fn hedge(mut self, policy: HedgePolicy) -> Self {
+        self.options.hedge = Some(policy);
+        self
+    }
+
+    /// Turns the request into a `CONNECT` asking the server at the request URI,
+    /// usually a proxy, for a tunnel to `target` given as `host:port`.
+    /// Once the tunnel is established it is taken over with `HttpResponse::upgrade`.
+    /// Not available with crate feature hyper-tls, its connections can't be taken over.
+    /// # Examples
+    /// ```
+    /// let response = client
+    ///     .make_request()
+    ///     .set_uri("http://proxy.local:3128")
+    ///     .tunnel("example.com:443")
+    ///     .send()
+    ///     .await?;
+    /// ```
+    #[cfg(not(feature = "hyper-tls"))]
+    pub fn tunnel(mut self, target: impl Into<String>) -> Self {
+        self.options.connect_target = Some(target.into());
+        self.set_method(Method::CONNECT)
+    }
+
+    /// Sends the request head with `Expect: 100-continue` and holds the body back until the server
+    /// answers `100 Continue`, so a request rejected on its headers (authentication, size, ...)
+    /// does not upload the body for nothing. If the server stays silent for the timeout configured
+    /// on the client builder, one second by default, the body is sent anyway.
+    /// The request goes over a dedicated HTTP/1.1 connection. Has no effect without a body
+    /// and is ignored with crate feature hyper-tls.
+    /// # Examples
+    /// ```
+    /// let response = client
+    ///     .make_request()
+    ///     .set_method(Method::PUT)
+    ///     .set_uri("https://example.com/upload")
+    ///     .expect_continue()
+    ///     .send_body(payload)
+    ///     .await?;
+    /// ```
+    pub fn expect_continue(mut self) -> Self {
+        self.options.expect_continue.enabled = true;
+        self
+    }
+
+    /// Adds a trailer field sent after the request body, announced in the `Trailer` header.
+    /// The body is sent with chunked encoding over HTTP/1.1, the default client sends such requests
+    /// over a dedicated HTTP/1.1 connection.
+    /// # Examples
+    /// ```
+    /// let response = request
+    ///     .set_trailer(HeaderName::from_static("x-checksum"), HeaderValue::from_str(&digest)?)
+    ///     .send_body(payload)
+    ///     .await?;
+    /// ```
+    pub fn set_trailer(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.options.trailers.append(name, value);
+        self
+    }
+
+    /// Asks the server for trailer fields after the response body with `TE: trailers`,
+    /// they are read with `HttpResponse::trailers`. The pooled connections of the default client
+    /// drop trailers, so it sends such requests over a dedicated HTTP/1.1 connection.
+    /// The Hyper client receives trailers without asking.
+    pub fn accept_trailers(mut self) -> Self {
+        self.options.accept_trailers = true;
+        self
+    }
+
+    /// Whether the request asks for a protocol switch or a tunnel,
+    /// such requests are sent over a dedicated connection that can be taken over.
+    /// The `h2c` upgrade offered by the Hyper client in auto mode is handled by the pooled connectors.
+    fn is_upgrade(&self) -> bool {
+        self.builder.method_ref() == Some(&Method::CONNECT)
+            || self.builder.headers_ref().is_some_and(|h| {
+                h.get_all(UPGRADE)
+                    .iter()
+                    .any(|v| !v.as_bytes().eq_ignore_ascii_case(b"h2c"))
+            })
+    }
+
+    /// Adds a `Range` header continuing an interrupted download of `path`, if any,
+    /// with `If-Range` set to the validator of the response the download was started from.
+    /// Returns the request along with the requested range start.
+    async fn with_resume_range(self, path: &Path) -> (Self, u64) {
+        let len = download::partial_len(path).await;
+        if len == 0 {
+            return (self, 0);
+        }
+
+        let request = self.set_header(RANGE, format!("bytes={len}-"));
+        match download::partial_validator(path).await {
+            Some(validator) => (request.set_header(IF_RANGE, validator), len),
+            None => (request, len),
+        }
+    }
+
     fn build_request<B: RequestBody>(
         builder: Builder,
         body: Option<Bytes>,
+        options: &RequestOptions,
     ) -> Result<(Request<B::Body>, Uri), Error> {
         let mut request = builder
             .body(B::create_body(body))
@@ -126,6 +441,15 @@ impl<C> HttpRequest<C> {
 
         let uri = request.uri().clone();
 
+        let headers = request.headers_mut();
+        for name in options.default_headers.keys() {
+            if !headers.contains_key(name) {
+                for value in options.default_headers.get_all(name) {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+
         // Remove any connection specific headers to Http/2 requests
         // Avoid adding host header to Http/2 based requests but not Http/1.1
         // unless you are sending request to a proxy which downgrade the connection
@@ -168,9 +492,47 @@ impl HttpRequest<MonoioClient> {
     /// let response = request.send_body(None).await?; // No body
     /// ```
     pub async fn send_body(self, body: impl Into<Option<Bytes>>) -> Result<HttpResponse<HttpBody>, Error> {
-        let (req, uri) = Self::build_request::<MonoioBody>(self.builder, body.into())?;
-        let response = self.client.send_request(req, uri).await?;
-        Ok(HttpResponse::new(response))
+        let body = body.into();
+        if self.is_upgrade() {
+            let builder = self.builder.version(Version::HTTP_11);
+            let (req, uri) = Self::build_request::<BufferedBody>(builder, body, &self.options)?;
+            let raw = self.client.execute_upgrade(req, uri, &self.options).await?;
+            let body = HttpBody::fixed_body(Some(raw.body.clone()));
+            return Ok(HttpResponse::from_raw(raw, body, self.options.limits));
+        }
+
+        let mut builder = self.builder;
+        if self.options.needs_raw_body(body.as_ref().map_or(0, Bytes::len)) {
+            builder = builder.version(Version::HTTP_11);
+        }
+        let (req, uri) = Self::build_request::<BufferedBody>(builder, body, &self.options)?;
+
+        self.client.execute(req, uri, &self.options).await
+    }
+
+    /// Downloads the response body into the file at `path`, resuming an interrupted download.
+    /// If `<path>.part` exists a `Range` request is sent for the missing bytes, along with `If-Range`
+    /// holding the `ETag` or `Last-Modified` of the first response, kept in `<path>.part.validator`.
+    /// The download starts over when the server does not honour the range or the content changed.
+    /// A partial file that doesn't match the size reported by a `416 Range Not Satisfiable` is removed
+    /// and `Error::UnexpectedStatus` is returned.
+    /// Returns the size of the complete file.
+    /// # Examples
+    /// ```
+    /// let size = request.resume_download("artifact.tar.gz", DownloadOptions::new()).await?;
+    /// ```
+    pub async fn resume_download(self, path: impl AsRef<Path>, options: DownloadOptions) -> Result<u64, Error> {
+        let path = path.as_ref();
+        let (request, requested) = self.with_resume_range(path).await;
+        let response = request.send().await?;
+        response.write_to_file(path, requested, options).await
+    }
+
+    /// Sends the request without a body, bypassing the cache, the cassette and body limits.
+    /// The body is handed back unread, used by long lived streams.
+    pub(crate) async fn send_streaming(self) -> Result<http::Response<HttpBody>, Error> {
+        let (req, uri) = Self::build_request::<BufferedBody>(self.builder, None, &self.options)?;
+        self.client.execute_streaming(req, uri, &self.options).await
     }
 }
 
@@ -189,8 +551,64 @@ impl HttpRequest<MonoioHyperClient> {
     /// let response = request.send_body(None).await?; // No body
     /// ```
     pub async fn send_body(self, body: impl Into<Option<Bytes>>) -> Result<HttpResponse<Bytes>, Error> {
-        let (req, uri) = Self::build_request::<HyperBody>(self.builder, body.into())?;
-        let response = self.client.send_request(req, uri).await?;
-        HttpResponse::hyper_new(response).await
+        if self.is_upgrade() {
+            #[cfg(not(feature = "hyper-tls"))]
+            {
+                let builder = self.builder.version(Version::HTTP_11);
+                let (req, uri) = Self::build_request::<HyperBody>(builder, body.into(), &self.options)?;
+                let raw = self.client.execute_upgrade(req, uri, &self.options).await?;
+                let body = raw.body.clone();
+                return Ok(HttpResponse::from_raw(raw, body, self.options.limits));
+            }
+            #[cfg(feature = "hyper-tls")]
+            return Err(Error::UpgradeError("upgrades are not supported with hyper-tls".to_string()));
+        }
+
+        let body = body.into();
+        let mut builder = self.builder;
+        #[cfg(not(feature = "hyper-tls"))]
+        if self.options.expect_continue.applies(body.as_ref().map_or(0, |b| b.len())) {
+            builder = builder.version(Version::HTTP_11);
+        }
+        if let Some(headers) = builder.headers_mut() {
+            for name in self.options.trailers.keys() {
+                headers.append(TRAILER, HeaderValue::from(name.clone()));
+            }
+        }
+
+        let (mut req, uri) = Self::build_request::<HyperBody>(builder, body, &self.options)?;
+        if let Some(progress) = self.options.upload_progress.clone() {
+            req = req.map(|body| body.with_progress(progress));
+        }
+        if !self.options.trailers.is_empty() {
+            let trailers = self.options.trailers.clone();
+            req = req.map(|body| body.with_trailers(trailers));
+        }
+
+        self.client.execute(req, uri, &self.options).await
+    }
+
+    /// Downloads the response body into the file at `path`, resuming an interrupted download.
+    /// If `<path>.part` exists a `Range` request is sent for the missing bytes, along with `If-Range`
+    /// holding the `ETag` or `Last-Modified` of the first response, kept in `<path>.part.validator`.
+    /// The download starts over when the server does not honour the range or the content changed.
+    /// A partial file that doesn't match the size reported by a `416 Range Not Satisfiable` is removed
+    /// and `Error::UnexpectedStatus` is returned.
+    /// The body is streamed to disk frame by frame, the cache and the cassette don't apply.
+    /// Returns the size of the complete file.
+    pub async fn resume_download(self, path: impl AsRef<Path>, options: DownloadOptions) -> Result<u64, Error> {
+        let path = path.as_ref();
+        let (request, requested) = self.with_resume_range(path).await;
+        let (limits, progress) = (request.options.limits, request.options.download_progress.clone());
+        let (parts, body) = request.send_streaming().await?.into_parts();
+        let response = HttpResponse::from_parts(parts, body, limits, progress);
+        response.write_to_file(path, requested, options).await
+    }
+
+    /// Sends the request without a body, bypassing the cache, the cassette and body limits.
+    /// The body is handed back unread, used by long lived streams and downloads.
+    pub(crate) async fn send_streaming(self) -> Result<http::Response<StreamingBody>, Error> {
+        let (req, uri) = Self::build_request::<HyperBody>(self.builder, None, &self.options)?;
+        self.client.execute_streaming(req, uri, &self.options).await
     }
 }
@@ -1,19 +1,174 @@
-use bytes::Bytes;
+use std::path::Path;
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use std::time::Instant;
+
+use bytes::{Bytes, BytesMut};
+use encoding_rs::{Encoding, UTF_8};
 use http::{Extensions, HeaderMap, HeaderValue, StatusCode, Version};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
 #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
 use http_body_util::BodyExt as HyperBodyExt;
 #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
 use hyper::body::Incoming;
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use super::hyper::client::StreamingBody;
 #[cfg(not(feature = "hyper-tls"))]
 use monoio_http::{
-    common::body::{BodyExt, HttpBody},
+    common::body::{Body, FixedBody, HttpBody},
     h1::payload::Payload,
 };
+#[cfg(not(feature = "hyper-tls"))]
+use super::http::{raw::RawResponse, upgrade::Upgraded};
+use super::download::{self, DownloadOptions, FileSink};
 use super::error::Error;
+use super::progress::ProgressHook;
+use super::trace::{self, RequestSpan, Span};
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use super::timings::RequestTimings;
 
 #[cfg(not(feature = "hyper-tls"))]
 pub type Response<P = Payload> = http::response::Response<P>;
 
+// Header fields accepted by the HTTP/1.1 parsers without a configured limit
+const DEFAULT_MAX_HEADERS: usize = 100;
+// Largest HTTP/1.1 response head read without a configured limit
+const DEFAULT_MAX_HEAD_SIZE: usize = 64 * 1024;
+// Room for the status line on top of the header size limit
+const STATUS_LINE_SIZE: usize = 1024;
+
+/// Limits applied while receiving a response.
+/// Unset limits are not enforced.
+#[derive(Default, Clone, Copy, Debug)]
+pub(crate) struct ResponseLimits {
+    pub(crate) max_body_size: Option<usize>,
+    pub(crate) max_headers: Option<usize>,
+    pub(crate) max_header_size: Option<usize>,
+}
+
+impl ResponseLimits {
+    /// Number of header fields the HTTP/1.1 parsers accept.
+    pub(crate) fn parser_max_headers(&self) -> usize {
+        self.max_headers.unwrap_or(DEFAULT_MAX_HEADERS)
+    }
+
+    /// Number of bytes an HTTP/1.1 response head may take before parsing is given up.
+    pub(crate) fn parser_max_head_size(&self) -> usize {
+        self.max_header_size
+            .map_or(DEFAULT_MAX_HEAD_SIZE, |max| max.saturating_add(STATUS_LINE_SIZE))
+    }
+
+    /// `SETTINGS_MAX_HEADER_LIST_SIZE` advertised over HTTP/2. The setting counts 32 bytes of overhead
+    /// per field where `max_header_size` counts 4, the difference is allowed for every accepted field.
+    pub(crate) fn h2_max_header_list_size(&self) -> Option<u32> {
+        self.max_header_size.map(|max| {
+            let size = max.saturating_add(28 * self.parser_max_headers());
+            u32::try_from(size).unwrap_or(u32::MAX)
+        })
+    }
+
+    /// Validates the received headers against the header count and size limits,
+    /// and rejects bodies whose advertised `Content-Length` already exceeds the body limit.
+    /// The parsers are bounded by the same limits, this check makes them exact.
+    pub(crate) fn check_headers(&self, headers: &HeaderMap) -> Result<(), Error> {
+        if let Some(max) = self.max_headers {
+            if headers.len() > max {
+                return Err(Error::ResponseHeadersTooLarge(format!(
+                    "{} headers received, limit is {max}",
+                    headers.len()
+                )));
+            }
+        }
+
+        if let Some(max) = self.max_header_size {
+            // Approximates the wire size as `name: value\r\n` per header
+            let size: usize = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len() + 4)
+                .sum();
+            if size > max {
+                return Err(Error::ResponseHeadersTooLarge(format!(
+                    "{size} bytes of headers received, limit is {max}"
+                )));
+            }
+        }
+
+        if let Some(max) = self.max_body_size {
+            if matches!(content_length(headers), Some(len) if len > max as u64) {
+                return Err(Error::ResponseBodyTooLarge(max));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+/// Accumulates body chunks in memory, enforcing the body limit and reporting download progress.
+struct BodyCollector {
+    buf: BytesMut,
+    limit: Option<usize>,
+    total: Option<u64>,
+    progress: Option<ProgressHook>,
+}
+
+impl BodyCollector {
+    fn new(headers: &HeaderMap, limits: &ResponseLimits, progress: Option<ProgressHook>) -> Self {
+        BodyCollector {
+            buf: BytesMut::new(),
+            limit: limits.max_body_size,
+            total: content_length(headers),
+            progress,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        if let Some(max) = self.limit {
+            if self.buf.len() + chunk.len() > max {
+                return Err(Error::ResponseBodyTooLarge(max));
+            }
+        }
+
+        self.buf.extend_from_slice(chunk);
+        if let Some(progress) = self.progress.as_ref() {
+            progress.report(self.buf.len() as u64, self.total);
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Bytes {
+        self.buf.freeze()
+    }
+}
+
+/// Trailer fields received after a response body, carried in the response extensions
+/// by the transports that read the body themselves.
+#[derive(Clone, Debug)]
+pub(crate) struct Trailers(pub(crate) HeaderMap);
+
+/// Status and headers of the responses going through the client pipelines,
+/// whether their body is buffered or streamed.
+pub(crate) trait ResponseHead {
+    fn status(&self) -> StatusCode;
+    fn headers(&self) -> &HeaderMap;
+}
+
+impl<B> ResponseHead for http::Response<B> {
+    fn status(&self) -> StatusCode {
+        http::Response::status(self)
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        http::Response::headers(self)
+    }
+}
+
 #[derive(Debug)]
 pub struct HttpResponse<B> {
     status: StatusCode,
@@ -21,9 +176,55 @@ pub struct HttpResponse<B> {
     headers: HeaderMap<HeaderValue>,
     extensions: Extensions,
     body: B,
+    limits: ResponseLimits,
+    download_progress: Option<ProgressHook>,
+    trailers: Option<HeaderMap>,
+    span: Option<Span>,
+    #[cfg(not(feature = "hyper-tls"))]
+    upgraded: Option<Upgraded>,
+}
+
+impl<B> ResponseHead for HttpResponse<B> {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
 }
 
 impl<B> HttpResponse<B> {
+    pub(crate) fn from_parts(
+        mut parts: http::response::Parts,
+        body: B,
+        limits: ResponseLimits,
+        download_progress: Option<ProgressHook>,
+    ) -> Self {
+        let trailers = parts.extensions.remove::<Trailers>().map(|t| t.0);
+        let span = parts.extensions.remove::<RequestSpan>().map(|s| s.0);
+        HttpResponse {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            extensions: parts.extensions,
+            body,
+            limits,
+            download_progress,
+            trailers,
+            span,
+            #[cfg(not(feature = "hyper-tls"))]
+            upgraded: None,
+        }
+    }
+
+    #[cfg(not(feature = "hyper-tls"))]
+    pub(crate) fn from_raw(raw: RawResponse, body: B, limits: ResponseLimits) -> Self {
+        let mut response = Self::from_parts(raw.parts, body, limits, None);
+        response.upgraded = raw.upgraded;
+        response
+    }
+
     pub fn status(&self) -> StatusCode {
         self.status
     }
@@ -43,59 +244,292 @@ impl<B> HttpResponse<B> {
     pub fn raw_body(self) -> B {
         self.body
     }
+
+    #[cfg(all(feature = "vcr", any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls")))]
+    pub(crate) fn body(&self) -> &B {
+        &self.body
+    }
+
+    /// Splits the response back into parts, the trailers and the span going back to the extensions
+    /// so `from_parts` picks them up again.
+    #[cfg(all(feature = "cache", any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls")))]
+    pub(crate) fn into_parts(self) -> (http::response::Parts, B) {
+        let (mut parts, ()) = http::Response::new(()).into_parts();
+        parts.status = self.status;
+        parts.version = self.version;
+        parts.headers = self.headers;
+        parts.extensions = self.extensions;
+        if let Some(trailers) = self.trailers {
+            parts.extensions.insert(Trailers(trailers));
+        }
+        if let Some(span) = self.span {
+            parts.extensions.insert(RequestSpan(span));
+        }
+        (parts, self.body)
+    }
+
+    /// Takes over the connection after a `101 Switching Protocols` response or a successful `CONNECT`.
+    /// Only requests carrying an `Upgrade` header or using the `CONNECT` method can be upgraded,
+    /// they are sent over a dedicated HTTP/1.1 connection instead of the pool.
+    /// # Examples
+    /// ```
+    /// let response = client
+    ///     .make_request()
+    ///     .set_uri("http://proxy.local:3128")
+    ///     .tunnel("example.com:443")
+    ///     .send()
+    ///     .await?;
+    /// let mut stream = response.upgrade()?;
+    /// ```
+    #[cfg(not(feature = "hyper-tls"))]
+    pub fn upgrade(self) -> Result<Upgraded, Error> {
+        let status = self.status;
+        self.upgraded
+            .ok_or_else(|| Error::UpgradeError(format!("connection was not upgraded, status {status}")))
+    }
+
+    /// Opens the file sink for a download.
+    /// `requested` is the range start sent with the request, zero for a plain download.
+    async fn open_download(
+        &self,
+        path: &Path,
+        requested: u64,
+        options: DownloadOptions,
+    ) -> Result<FileSink, Error> {
+        let offset = if requested == 0 {
+            if !self.status.is_success() {
+                return Err(Error::UnexpectedStatus(self.status));
+            }
+            Some(0)
+        } else {
+            match download::resume_offset(self.status, &self.headers, requested) {
+                Err(e) if self.status == StatusCode::RANGE_NOT_SATISFIABLE => {
+                    // The partial file does not match the content, the next attempt starts over
+                    download::discard_partial(path).await;
+                    return Err(e);
+                }
+                result => result?,
+            }
+        };
+
+        // An unsatisfiable range means the partial file is already complete
+        let (offset, total) = match offset {
+            Some(offset) => (offset, download::expected_total(&self.headers, offset)),
+            None => (requested, Some(requested)),
+        };
+
+        let sink = FileSink::create(path, offset, total, &self.headers, options).await?;
+        Ok(sink.report_to(self.download_progress.clone()))
+    }
+}
+
+/// Resolves the encoding used to decode a body as text.
+/// The `charset` parameter of the `Content-Type` header takes precedence,
+/// falling back to `default` and finally to UTF-8 for unknown labels.
+fn text_encoding(headers: &HeaderMap, default: &str) -> &'static Encoding {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(charset_from_content_type)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .or_else(|| Encoding::for_label(default.as_bytes()))
+        .unwrap_or(UTF_8)
+}
+
+/// Extracts the `charset` parameter from a `Content-Type` header value.
+fn charset_from_content_type(content_type: &str) -> Option<&str> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+/// Decodes the body bytes, a byte order mark overrides the resolved encoding.
+/// Malformed sequences are replaced with the REPLACEMENT CHARACTER.
+fn decode_text(bytes: &[u8], encoding: &'static Encoding) -> String {
+    let (text, _, _) = encoding.decode(bytes);
+    text.into_owned()
 }
 
 #[cfg(not(feature = "hyper-tls"))]
 impl HttpResponse<HttpBody> {
-    pub(crate) fn new(response: Response<HttpBody>) -> Self {
+    pub(crate) fn new(
+        response: Response<HttpBody>,
+        limits: ResponseLimits,
+        download_progress: Option<ProgressHook>,
+    ) -> Result<Self, Error> {
         let (parts, body) = response.into_parts();
+        limits.check_headers(&parts.headers)?;
 
-        HttpResponse {
-            status: parts.status,
-            version: parts.version,
-            headers: parts.headers,
-            extensions: parts.extensions,
-            body,
-        }
+        Ok(HttpResponse::from_parts(parts, body, limits, download_progress))
     }
 
+    /// Reads the full body into memory.
+    /// Fails with `Error::ResponseBodyTooLarge` as soon as the configured body limit is crossed.
     pub async fn bytes(self) -> Result<Bytes, Error> {
-        let body = self.body;
-        body
-            .bytes()
-            .await
-            .map_err(|e| Error::BytesError(e.to_string()))
+        let mut collector = BodyCollector::new(&self.headers, &self.limits, self.download_progress);
+        let mut body = self.body;
+
+        let read = async {
+            while let Some(chunk) = body.next_data().await {
+                let chunk = chunk.map_err(|e| Error::BytesError(e.to_string()))?;
+                collector.push(&chunk)?;
+            }
+            Ok::<_, Error>(())
+        };
+        trace::body_read(self.span.as_ref(), read).await?;
+
+        Ok(collector.finish())
+    }
+
+    /// Returns the trailer fields sent after the body, empty if there were none.
+    /// The body is read into memory first if needed, it remains available to the other readers.
+    /// The pooled connections of this client don't surface trailers, they are received
+    /// over HTTP/3 and for requests sent with `HttpRequest::accept_trailers` or `HttpRequest::set_trailer`.
+    /// # Examples
+    /// ```
+    /// let mut response = request.accept_trailers().send().await?;
+    /// let status = response.trailers().await?.get("grpc-status").cloned();
+    /// let body = response.bytes().await?;
+    /// ```
+    pub async fn trailers(&mut self) -> Result<&HeaderMap, Error> {
+        if self.trailers.is_none() {
+            let mut collector = BodyCollector::new(&self.headers, &self.limits, None);
+            let body = &mut self.body;
+            let read = async {
+                while let Some(chunk) = body.next_data().await {
+                    let chunk = chunk.map_err(|e| Error::BytesError(e.to_string()))?;
+                    collector.push(&chunk)?;
+                }
+                Ok::<_, Error>(())
+            };
+            trace::body_read(self.span.as_ref(), read).await?;
+            self.body = HttpBody::fixed_body(Some(collector.finish()));
+        }
+
+        Ok(self.trailers.get_or_insert_with(HeaderMap::new))
     }
 
     pub async fn json<T: serde::de::DeserializeOwned>(self) -> Result<T, Error> {
-        let bytes = self
-            .body
-            .bytes()
-            .await
-            .map_err(|e| Error::BytesError(e.to_string()))?;
+        let bytes = self.bytes().await?;
         let d = serde_json::from_slice(&bytes).map_err(|e| Error::SerdeDeserializeError(e))?;
 
         Ok(d)
     }
+
+    /// Streams the body into the file at `path` without buffering it in memory.
+    /// The body is written to `<path>.part` and renamed to `path` once complete.
+    /// Returns the number of bytes written.
+    /// # Examples
+    /// ```
+    /// let size = response.save_to("artifact.tar.gz").await?;
+    /// ```
+    pub async fn save_to(self, path: impl AsRef<Path>) -> Result<u64, Error> {
+        self.save_to_with(path, DownloadOptions::default()).await
+    }
+
+    /// Streams the body into the file at `path` with progress reporting and checksum verification.
+    /// # Examples
+    /// ```
+    /// let options = DownloadOptions::new()
+    ///     .expect_sha256("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+    ///     .on_progress(|done, total| println!("{done}/{total:?}"));
+    /// response.save_to_with("artifact.tar.gz", options).await?;
+    /// ```
+    pub async fn save_to_with(self, path: impl AsRef<Path>, options: DownloadOptions) -> Result<u64, Error> {
+        self.write_to_file(path.as_ref(), 0, options).await
+    }
+
+    pub(crate) async fn write_to_file(
+        self,
+        path: &Path,
+        requested: u64,
+        options: DownloadOptions,
+    ) -> Result<u64, Error> {
+        let mut sink = self.open_download(path, requested, options).await?;
+        if self.status != StatusCode::RANGE_NOT_SATISFIABLE {
+            let mut body = self.body;
+            let read = async {
+                while let Some(chunk) = body.next_data().await {
+                    let chunk = chunk.map_err(|e| Error::BytesError(e.to_string()))?;
+                    sink.write(chunk).await?;
+                }
+                Ok::<_, Error>(())
+            };
+            trace::body_read(self.span.as_ref(), read).await?;
+        }
+
+        sink.finish().await
+    }
+
+    /// Reads the full body and decodes it as text.
+    /// The charset is taken from the `Content-Type` header or a byte order mark, defaulting to UTF-8.
+    pub async fn text(self) -> Result<String, Error> {
+        self.text_with_charset("utf-8").await
+    }
+
+    /// Reads the full body and decodes it as text, using `default` as the charset
+    /// when neither the `Content-Type` header nor a byte order mark specify one.
+    /// # Examples
+    /// ```
+    /// let text = response.text_with_charset("windows-1252").await?;
+    /// ```
+    pub async fn text_with_charset(self, default: &str) -> Result<String, Error> {
+        let encoding = text_encoding(&self.headers, default);
+        let bytes = self.bytes().await?;
+
+        Ok(decode_text(&bytes, encoding))
+    }
 }
 
 #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
 impl HttpResponse<Bytes> {
-    pub(crate) async fn hyper_new(response: http::Response<Incoming>) -> Result<Self, Error> {
-        let (parts, byte_stream) = response.into_parts();
-        let body = byte_stream
-            .collect()
-            .await
-            .map_err(|e| Error::BytesError(e.to_string()))?
-            .to_bytes();
-
-        Ok(HttpResponse {
-            status: parts.status,
-            version: parts.version,
-            headers: parts.headers,
-            extensions: parts.extensions,
-            body,
-        })
+    pub(crate) async fn hyper_new(
+        response: http::Response<Incoming>,
+        limits: ResponseLimits,
+        download_progress: Option<ProgressHook>,
+    ) -> Result<Self, Error> {
+        let (mut parts, mut byte_stream) = response.into_parts();
+        limits.check_headers(&parts.headers)?;
+
+        let mut collector = BodyCollector::new(&parts.headers, &limits, download_progress);
+        let mut trailers = HeaderMap::new();
+        let read = async {
+            while let Some(frame) = byte_stream.frame().await {
+                let frame = frame.map_err(|e| Error::BytesError(e.to_string()))?;
+                match frame.into_trailers() {
+                    Ok(fields) => trailers.extend(fields),
+                    Err(frame) => {
+                        if let Some(chunk) = frame.data_ref() {
+                            collector.push(chunk)?;
+                        }
+                    }
+                }
+            }
+            Ok::<_, Error>(())
+        };
+        let started = Instant::now();
+        let span = parts.extensions.get::<RequestSpan>().map(|s| &s.0);
+        trace::body_read(span, read).await?;
+        if let Some(timings) = parts.extensions.get_mut::<RequestTimings>() {
+            timings.total += started.elapsed();
+        }
+
+        let mut response = HttpResponse::from_parts(parts, collector.finish(), limits, None);
+        response.trailers = Some(trailers);
+        Ok(response)
+    }
+
+    /// Returns the trailer fields sent after the body, empty if there were none.
+    /// # Examples
+    /// ```
+    /// let mut response = request.send().await?;
+    /// let status = response.trailers().await?.get("grpc-status").cloned();
+    /// ```
+    pub async fn trailers(&mut self) -> Result<&HeaderMap, Error> {
+        Ok(self.trailers.get_or_insert_with(HeaderMap::new))
     }
 
     pub async fn json<T: serde::de::DeserializeOwned>(self) -> Result<T, Error> {
@@ -103,4 +537,125 @@ impl HttpResponse<Bytes> {
 
         Ok(d)
     }
+
+    /// Writes the body into the file at `path`.
+    /// The body was read in memory by `send`, large files are better fetched with
+    /// `HttpRequest::resume_download` which streams them to disk.
+    /// The body is written to `<path>.part` and renamed to `path` once complete.
+    /// Returns the number of bytes written.
+    pub async fn save_to(self, path: impl AsRef<Path>) -> Result<u64, Error> {
+        self.save_to_with(path, DownloadOptions::default()).await
+    }
+
+    /// Writes the body into the file at `path` with progress reporting and checksum verification.
+    /// Like `save_to` it writes the body already held in memory.
+    pub async fn save_to_with(self, path: impl AsRef<Path>, options: DownloadOptions) -> Result<u64, Error> {
+        self.write_to_file(path.as_ref(), 0, options).await
+    }
+
+    pub(crate) async fn write_to_file(
+        self,
+        path: &Path,
+        requested: u64,
+        options: DownloadOptions,
+    ) -> Result<u64, Error> {
+        let mut sink = self.open_download(path, requested, options).await?;
+        if self.status != StatusCode::RANGE_NOT_SATISFIABLE && !self.body.is_empty() {
+            sink.write(self.body).await?;
+        }
+
+        sink.finish().await
+    }
+
+    /// Decodes the body as text.
+    /// The charset is taken from the `Content-Type` header or a byte order mark, defaulting to UTF-8.
+    pub async fn text(self) -> Result<String, Error> {
+        self.text_with_charset("utf-8").await
+    }
+
+    /// Decodes the body as text, using `default` as the charset
+    /// when neither the `Content-Type` header nor a byte order mark specify one.
+    pub async fn text_with_charset(self, default: &str) -> Result<String, Error> {
+        let encoding = text_encoding(&self.headers, default);
+
+        Ok(decode_text(&self.body, encoding))
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+impl HttpResponse<StreamingBody> {
+    /// Streams the body frames into the file at `path` as they are received.
+    pub(crate) async fn write_to_file(
+        self,
+        path: &Path,
+        requested: u64,
+        options: DownloadOptions,
+    ) -> Result<u64, Error> {
+        let mut sink = self.open_download(path, requested, options).await?;
+        if self.status != StatusCode::RANGE_NOT_SATISFIABLE {
+            let mut body = self.body;
+            let read = async {
+                while let Some(frame) = body.frame().await {
+                    let frame = frame.map_err(|e| Error::BytesError(e.to_string()))?;
+                    if let Ok(chunk) = frame.into_data() {
+                        sink.write(chunk).await?;
+                    }
+                }
+                Ok::<_, Error>(())
+            };
+            trace::body_read(self.span.as_ref(), read).await?;
+        }
+
+        sink.finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{UTF_16LE, WINDOWS_1252};
+
+    use super::*;
+
+    fn content_type(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn charset_parameter_is_extracted() {
+        assert_eq!(charset_from_content_type("text/html; charset=ISO-8859-1"), Some("ISO-8859-1"));
+        assert_eq!(charset_from_content_type("text/html;charset=\"utf-8\""), Some("utf-8"));
+        assert_eq!(charset_from_content_type("text/plain; format=flowed; Charset = koi8-r"), Some("koi8-r"));
+        assert_eq!(charset_from_content_type("text/plain"), None);
+        assert_eq!(charset_from_content_type("text/plain; format=flowed"), None);
+    }
+
+    #[test]
+    fn header_charset_takes_precedence_over_default() {
+        let headers = content_type("text/plain; charset=windows-1252");
+        assert_eq!(text_encoding(&headers, "utf-8"), WINDOWS_1252);
+        assert_eq!(text_encoding(&content_type("text/plain"), "latin1"), WINDOWS_1252);
+        assert_eq!(text_encoding(&HeaderMap::new(), "utf-8"), UTF_8);
+    }
+
+    #[test]
+    fn unknown_labels_fall_back() {
+        let headers = content_type("text/plain; charset=x-unknown");
+        assert_eq!(text_encoding(&headers, "windows-1252"), WINDOWS_1252);
+        assert_eq!(text_encoding(&headers, "also-unknown"), UTF_8);
+    }
+
+    #[test]
+    fn byte_order_mark_overrides_the_encoding() {
+        assert_eq!(decode_text(b"\xEF\xBB\xBFcaf\xC3\xA9", WINDOWS_1252), "café");
+        assert_eq!(decode_text(b"\xFF\xFEh\x00i\x00", UTF_8), "hi");
+        assert_eq!(decode_text(b"caf\xE9", WINDOWS_1252), "café");
+        assert_eq!(decode_text(b"h\x00i\x00", UTF_16LE), "hi");
+    }
+
+    #[test]
+    fn malformed_sequences_are_replaced() {
+        assert_eq!(decode_text(b"a\xFFb", UTF_8), "a\u{FFFD}b");
+    }
 }
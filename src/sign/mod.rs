@@ -0,0 +1,61 @@
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use bytes::Bytes;
+use http::Request;
+
+use crate::error::Error;
+
+#[cfg(feature = "sigv4")]
+pub use self::sigv4::SigV4Signer;
+
+#[cfg(feature = "sigv4")]
+mod sigv4;
+
+/// Hook signing requests once their headers are final, including the computed `Host`,
+/// right before they are sent. Requests retried after an authentication challenge are signed again.
+/// Closures taking the request parts and the body are signers.
+/// # Examples
+/// ```
+/// let client = MonoioClient::builder()
+///     .signer(|parts: &mut http::request::Parts, body: &[u8]| {
+///         let signature = hmac_sha256(SECRET, body);
+///         parts.headers.insert("x-signature", HeaderValue::try_from(signature)?);
+///         Ok(())
+///     })
+///     .build();
+/// ```
+pub trait RequestSigner {
+    fn sign(&self, parts: &mut http::request::Parts, body: &[u8]) -> Result<(), Error>;
+}
+
+impl<F> RequestSigner for F
+    where
+        F: Fn(&mut http::request::Parts, &[u8]) -> Result<(), Error>,
+{
+    fn sign(&self, parts: &mut http::request::Parts, body: &[u8]) -> Result<(), Error> {
+        self(parts, body)
+    }
+}
+
+/// Signer configured on a client or a request.
+#[derive(Clone)]
+pub(crate) struct SignerHook(Rc<dyn RequestSigner>);
+
+impl SignerHook {
+    pub(crate) fn new(signer: impl RequestSigner + 'static) -> Self {
+        SignerHook(Rc::new(signer))
+    }
+
+    pub(crate) fn sign<B>(&self, req: Request<B>, payload: Option<Bytes>) -> Result<Request<B>, Error> {
+        let (mut parts, body) = req.into_parts();
+        self.0.sign(&mut parts, payload.as_deref().unwrap_or_default())?;
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+impl Debug for SignerHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SignerHook")
+    }
+}
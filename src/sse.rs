@@ -0,0 +1,477 @@
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE};
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode, Uri};
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use http_body_util::BodyExt as HyperBodyExt;
+#[cfg(not(feature = "hyper-tls"))]
+use monoio_http::common::body::{Body, HttpBody};
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+use crate::hyper::client::{MonoioHyperClient, StreamingBody};
+#[cfg(not(feature = "hyper-tls"))]
+use crate::http::client::MonoioClient;
+use crate::error::Error;
+
+const LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+const DEFAULT_MAX_EVENT_SIZE: usize = 1024 * 1024;
+
+/// A server-sent event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Event type, `message` unless set by the `event` field.
+    pub event: String,
+    pub data: String,
+    /// Last event id seen on the stream when the event was dispatched.
+    pub id: Option<String>,
+}
+
+enum SseBody {
+    #[cfg(not(feature = "hyper-tls"))]
+    Monoio(HttpBody),
+    #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+    Hyper(StreamingBody),
+}
+
+impl SseBody {
+    async fn next_chunk(&mut self) -> Option<Result<Bytes, Error>> {
+        match self {
+            #[cfg(not(feature = "hyper-tls"))]
+            SseBody::Monoio(body) => body
+                .next_data()
+                .await
+                .map(|chunk| chunk.map_err(|e| Error::BytesError(e.to_string()))),
+            #[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+            SseBody::Hyper(body) => loop {
+                match body.frame().await? {
+                    Ok(frame) => {
+                        if let Ok(data) = frame.into_data() {
+                            return Some(Ok(data));
+                        }
+                    }
+                    Err(e) => return Some(Err(Error::BytesError(e.to_string()))),
+                }
+            },
+        }
+    }
+}
+
+/// Incremental `text/event-stream` parser, following the WHATWG event stream interpretation rules.
+struct EventParser {
+    buf: BytesMut,
+    // Largest event accepted, counting its data and the line being received
+    max_event_size: usize,
+    started: bool,
+    skip_lf: bool,
+    event: Option<String>,
+    data: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl EventParser {
+    fn new() -> Self {
+        EventParser {
+            buf: BytesMut::new(),
+            max_event_size: DEFAULT_MAX_EVENT_SIZE,
+            started: false,
+            skip_lf: false,
+            event: None,
+            data: String::new(),
+            last_event_id: None,
+            retry: None,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Processes buffered lines until an event is dispatched or a complete line is missing.
+    /// Fails once the event being received grows over the size limit.
+    fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        while let Some(line) = self.next_line() {
+            if let Some(event) = self.process_line(&line) {
+                return Ok(Some(event));
+            }
+            self.check_size(self.data.len())?;
+        }
+
+        // Only an incomplete line is left in the buffer
+        self.check_size(self.data.len() + self.buf.len())?;
+        Ok(None)
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), Error> {
+        if size > self.max_event_size {
+            return Err(Error::SseError(format!("event exceeds the limit of {} bytes", self.max_event_size)));
+        }
+        Ok(())
+    }
+
+    fn next_line(&mut self) -> Option<String> {
+        if self.skip_lf && self.buf.first() == Some(&b'\n') {
+            self.buf.advance(1);
+        }
+        self.skip_lf = false;
+
+        if !self.started {
+            // A leading byte order mark is ignored
+            if self.buf.len() < 3 && b"\xEF\xBB\xBF".starts_with(&self.buf) {
+                return None;
+            }
+            if self.buf.starts_with(b"\xEF\xBB\xBF") {
+                self.buf.advance(3);
+            }
+            self.started = true;
+        }
+
+        let end = self.buf.iter().position(|b| *b == b'\n' || *b == b'\r')?;
+        self.skip_lf = self.buf[end] == b'\r';
+        let line = self.buf.split_to(end);
+        self.buf.advance(1);
+
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(millis) = value.parse::<u64>() {
+                    self.retry = Some(Duration::from_millis(millis));
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+
+        Some(Event {
+            event: event.unwrap_or_else(|| "message".to_string()),
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+
+    /// Drops a partially received event, as required when the connection is lost.
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.started = false;
+        self.skip_lf = false;
+        self.event = None;
+        self.data.clear();
+    }
+}
+
+/// `EventSource` style client for `text/event-stream` endpoints.
+/// The stream is reopened after connection loss, sending the last seen event id as `Last-Event-ID`.
+/// # Examples
+/// ```
+/// let mut events = client.event_source("https://example.com/events");
+/// while let Some(event) = events.next_event().await? {
+///     println!("{}: {}", event.event, event.data);
+/// }
+/// ```
+pub struct EventSource<C> {
+    client: C,
+    uri: Uri,
+    headers: HeaderMap,
+    // First error from building the uri or headers, reported by `next_event`
+    error: Option<http::Error>,
+    body: Option<SseBody>,
+    parser: EventParser,
+    retry: Duration,
+    max_retries: Option<usize>,
+    retries: usize,
+    // Error of the last failed connection attempt or lost connection, returned once retries are exhausted
+    last_error: Option<Error>,
+    attempted: bool,
+    closed: bool,
+}
+
+impl<C> EventSource<C> {
+    pub(crate) fn new(client: C, uri: Result<Uri, http::Error>) -> Self {
+        let (uri, error) = match uri {
+            Ok(uri) => (uri, None),
+            Err(e) => (Uri::default(), Some(e)),
+        };
+
+        EventSource {
+            client,
+            uri,
+            headers: HeaderMap::new(),
+            error,
+            body: None,
+            parser: EventParser::new(),
+            retry: DEFAULT_RETRY,
+            max_retries: None,
+            retries: 0,
+            last_error: None,
+            attempted: false,
+            closed: false,
+        }
+    }
+
+    /// Sets a header sent with every connection attempt.
+    pub fn set_header<K, T>(mut self, key: K, value: T) -> Self
+        where
+            HeaderName: TryFrom<K>,
+            <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+            HeaderValue: TryFrom<T>,
+            <HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        let name = HeaderName::try_from(key).map_err(Into::into);
+        let value = HeaderValue::try_from(value).map_err(Into::into);
+        match (name, value) {
+            (Ok(name), Ok(value)) => {
+                self.headers.insert(name, value);
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                self.error.get_or_insert(e);
+            }
+        }
+        self
+    }
+
+    /// Sets the delay before reconnecting, until the server overrides it with a `retry` field.
+    /// Default is 3 seconds.
+    pub fn retry_delay(mut self, val: Duration) -> Self {
+        self.retry = val;
+        self
+    }
+
+    /// Limits the number of consecutive reconnection attempts, unlimited by default.
+    pub fn max_retries(mut self, val: usize) -> Self {
+        self.max_retries = Some(val);
+        self
+    }
+
+    /// Sets the maximum size in bytes of an event, counting its data and the line being received.
+    /// A larger event closes the stream with `Error::SseError`. Default is 1 MiB.
+    pub fn max_event_size(mut self, val: usize) -> Self {
+        self.parser.max_event_size = val;
+        self
+    }
+
+    /// Sets the initial `Last-Event-ID`, to resume a stream consumed by an earlier `EventSource`.
+    pub fn last_event_id(mut self, id: impl Into<String>) -> Self {
+        self.parser.last_event_id = Some(id.into());
+        self
+    }
+
+    /// Returns the id of the last event received.
+    pub fn current_event_id(&self) -> Option<&str> {
+        self.parser.last_event_id.as_deref()
+    }
+
+    /// Closes the stream, `next_event` returns `None` afterwards.
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.body = None;
+        self.last_error = None;
+    }
+
+    fn request_headers(&mut self) -> Result<HeaderMap, Error> {
+        if let Some(e) = self.error.take() {
+            self.close();
+            return Err(Error::HttpRequestBuilder(e));
+        }
+
+        let mut headers = self.headers.clone();
+        headers.insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        if let Some(id) = self.parser.last_event_id.as_deref() {
+            let id = HeaderValue::try_from(id).map_err(Error::InvalidHeaderValue)?;
+            headers.insert(LAST_EVENT_ID, id);
+        }
+
+        Ok(headers)
+    }
+
+    /// Waits before a reconnection attempt.
+    /// Returns false once the stream is closed or the attempts are exhausted.
+    async fn before_connect(&mut self) -> bool {
+        if self.closed {
+            return false;
+        }
+        if self.attempted {
+            if matches!(self.max_retries, Some(max) if self.retries >= max) {
+                self.closed = true;
+                return false;
+            }
+            self.retries += 1;
+            monoio::time::sleep(self.retry).await;
+        }
+        self.attempted = true;
+
+        true
+    }
+
+    /// Validates the response of a connection attempt, following the HTML EventSource rules:
+    /// `204 No Content` ends the stream, any other non `200` status or content type fails it.
+    fn accept(&mut self, status: StatusCode, headers: &HeaderMap, body: SseBody) -> Result<bool, Error> {
+        if status == StatusCode::NO_CONTENT {
+            self.closed = true;
+            return Ok(false);
+        }
+        if status != StatusCode::OK {
+            self.closed = true;
+            return Err(Error::UnexpectedStatus(status));
+        }
+
+        let event_stream = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case("text/event-stream"))
+            .unwrap_or(false);
+        if !event_stream {
+            self.closed = true;
+            return Err(Error::SseError("response is not a text/event-stream".to_string()));
+        }
+
+        self.body = Some(body);
+        self.retries = 0;
+        self.last_error = None;
+        self.parser.reset();
+        Ok(true)
+    }
+
+    /// Reads from the open stream until an event is complete.
+    /// Returns `None` if the connection was lost, an error closing the stream if the event is too large.
+    async fn read_event(&mut self) -> Result<Option<Event>, Error> {
+        loop {
+            let event = self.parser.next_event().inspect_err(|_| self.close())?;
+            if let Some(event) = event {
+                if let Some(retry) = self.parser.retry.take() {
+                    self.retry = retry;
+                }
+                return Ok(Some(event));
+            }
+            if let Some(retry) = self.parser.retry.take() {
+                self.retry = retry;
+            }
+
+            let Some(body) = self.body.as_mut() else { return Ok(None) };
+            match body.next_chunk().await {
+                Some(Ok(chunk)) => self.parser.feed(&chunk),
+                Some(Err(e)) => {
+                    self.last_error = Some(e);
+                    self.body = None;
+                    return Ok(None);
+                }
+                None => {
+                    self.body = None;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "hyper-tls"))]
+impl EventSource<MonoioClient> {
+    /// Returns the next event, reconnecting if the connection was lost.
+    /// Returns `None` once the stream was closed by `close` or a `204` response,
+    /// the error of the last attempt once the reconnection attempts are exhausted.
+    pub async fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        loop {
+            if self.body.is_none() {
+                if !self.before_connect().await {
+                    return self.last_error.take().map_or(Ok(None), Err);
+                }
+
+                let headers = self.request_headers()?;
+                let mut request = self.client.make_request().set_uri(self.uri.clone());
+                for (name, value) in headers.iter() {
+                    request = request.set_header(name, value);
+                }
+
+                // Connection errors are retried, the status and content type are not
+                let response = match request.send_streaming().await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        self.last_error = Some(e);
+                        continue;
+                    }
+                };
+                let (parts, body) = response.into_parts();
+                if !self.accept(parts.status, &parts.headers, SseBody::Monoio(body))? {
+                    return Ok(None);
+                }
+            }
+
+            if let Some(event) = self.read_event().await? {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "hyper", feature = "pool-hyper", feature = "hyper-tls"))]
+impl EventSource<MonoioHyperClient> {
+    /// Returns the next event, reconnecting if the connection was lost.
+    /// Returns `None` once the stream was closed by `close` or a `204` response,
+    /// the error of the last attempt once the reconnection attempts are exhausted.
+    pub async fn next_event(&mut self) -> Result<Option<Event>, Error> {
+        loop {
+            if self.body.is_none() {
+                if !self.before_connect().await {
+                    return self.last_error.take().map_or(Ok(None), Err);
+                }
+
+                let headers = self.request_headers()?;
+                let mut request = self.client.new_request().set_uri(self.uri.clone());
+                for (name, value) in headers.iter() {
+                    request = request.set_header(name, value);
+                }
+
+                // Connection errors are retried, the status and content type are not
+                let response = match request.send_streaming().await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        self.last_error = Some(e);
+                        continue;
+                    }
+                };
+                let (parts, body) = response.into_parts();
+                if !self.accept(parts.status, &parts.headers, SseBody::Hyper(body))? {
+                    return Ok(None);
+                }
+            }
+
+            if let Some(event) = self.read_event().await? {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
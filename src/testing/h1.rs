@@ -0,0 +1,208 @@
+use std::rc::Rc;
+
+use bytes::{Bytes, BytesMut};
+use http::header::{CONNECTION, CONTENT_LENGTH, EXPECT, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode, Uri, Version};
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt};
+
+use super::{h2, Prefixed, ReceivedRequest, ResponseTrailers, ServerState};
+use crate::error::{Error, Result};
+use crate::http::stream::{read_body, read_more, write_chunked_body};
+use crate::response::ResponseLimits;
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const MAX_HEADERS: usize = 100;
+
+/// Path answered with `417 Expectation Failed` to requests expecting `100 Continue`.
+const REJECT_EXPECT_PATH: &str = "/expect/reject";
+
+/// Serves HTTP/1.1 requests on `stream` until the client closes it, handing the connection
+/// over to the HTTP/2 server if it starts with the prior knowledge preface.
+pub(super) async fn serve<S>(mut stream: S, state: Rc<ServerState>) -> Result<()>
+    where
+        S: AsyncReadRent + AsyncWriteRent + Unpin + 'static,
+{
+    let mut buf = BytesMut::new();
+
+    if !state.http1_only {
+        while buf.len() < PREFACE.len() && PREFACE.starts_with(&buf) {
+            if read_more(&mut stream, &mut buf).await.is_err() {
+                return Ok(());
+            }
+        }
+        if buf.starts_with(PREFACE) {
+            return h2::serve(Prefixed::new(stream, buf), state).await;
+        }
+    }
+
+    loop {
+        let Some(mut request) = read_request_head(&mut stream, &mut buf).await? else {
+            return Ok(());
+        };
+
+        let expects_continue = request
+            .headers
+            .get(EXPECT)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"100-continue"));
+        if expects_continue {
+            if request.uri.path() == REJECT_EXPECT_PATH {
+                let mut response = Response::new(Bytes::new());
+                *response.status_mut() = StatusCode::EXPECTATION_FAILED;
+                response.headers_mut().insert(CONNECTION, HeaderValue::from_static("close"));
+                return write_response(&mut stream, &request.method, response).await;
+            }
+            let (res, _) = stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n".to_vec()).await;
+            res.map_err(Error::IoError)?;
+        }
+
+        // Requests without framing headers have no body
+        if request.headers.contains_key(CONTENT_LENGTH) || request.headers.contains_key(TRANSFER_ENCODING) {
+            let (body, trailers) = read_body(&mut stream, &mut buf, &request.headers, &ResponseLimits::default()).await?;
+            request.body = body;
+            request.trailers = trailers;
+        }
+
+        let close = request.version == Version::HTTP_10
+            || request
+                .headers
+                .get(CONNECTION)
+                .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"close"));
+        let method = request.method.clone();
+        let response = state.respond(request).await;
+
+        if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+            #[cfg(feature = "websocket")]
+            let websocket = response.extensions().get::<super::WebSocketEcho>().copied();
+            write_response(&mut stream, &method, response).await?;
+            #[cfg(feature = "websocket")]
+            if let Some(session) = websocket {
+                return super::websocket::serve(stream, buf, session).await;
+            }
+            return echo(stream, buf).await;
+        }
+
+        write_response(&mut stream, &method, response).await?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads the next request head, `None` once the client closed the connection between requests.
+async fn read_request_head<S: AsyncReadRent>(stream: &mut S, buf: &mut BytesMut) -> Result<Option<ReceivedRequest>> {
+    loop {
+        if !buf.is_empty() {
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut parsed = httparse::Request::new(&mut headers);
+            let status = parsed
+                .parse(buf)
+                .map_err(|e| Error::HttpResponseHeadError(e.to_string()))?;
+            if let httparse::Status::Complete(head_len) = status {
+                let request = build_request(&parsed)?;
+                let _ = buf.split_to(head_len);
+                return Ok(Some(request));
+            }
+        }
+
+        if read_more(stream, buf).await.is_err() {
+            return Ok(None);
+        }
+    }
+}
+
+fn build_request(parsed: &httparse::Request) -> Result<ReceivedRequest> {
+    let invalid = |what: &str| Error::HttpResponseHeadError(format!("invalid request {what}"));
+    let method = parsed
+        .method
+        .and_then(|method| Method::from_bytes(method.as_bytes()).ok())
+        .ok_or_else(|| invalid("method"))?;
+    let uri = parsed
+        .path
+        .and_then(|path| path.parse::<Uri>().ok())
+        .ok_or_else(|| invalid("target"))?;
+    let version = match parsed.version {
+        Some(0) => Version::HTTP_10,
+        _ => Version::HTTP_11,
+    };
+
+    let mut headers = HeaderMap::with_capacity(parsed.headers.len());
+    for header in parsed.headers.iter() {
+        let name = HeaderName::from_bytes(header.name.as_bytes()).map_err(|_| invalid("header name"))?;
+        let value = HeaderValue::from_bytes(header.value).map_err(Error::InvalidHeaderValue)?;
+        headers.append(name, value);
+    }
+
+    Ok(ReceivedRequest {
+        method,
+        uri,
+        version,
+        headers,
+        body: Bytes::new(),
+        trailers: HeaderMap::new(),
+    })
+}
+
+/// Writes `response`, chunked when it carries trailers or asks for chunked encoding,
+/// without a body for `HEAD` requests and bodiless statuses.
+async fn write_response<S: AsyncWriteRent>(stream: &mut S, method: &Method, response: Response<Bytes>) -> Result<()> {
+    let (mut parts, body) = response.into_parts();
+    let trailers = parts.extensions.remove::<ResponseTrailers>();
+    let chunked = trailers.is_some()
+        || parts
+            .headers
+            .get(TRANSFER_ENCODING)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"chunked"));
+    let bodiless = parts.status.is_informational()
+        || parts.status == StatusCode::NO_CONTENT
+        || parts.status == StatusCode::NOT_MODIFIED;
+
+    if chunked && !bodiless {
+        parts.headers.insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        parts.headers.remove(CONTENT_LENGTH);
+    } else if !bodiless && !parts.headers.contains_key(CONTENT_LENGTH) {
+        parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+    }
+
+    let mut head = Vec::with_capacity(256);
+    head.extend_from_slice(format!("HTTP/1.1 {}\r\n", parts.status).as_bytes());
+    for (name, value) in parts.headers.iter() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    let (res, _) = stream.write_all(head).await;
+    res.map_err(Error::IoError)?;
+
+    if *method == Method::HEAD || bodiless {
+        return stream.flush().await.map_err(Error::IoError);
+    }
+    if chunked {
+        let trailers = trailers.unwrap_or_default();
+        return write_chunked_body(stream, body, &trailers.0).await;
+    }
+
+    let (res, _) = stream.write_all(body).await;
+    res.map_err(Error::IoError)?;
+    stream.flush().await.map_err(Error::IoError)
+}
+
+/// Echoes the bytes of an upgraded connection back until the client closes it.
+async fn echo<S: AsyncReadRent + AsyncWriteRent>(mut stream: S, buf: BytesMut) -> Result<()> {
+    if !buf.is_empty() {
+        let (res, _) = stream.write_all(buf.freeze()).await;
+        res.map_err(Error::IoError)?;
+    }
+
+    loop {
+        let (res, chunk) = stream.read(Vec::with_capacity(8 * 1024)).await;
+        match res.map_err(Error::IoError)? {
+            0 => return Ok(()),
+            _ => {
+                let (res, _) = stream.write_all(chunk).await;
+                res.map_err(Error::IoError)?;
+            }
+        }
+    }
+}
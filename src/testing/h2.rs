@@ -0,0 +1,76 @@
+use std::io;
+use std::rc::Rc;
+
+use bytes::{Bytes, BytesMut};
+use http::header::{CONNECTION, TRANSFER_ENCODING};
+use http::{Request, Response, Uri};
+use monoio::io::{AsyncReadRent, AsyncWriteRent};
+use monoio_http::h2::server::{self as h2_server, SendResponse};
+use monoio_http::h2::RecvStream;
+
+use super::{ReceivedRequest, ResponseTrailers, ServerState};
+use crate::error::{Error, Result};
+
+/// Serves HTTP/2 streams on `stream`, each answered on its own task.
+pub(super) async fn serve<S>(stream: S, state: Rc<ServerState>) -> Result<()>
+    where
+        S: AsyncReadRent + AsyncWriteRent + Unpin + 'static,
+{
+    let mut connection = h2_server::handshake(stream).await.map_err(h2_error)?;
+    while let Some(accepted) = connection.accept().await {
+        let (request, respond) = accepted.map_err(h2_error)?;
+        let state = state.clone();
+        monoio::spawn(async move {
+            let _ = respond_to(request, respond, state).await;
+        });
+    }
+    Ok(())
+}
+
+async fn respond_to(request: Request<RecvStream>, mut respond: SendResponse<Bytes>, state: Rc<ServerState>) -> Result<()> {
+    let (parts, mut recv) = request.into_parts();
+    let mut body = BytesMut::new();
+    while let Some(data) = recv.data().await {
+        let data = data.map_err(h2_error)?;
+        let _ = recv.flow_control().release_capacity(data.len());
+        body.extend_from_slice(&data);
+    }
+    let trailers = recv.trailers().await.map_err(h2_error)?.unwrap_or_default();
+
+    let uri = parts
+        .uri
+        .path_and_query()
+        .map_or_else(|| Uri::from_static("/"), |path| Uri::from(path.clone()));
+    let request = ReceivedRequest {
+        method: parts.method,
+        uri,
+        version: parts.version,
+        headers: parts.headers,
+        body: body.freeze(),
+        trailers,
+    };
+
+    let (mut parts, body) = state.respond(request).await.into_parts();
+    let trailers = parts.extensions.remove::<ResponseTrailers>();
+    // Connection specific fields are not allowed in HTTP/2
+    parts.headers.remove(CONNECTION);
+    parts.headers.remove(TRANSFER_ENCODING);
+
+    let end_of_stream = body.is_empty() && trailers.is_none();
+    let mut send = respond
+        .send_response(Response::from_parts(parts, ()), end_of_stream)
+        .map_err(h2_error)?;
+    if end_of_stream {
+        return Ok(());
+    }
+
+    send.send_data(body, trailers.is_none()).map_err(h2_error)?;
+    if let Some(ResponseTrailers(trailers)) = trailers {
+        send.send_trailers(trailers).map_err(h2_error)?;
+    }
+    Ok(())
+}
+
+fn h2_error(e: monoio_http::h2::Error) -> Error {
+    Error::IoError(io::Error::other(e))
+}
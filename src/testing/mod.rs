@@ -0,0 +1,303 @@
+//! In-process HTTP server to run the clients against in tests, serving HTTP/1.1 and HTTP/2
+//! over plain TCP, with prior knowledge, and over TLS with a generated self-signed certificate.
+//! Available only on crate feature testing
+
+mod h1;
+mod h2;
+mod routes;
+pub(crate) mod tls;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+use std::cell::{Cell, RefCell};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use http::{HeaderMap, Method, Response, Uri, Version};
+use monoio::buf::{IoBuf, IoBufMut, IoVecBuf, IoVecBufMut, IoVecWrapperMut};
+use monoio::io::{AsyncReadRent, AsyncWriteRent};
+use monoio::net::{TcpListener, TcpStream};
+use monoio::BufResult;
+
+pub use self::routes::default_handler;
+use self::tls::TlsIdentity;
+use crate::error::{Error, Result};
+
+type Handler = Rc<dyn Fn(&ReceivedRequest) -> Response<Bytes>>;
+
+/// A request received by a `TestServer`.
+#[derive(Clone, Debug)]
+pub struct ReceivedRequest {
+    pub method: Method,
+    /// Path and query of the request target.
+    pub uri: Uri,
+    pub version: Version,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+    pub trailers: HeaderMap,
+}
+
+/// Trailer fields sent after the body of a `TestServer` response, set in the response extensions.
+/// HTTP/1.1 responses carrying trailers are sent chunked.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseTrailers(pub HeaderMap);
+
+/// Delay before a `TestServer` response is sent, set in the response extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseDelay(pub Duration);
+
+/// Serves the connection as a WebSocket echo once a `101 Switching Protocols` response is sent,
+/// set in the response extensions. Data frames are echoed as received, pings are answered
+/// and the text message `close` makes the server close the connection.
+/// Close frames of the client are left unanswered if `ignore_close` is set.
+/// Available only on crate feature websocket
+#[cfg(feature = "websocket")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WebSocketEcho {
+    pub ignore_close: bool,
+}
+
+/// Server bound to a local port, accepting connections on a task of the current monoio runtime
+/// until the runtime shuts down. Requests are answered by `default_handler` unless
+/// a handler is set, see `default_handler` for the routes it serves.
+/// Available only on crate feature testing
+/// # Examples
+/// ```
+/// let server = TestServer::builder().tls().start()?;
+/// let client = MonoioClient::builder()
+///     .enable_https()
+///     .add_root_certificate(server.certificate_der().unwrap())
+///     .build();
+///
+/// let response = client.make_request().set_uri(server.url("/status/204")).send().await?;
+/// assert_eq!(response.status(), StatusCode::NO_CONTENT);
+/// assert_eq!(server.received_requests().len(), 1);
+/// ```
+pub struct TestServer {
+    addr: SocketAddr,
+    certificate: Option<Vec<u8>>,
+    state: Rc<ServerState>,
+}
+
+impl TestServer {
+    pub fn builder() -> TestServerBuilder {
+        TestServerBuilder::default()
+    }
+
+    /// Starts a plain TCP server with the default handler.
+    pub fn start() -> io::Result<TestServer> {
+        TestServer::builder().start()
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the URL of `path` on this server, with the `https` scheme and the `localhost`
+    /// host the certificate is issued for on TLS servers.
+    pub fn url(&self, path: &str) -> String {
+        match self.certificate {
+            Some(_) => format!("https://localhost:{}{path}", self.addr.port()),
+            None => format!("http://{}{path}", self.addr),
+        }
+    }
+
+    /// Returns the DER encoded self-signed certificate of a TLS server.
+    pub fn certificate_der(&self) -> Option<Vec<u8>> {
+        self.certificate.clone()
+    }
+
+    /// Returns every request received so far, in the order they were received.
+    pub fn received_requests(&self) -> Vec<ReceivedRequest> {
+        self.state.requests.borrow().clone()
+    }
+
+    /// Returns the number of connections accepted so far.
+    pub fn connection_count(&self) -> usize {
+        self.state.connections.get()
+    }
+}
+
+impl Debug for TestServer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TestServer")
+            .field("addr", &self.addr)
+            .field("tls", &self.certificate.is_some())
+            .finish()
+    }
+}
+
+#[derive(Default)]
+pub struct TestServerBuilder {
+    tls: bool,
+    http1_only: bool,
+    handler: Option<Handler>,
+}
+
+impl TestServerBuilder {
+    /// Serves TLS with a self-signed certificate for `localhost`, negotiating the protocol through ALPN.
+    pub fn tls(mut self) -> Self {
+        self.tls = true;
+        self
+    }
+
+    /// Serves HTTP/1.1 only, without `h2` in ALPN nor prior knowledge HTTP/2 on plain connections.
+    pub fn http1_only(mut self) -> Self {
+        self.http1_only = true;
+        self
+    }
+
+    /// Answers requests with `handler` instead of `default_handler`.
+    pub fn handler<F>(mut self, handler: F) -> Self
+        where
+            F: Fn(&ReceivedRequest) -> Response<Bytes> + 'static,
+    {
+        self.handler = Some(Rc::new(handler));
+        self
+    }
+
+    /// Binds a local port and starts accepting connections, must be called within a monoio runtime.
+    pub fn start(self) -> io::Result<TestServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let identity = match self.tls {
+            true => Some(TlsIdentity::generate(self.http1_only)?),
+            false => None,
+        };
+        let certificate = identity.as_ref().map(|identity| identity.certificate.to_vec());
+
+        let state = Rc::new(ServerState {
+            handler: self.handler.unwrap_or_else(|| Rc::new(default_handler)),
+            http1_only: self.http1_only,
+            requests: RefCell::new(Vec::new()),
+            connections: Cell::new(0),
+        });
+
+        let server_state = state.clone();
+        monoio::spawn(async move {
+            let identity = identity.map(Rc::new);
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                server_state.connections.set(server_state.connections.get() + 1);
+                let (state, identity) = (server_state.clone(), identity.clone());
+                monoio::spawn(async move {
+                    let _ = serve(stream, identity, state).await;
+                });
+            }
+        });
+
+        Ok(TestServer {
+            addr,
+            certificate,
+            state,
+        })
+    }
+}
+
+struct ServerState {
+    handler: Handler,
+    http1_only: bool,
+    requests: RefCell<Vec<ReceivedRequest>>,
+    connections: Cell<usize>,
+}
+
+impl ServerState {
+    /// Records `request` and answers it, after the delay the handler asked for.
+    async fn respond(&self, request: ReceivedRequest) -> Response<Bytes> {
+        let response = (self.handler)(&request);
+        self.requests.borrow_mut().push(request);
+        if let Some(ResponseDelay(delay)) = response.extensions().get::<ResponseDelay>() {
+            monoio::time::sleep(*delay).await;
+        }
+        response
+    }
+}
+
+async fn serve(stream: TcpStream, identity: Option<Rc<TlsIdentity>>, state: Rc<ServerState>) -> Result<()> {
+    let Some(identity) = identity else {
+        return h1::serve(stream, state).await;
+    };
+
+    let stream = identity
+        .acceptor
+        .accept(stream)
+        .await
+        .map_err(|e| Error::IoError(io::Error::other(e)))?;
+    if stream.alpn_protocol().as_deref() == Some(b"h2".as_slice()) {
+        h2::serve(stream, state).await
+    } else {
+        h1::serve(stream, state).await
+    }
+}
+
+/// Connection whose first reads return bytes already read from it, used to hand
+/// a connection over to the HTTP/2 server once the prior knowledge preface was seen.
+struct Prefixed<S> {
+    stream: S,
+    read_buf: BytesMut,
+}
+
+impl<S> Prefixed<S> {
+    fn new(stream: S, read_buf: BytesMut) -> Self {
+        Prefixed { stream, read_buf }
+    }
+
+    fn copy_buffered<T: IoBufMut>(&mut self, mut buf: T) -> (usize, T) {
+        let n = self.read_buf.len().min(buf.bytes_total());
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.read_buf.as_ptr(), buf.write_ptr(), n);
+            buf.set_init(n);
+        }
+        self.read_buf.advance(n);
+        (n, buf)
+    }
+}
+
+impl<S: AsyncReadRent> AsyncReadRent for Prefixed<S> {
+    async fn read<T: IoBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        if self.read_buf.is_empty() {
+            return self.stream.read(buf).await;
+        }
+
+        let (n, buf) = self.copy_buffered(buf);
+        (Ok(n), buf)
+    }
+
+    async fn readv<T: IoVecBufMut>(&mut self, buf: T) -> BufResult<usize, T> {
+        if self.read_buf.is_empty() {
+            return self.stream.readv(buf).await;
+        }
+
+        match IoVecWrapperMut::new(buf) {
+            Ok(wrapper) => {
+                let (n, wrapper) = self.copy_buffered(wrapper);
+                (Ok(n), wrapper.into_inner())
+            }
+            Err(buf) => (Ok(0), buf),
+        }
+    }
+}
+
+impl<S: AsyncWriteRent> AsyncWriteRent for Prefixed<S> {
+    async fn write<T: IoBuf>(&mut self, buf: T) -> BufResult<usize, T> {
+        self.stream.write(buf).await
+    }
+
+    async fn writev<T: IoVecBuf>(&mut self, buf_vec: T) -> BufResult<usize, T> {
+        self.stream.writev(buf_vec).await
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
+
+    async fn shutdown(&mut self) -> io::Result<()> {
+        self.stream.shutdown().await
+    }
+}
@@ -0,0 +1,260 @@
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use http::header::{
+    AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH, TRANSFER_ENCODING, UPGRADE, WWW_AUTHENTICATE,
+};
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
+use md5::{Digest, Md5};
+use serde_json::{json, Map, Value};
+
+use super::{ReceivedRequest, ResponseDelay, ResponseTrailers};
+
+const REALM: &str = "monoio-netreq";
+const NONCE: &str = "dcd98b7102dd2f0e8b11d0f600bfb0c093";
+const CACHE_ETAG: &str = "\"v1\"";
+
+/// Answers the routes of a `TestServer` started without a handler:
+/// - `/status/{code}` with an empty response of that status
+/// - `/bytes/{n}` with `n` bytes counting up from zero
+/// - `/delay/{ms}` with the echo after `ms` milliseconds
+/// - `/stream/{n}` with `n` lines sent chunked over HTTP/1.1
+/// - `/trailers` with a body followed by an `x-checksum` trailer field
+/// - `/cache` with a cacheable body and an `ETag`, `304 Not Modified` once revalidated
+/// - `/basic-auth/{user}/{password}`, `/digest-auth/{user}/{password}` and `/bearer`
+///   with the echo once authenticated, `401 Unauthorized` with a challenge otherwise
+/// - `/upgrade` with `101 Switching Protocols`, echoing the bytes of the connection afterwards
+/// - `/websocket` with the WebSocket handshake, echoing messages afterwards, see `WebSocketEcho`.
+///   `permessage-deflate` and the first subprotocol are accepted if offered, close frames of the
+///   client are left unanswered on `/websocket?ignore-close`. Available only on crate feature websocket
+/// - `/sse` with two server-sent events
+/// - `/expect/reject` with `417 Expectation Failed` to requests expecting `100 Continue`
+///
+/// Every other path is answered with the echo, a JSON object with the `method`, `path`, `query`,
+/// `version`, `headers`, `body` and `trailers` of the request.
+pub fn default_handler(request: &ReceivedRequest) -> Response<Bytes> {
+    let segments: Vec<&str> = request.uri.path().trim_start_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["status", code] => match code.parse::<u16>().ok().and_then(|code| StatusCode::from_u16(code).ok()) {
+            Some(status) => with_status(status, Bytes::new()),
+            None => with_status(StatusCode::BAD_REQUEST, Bytes::new()),
+        },
+        ["bytes", n] => {
+            let n = n.parse::<usize>().unwrap_or_default();
+            let body: Vec<u8> = (0..n).map(|i| i as u8).collect();
+            with_status(StatusCode::OK, Bytes::from(body))
+        }
+        ["delay", ms] => {
+            let mut response = echo(request);
+            let delay = Duration::from_millis(ms.parse().unwrap_or_default());
+            response.extensions_mut().insert(ResponseDelay(delay));
+            response
+        }
+        ["stream", n] => {
+            let n = n.parse::<usize>().unwrap_or_default();
+            let body: String = (0..n).map(|i| format!("line {i}\n")).collect();
+            let mut response = with_status(StatusCode::OK, Bytes::from(body));
+            response
+                .headers_mut()
+                .insert(TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+            response
+        }
+        ["trailers"] => {
+            let mut trailers = HeaderMap::new();
+            trailers.insert(HeaderName::from_static("x-checksum"), HeaderValue::from_static("5d41402a"));
+            let mut response = with_status(StatusCode::OK, Bytes::from_static(b"hello"));
+            response.extensions_mut().insert(ResponseTrailers(trailers));
+            response
+        }
+        ["cache"] => {
+            let revalidated = request
+                .headers
+                .get(IF_NONE_MATCH)
+                .is_some_and(|value| value.as_bytes() == CACHE_ETAG.as_bytes());
+            let mut response = match revalidated {
+                true => with_status(StatusCode::NOT_MODIFIED, Bytes::new()),
+                false => with_status(StatusCode::OK, Bytes::from_static(b"cached body")),
+            };
+            let headers = response.headers_mut();
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=60"));
+            headers.insert(ETAG, HeaderValue::from_static(CACHE_ETAG));
+            response
+        }
+        ["basic-auth", user, password] => {
+            let expected = format!("Basic {}", STANDARD.encode(format!("{user}:{password}")));
+            match authorization(request) == Some(expected.as_str()) {
+                true => echo(request),
+                false => unauthorized(format!("Basic realm=\"{REALM}\"")),
+            }
+        }
+        ["digest-auth", user, password] => match verify_digest(request, user, password) {
+            true => echo(request),
+            false => unauthorized(format!(
+                "Digest realm=\"{REALM}\", qop=\"auth\", nonce=\"{NONCE}\", algorithm=MD5"
+            )),
+        },
+        ["bearer"] => match authorization(request).is_some_and(|value| value.starts_with("Bearer ")) {
+            true => echo(request),
+            false => unauthorized("Bearer".to_string()),
+        },
+        ["upgrade"] => {
+            let mut response = with_status(StatusCode::SWITCHING_PROTOCOLS, Bytes::new());
+            let protocol = request
+                .headers
+                .get(UPGRADE)
+                .cloned()
+                .unwrap_or_else(|| HeaderValue::from_static("echo"));
+            response.headers_mut().insert(UPGRADE, protocol);
+            response
+                .headers_mut()
+                .insert(http::header::CONNECTION, HeaderValue::from_static("upgrade"));
+            response
+        }
+        #[cfg(feature = "websocket")]
+        ["websocket"] => websocket_handshake(request),
+        ["sse"] => {
+            let body = "id: 1\nevent: greeting\ndata: hello\n\nid: 2\ndata: world\n\n";
+            let mut response = with_status(StatusCode::OK, Bytes::from_static(body.as_bytes()));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+            response
+        }
+        _ => echo(request),
+    }
+}
+
+#[cfg(feature = "websocket")]
+fn websocket_handshake(request: &ReceivedRequest) -> Response<Bytes> {
+    use http::header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL};
+
+    let Some(key) = request.headers.get(SEC_WEBSOCKET_KEY).and_then(|v| v.to_str().ok()) else {
+        return with_status(StatusCode::BAD_REQUEST, Bytes::new());
+    };
+    let accept = crate::http::websocket::accept_key(key);
+
+    let mut response = with_status(StatusCode::SWITCHING_PROTOCOLS, Bytes::new());
+    let headers = response.headers_mut();
+    headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(SEC_WEBSOCKET_ACCEPT, HeaderValue::try_from(accept).unwrap());
+
+    let offered = |name| request.headers.get(name).and_then(|v: &HeaderValue| v.to_str().ok());
+    if let Some(protocol) = offered(SEC_WEBSOCKET_PROTOCOL).and_then(|v| v.split(',').next()) {
+        headers.insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::try_from(protocol.trim()).unwrap());
+    }
+    if offered(SEC_WEBSOCKET_EXTENSIONS).is_some_and(|v| v.contains("permessage-deflate")) {
+        headers.insert(SEC_WEBSOCKET_EXTENSIONS, HeaderValue::from_static("permessage-deflate"));
+    }
+
+    let ignore_close = request.uri.query() == Some("ignore-close");
+    response.extensions_mut().insert(super::WebSocketEcho { ignore_close });
+    response
+}
+
+fn with_status(status: StatusCode, body: Bytes) -> Response<Bytes> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+}
+
+fn unauthorized(challenge: String) -> Response<Bytes> {
+    let mut response = with_status(StatusCode::UNAUTHORIZED, Bytes::new());
+    if let Ok(value) = HeaderValue::try_from(challenge) {
+        response.headers_mut().insert(WWW_AUTHENTICATE, value);
+    }
+    response
+}
+
+fn echo(request: &ReceivedRequest) -> Response<Bytes> {
+    let fields = |headers: &HeaderMap| {
+        let mut map = Map::new();
+        for name in headers.keys() {
+            let values: Vec<&str> = headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            map.insert(name.to_string(), Value::String(values.join(", ")));
+        }
+        Value::Object(map)
+    };
+
+    let body = json!({
+        "method": request.method.as_str(),
+        "path": request.uri.path(),
+        "query": request.uri.query().unwrap_or_default(),
+        "version": format!("{:?}", request.version),
+        "headers": fields(&request.headers),
+        "body": String::from_utf8_lossy(&request.body),
+        "trailers": fields(&request.trailers),
+    });
+
+    let mut response = with_status(StatusCode::OK, Bytes::from(body.to_string()));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn authorization(request: &ReceivedRequest) -> Option<&str> {
+    request.headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok())
+}
+
+/// Checks a digest answer to the `qop=auth` challenge of RFC 7616, or to the RFC 2069 one without `qop`.
+fn verify_digest(request: &ReceivedRequest, user: &str, password: &str) -> bool {
+    let Some(params) = authorization(request).and_then(|value| value.strip_prefix("Digest ")) else {
+        return false;
+    };
+    let params = digest_params(params);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    if param("username") != Some(user) || param("nonce") != Some(NONCE) {
+        return false;
+    }
+    let (Some(uri), Some(response)) = (param("uri"), param("response")) else {
+        return false;
+    };
+
+    let ha1 = md5_hex(&format!("{user}:{REALM}:{password}"));
+    let ha2 = md5_hex(&format!("{}:{uri}", request.method));
+    let expected = match (param("qop"), param("nc"), param("cnonce")) {
+        (Some(qop), Some(nc), Some(cnonce)) => md5_hex(&format!("{ha1}:{NONCE}:{nc}:{cnonce}:{qop}:{ha2}")),
+        _ => md5_hex(&format!("{ha1}:{NONCE}:{ha2}")),
+    };
+    expected == response
+}
+
+/// Splits the comma separated `name=value` parameters of a digest answer, values may be quoted.
+fn digest_params(input: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = input.trim();
+    while let Some((name, after)) = rest.split_once('=') {
+        let name = name.trim().trim_start_matches(',').trim().to_string();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        params.push((name, value.to_string()));
+        rest = remaining.trim_start().trim_start_matches(',');
+    }
+    params
+}
+
+fn md5_hex(input: &str) -> String {
+    hex::encode(Md5::digest(input.as_bytes()))
+}
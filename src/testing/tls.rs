@@ -0,0 +1,58 @@
+use std::io;
+use std::sync::Arc;
+
+use monoio_rustls::TlsAcceptor;
+use monoio_transports::connectors::TlsConnector;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+use crate::trace::PhaseConnector;
+
+/// Self-signed certificate for `localhost` and the acceptor serving it.
+pub(super) struct TlsIdentity {
+    pub(super) certificate: CertificateDer<'static>,
+    pub(super) acceptor: TlsAcceptor,
+}
+
+impl TlsIdentity {
+    /// Generates a certificate, offering `h2` through ALPN unless `http1_only` is set.
+    pub(super) fn generate(http1_only: bool) -> io::Result<Self> {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+            .map_err(io::Error::other)?;
+        let certificate = generated.cert.der().clone();
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der()));
+
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate.clone()], key)
+            .map_err(io::Error::other)?;
+        config.alpn_protocols = if http1_only {
+            vec![b"http/1.1".to_vec()]
+        } else {
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        };
+
+        Ok(TlsIdentity {
+            certificate,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        })
+    }
+}
+
+/// TLS connector negotiating `alpn`, trusting `roots` besides the web PKI roots.
+pub(crate) fn connector(alpn: Vec<&str>, roots: &[Vec<u8>]) -> TlsConnector<PhaseConnector> {
+    let mut store = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    for der in roots.iter() {
+        // Invalid certificates are skipped, the handshake then fails to verify the server
+        let _ = store.add(CertificateDer::from(der.clone()));
+    }
+
+    let mut config = ClientConfig::builder()
+        .with_root_certificates(store)
+        .with_no_client_auth();
+    config.alpn_protocols = alpn.into_iter().map(|protocol| protocol.as_bytes().to_vec()).collect();
+
+    TlsConnector::new(PhaseConnector::for_tls(), Arc::new(config).into())
+}
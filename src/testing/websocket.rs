@@ -0,0 +1,73 @@
+use bytes::{BufMut, BytesMut};
+use monoio::io::{AsyncReadRent, AsyncWriteRent, AsyncWriteRentExt};
+
+use super::WebSocketEcho;
+use crate::error::{Error, Result};
+use crate::http::stream::read_more;
+use crate::http::websocket::frame::{self, OpCode};
+
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Echoes the frames of a WebSocket connection until the close handshake completes
+/// or the client closes the connection.
+pub(super) async fn serve<S>(mut stream: S, mut buf: BytesMut, session: WebSocketEcho) -> Result<()>
+    where
+        S: AsyncReadRent + AsyncWriteRent,
+{
+    let mut close_sent = false;
+
+    loop {
+        let frame = match frame::decode_client(&mut buf, MAX_FRAME_SIZE)? {
+            Some(frame) => frame,
+            None => {
+                if read_more(&mut stream, &mut buf).await.is_err() {
+                    return Ok(());
+                }
+                continue;
+            }
+        };
+
+        let reply = match frame.opcode {
+            OpCode::Ping => encode(true, false, OpCode::Pong, &frame.payload),
+            OpCode::Pong => continue,
+            OpCode::Close if session.ignore_close => continue,
+            OpCode::Close if close_sent => return Ok(()),
+            OpCode::Close => {
+                write(&mut stream, encode(true, false, OpCode::Close, &frame.payload)).await?;
+                return Ok(());
+            }
+            OpCode::Text if frame.fin && !frame.rsv1 && frame.payload.as_ref() == b"close" => {
+                close_sent = true;
+                encode(true, false, OpCode::Close, b"\x03\xe8bye")
+            }
+            opcode => encode(frame.fin, frame.rsv1, opcode, &frame.payload),
+        };
+        write(&mut stream, reply).await?;
+    }
+}
+
+/// Encodes a server frame, which is never masked.
+fn encode(fin: bool, rsv1: bool, opcode: OpCode, payload: &[u8]) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(payload.len() + 10);
+    buf.put_u8((fin as u8) << 7 | (rsv1 as u8) << 6 | opcode.as_u8());
+
+    let len = payload.len();
+    if len < 126 {
+        buf.put_u8(len as u8);
+    } else if len <= u16::MAX as usize {
+        buf.put_u8(126);
+        buf.put_u16(len as u16);
+    } else {
+        buf.put_u8(127);
+        buf.put_u64(len as u64);
+    }
+
+    buf.put_slice(payload);
+    buf
+}
+
+async fn write<S: AsyncWriteRent>(stream: &mut S, frame: BytesMut) -> Result<()> {
+    let (res, _) = stream.write_all(frame).await;
+    res.map_err(Error::IoError)?;
+    stream.flush().await.map_err(Error::IoError)
+}
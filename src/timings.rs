@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+/// Durations of the phases of a request exchange, available through `HttpResponse::extensions()`
+/// for responses received over the pooled and dedicated connections of both clients.
+/// Connection phases are `None` when the request went over a reused connection.
+/// # Examples
+/// ```
+/// let response = client.make_request().set_uri("https://example.com").send().await?;
+/// if let Some(timings) = response.extensions().get::<RequestTimings>() {
+///     println!("dns {:?} connect {:?} tls {:?} ttfb {:?}", timings.dns(), timings.connect(), timings.tls(), timings.ttfb());
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RequestTimings {
+    pub(crate) dns: Option<Duration>,
+    pub(crate) connect: Option<Duration>,
+    pub(crate) tls: Option<Duration>,
+    pub(crate) pool_wait: Duration,
+    pub(crate) ttfb: Duration,
+    pub(crate) total: Duration,
+    pub(crate) reused: bool,
+}
+
+impl RequestTimings {
+    /// Time spent resolving the host name.
+    pub fn dns(&self) -> Option<Duration> {
+        self.dns
+    }
+
+    /// Time spent establishing the TCP connection.
+    pub fn connect(&self) -> Option<Duration> {
+        self.connect
+    }
+
+    /// Time spent in the TLS handshake.
+    pub fn tls(&self) -> Option<Duration> {
+        self.tls
+    }
+
+    /// Time spent waiting for a connection from the pool, besides dialing a new one.
+    pub fn pool_wait(&self) -> Duration {
+        self.pool_wait
+    }
+
+    /// Time from the start of the exchange until the response head was received.
+    pub fn ttfb(&self) -> Duration {
+        self.ttfb
+    }
+
+    /// Time from the start of the exchange until the response was handed over.
+    /// It covers the body when the client reads it before returning the response, as the Hyper client
+    /// and requests over dedicated connections do. Streamed bodies are read afterwards.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Whether the request was sent over a connection kept from an earlier request.
+    pub fn reused(&self) -> bool {
+        self.reused
+    }
+}
@@ -0,0 +1,344 @@
+use std::cell::{Cell, RefCell};
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::pin::pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tracing")]
+use http::uri::Scheme;
+use http::{Extensions, Method, StatusCode, Uri, Version};
+use monoio::net::TcpStream;
+use monoio_transports::connectors::{Connector, TcpConnector};
+
+#[cfg(not(feature = "hyper-tls"))]
+use crate::http::raw::RawResponse;
+use crate::error::Error;
+use crate::timings::RequestTimings;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// Stands in for `tracing::Span` without the tracing feature, nothing is recorded.
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Debug)]
+pub(crate) struct Span;
+
+/// Phases of a request exchange, each reported as a span named after it and timed
+/// into the `RequestTimings` of the response. The pooled connections write the request and wait for the response head in one step,
+/// reported as `time_to_first_byte`. Dedicated HTTP/1.1 connections report both phases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "hyper-tls", allow(dead_code))]
+pub(crate) enum Phase {
+    Resolve,
+    Connect,
+    TlsHandshake,
+    PoolCheckout,
+    RequestWrite,
+    FirstByte,
+    BodyRead,
+}
+
+/// Span of a request exchange, carried in the response extensions
+/// so the body read is reported under it.
+#[derive(Clone, Debug)]
+pub(crate) struct RequestSpan(pub(crate) Span);
+
+thread_local! {
+    static CURRENT: RefCell<Option<Rc<Exchange>>> = const { RefCell::new(None) };
+}
+
+/// State of the request exchange being polled, reachable from the connectors
+/// the connection pool dials with.
+struct Exchange {
+    started: Instant,
+    handshake: RefCell<Option<(Instant, Span)>>,
+    timings: Cell<RequestTimings>,
+    checkout: Cell<Option<Duration>>,
+}
+
+impl Exchange {
+    fn new() -> Self {
+        Exchange {
+            started: Instant::now(),
+            handshake: RefCell::new(None),
+            timings: Cell::new(RequestTimings::default()),
+            checkout: Cell::new(None),
+        }
+    }
+
+    fn current() -> Option<Rc<Exchange>> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Adds the duration of a phase, phases repeated by a protocol switch add up.
+    fn record(&self, phase: Phase, elapsed: Duration) {
+        let add = |total: Option<Duration>| Some(total.unwrap_or_default() + elapsed);
+        let mut timings = self.timings.get();
+        match phase {
+            Phase::Resolve => timings.dns = add(timings.dns),
+            Phase::Connect => timings.connect = add(timings.connect),
+            Phase::TlsHandshake => timings.tls = add(timings.tls),
+            Phase::PoolCheckout => self.checkout.set(add(self.checkout.get())),
+            Phase::FirstByte => timings.ttfb = self.started.elapsed(),
+            Phase::RequestWrite | Phase::BodyRead => {}
+        }
+        self.timings.set(timings);
+    }
+
+    fn timings(&self) -> RequestTimings {
+        let mut timings = self.timings.get();
+        // Connections dialed for the request have their connect phase timed
+        timings.reused = self.checkout.get().is_some() && timings.connect.is_none();
+        let dial = [timings.dns, timings.connect, timings.tls]
+            .into_iter()
+            .flatten()
+            .sum();
+        timings.pool_wait = self.checkout.get().unwrap_or_default().saturating_sub(dial);
+        timings.total = self.started.elapsed();
+        timings
+    }
+
+    /// Polls `fut` with this exchange as the current one. Tasks of a monoio runtime
+    /// share its thread, the exchange is swapped in and out around each poll.
+    async fn scope<F: Future>(self: Rc<Self>, fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        poll_fn(|cx| {
+            let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+            let poll = fut.as_mut().poll(cx);
+            CURRENT.with(|current| *current.borrow_mut() = previous);
+            poll
+        })
+        .await
+    }
+}
+
+/// Traces and times one request exchange, from the connection checkout to the response head.
+pub(crate) struct RequestTrace {
+    span: Span,
+    exchange: Rc<Exchange>,
+}
+
+impl RequestTrace {
+    pub(crate) fn new(method: &Method, uri: &Uri) -> Self {
+        RequestTrace {
+            span: request_span(method, uri),
+            exchange: Rc::new(Exchange::new()),
+        }
+    }
+
+    /// Runs the exchange, recording the response status, protocol and timings or the error.
+    pub(crate) async fn response<B, F>(self, fut: F) -> Result<http::Response<B>, Error>
+        where
+            F: Future<Output = Result<http::Response<B>, Error>>,
+    {
+        match self.run(fut).await {
+            Ok(mut response) => {
+                let (status, version) = (response.status(), response.version());
+                self.finish(status, version, response.extensions_mut());
+                Ok(response)
+            }
+            Err(e) => {
+                self.fail(&e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs an exchange over a dedicated HTTP/1.1 connection.
+    #[cfg(not(feature = "hyper-tls"))]
+    pub(crate) async fn raw<F>(self, fut: F) -> Result<RawResponse, Error>
+        where
+            F: Future<Output = Result<RawResponse, Error>>,
+    {
+        match self.run(fut).await {
+            Ok(mut raw) => {
+                let (status, version) = (raw.parts.status, raw.parts.version);
+                self.finish(status, version, &mut raw.parts.extensions);
+                Ok(raw)
+            }
+            Err(e) => {
+                self.fail(&e);
+                Err(e)
+            }
+        }
+    }
+
+    async fn run<F: Future>(&self, fut: F) -> F::Output {
+        instrument(self.span.clone(), self.exchange.clone().scope(fut)).await
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn finish(self, status: StatusCode, version: Version, extensions: &mut Extensions) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("http.response.status_code", status.as_u16());
+            self.span.record("network.protocol.version", protocol_version(version));
+            if status.is_client_error() || status.is_server_error() {
+                self.span.record("error.type", status.as_str());
+            }
+        }
+        extensions.insert(self.exchange.timings());
+        extensions.insert(RequestSpan(self.span));
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn fail(&self, err: &Error) {
+        #[cfg(feature = "tracing")]
+        self.span.record("error.type", error_type(err));
+    }
+}
+
+/// Runs `fut` as one phase of the current request exchange.
+pub(crate) async fn phase<F: Future>(phase: Phase, fut: F) -> F::Output {
+    let started = Instant::now();
+    let output = instrument(phase_span(phase), fut).await;
+    if let Some(exchange) = Exchange::current() {
+        exchange.record(phase, started.elapsed());
+    }
+    output
+}
+
+/// Checks a connection out of a pool, dialing a new one if none is idle.
+pub(crate) async fn checkout<F: Future>(fut: F) -> F::Output {
+    let output = phase(Phase::PoolCheckout, fut).await;
+    finish_handshake();
+    output
+}
+
+/// Ends the TLS handshake phase started when the TCP connection was established.
+pub(crate) fn finish_handshake() {
+    if let Some(exchange) = Exchange::current() {
+        let handshake = exchange.handshake.borrow_mut().take();
+        if let Some((started, _span)) = handshake {
+            exchange.record(Phase::TlsHandshake, started.elapsed());
+        }
+    }
+}
+
+/// Reads a response body, reported under the span of its request exchange when known.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) async fn body_read<F: Future>(request: Option<&Span>, fut: F) -> F::Output {
+    #[cfg(feature = "tracing")]
+    let span = match request {
+        Some(parent) => tracing::debug_span!(parent: parent, "body_read"),
+        None => phase_span(Phase::BodyRead),
+    };
+    #[cfg(not(feature = "tracing"))]
+    let span = Span;
+
+    instrument(span, fut).await
+}
+
+/// TCP connector reporting the name resolution and connect phases of new connections,
+/// and the start of the TLS handshake following them on TLS connections.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PhaseConnector {
+    tcp: TcpConnector,
+    tls: bool,
+}
+
+impl PhaseConnector {
+    /// Connector for the TCP connections a TLS connector wraps.
+    pub(crate) fn for_tls() -> Self {
+        PhaseConnector {
+            tls: true,
+            ..PhaseConnector::default()
+        }
+    }
+}
+
+impl<T: ToSocketAddrs> Connector<T> for PhaseConnector {
+    type Connection = TcpStream;
+    type Error = io::Error;
+
+    async fn connect(&self, key: T) -> Result<Self::Connection, Self::Error> {
+        // Resolving blocks as it would within the TCP connector, it is only split out to be reported
+        let addrs: Vec<SocketAddr> = phase(Phase::Resolve, async { key.to_socket_addrs() })
+            .await?
+            .collect();
+        let stream = phase(Phase::Connect, self.tcp.connect(addrs.as_slice())).await?;
+
+        if self.tls {
+            if let Some(exchange) = Exchange::current() {
+                *exchange.handshake.borrow_mut() = Some((Instant::now(), phase_span(Phase::TlsHandshake)));
+            }
+        }
+        Ok(stream)
+    }
+}
+
+#[cfg(feature = "tracing")]
+async fn instrument<F: Future>(span: Span, fut: F) -> F::Output {
+    tracing::Instrument::instrument(fut, span).await
+}
+
+#[cfg(not(feature = "tracing"))]
+async fn instrument<F: Future>(_: Span, fut: F) -> F::Output {
+    fut.await
+}
+
+/// Span of a request exchange, with the OpenTelemetry semantic convention attributes of HTTP clients.
+#[cfg(feature = "tracing")]
+fn request_span(method: &Method, uri: &Uri) -> Span {
+    let default_port = if uri.scheme() == Some(&Scheme::HTTPS) { 443 } else { 80 };
+    tracing::info_span!(
+        "http.client.request",
+        otel.name = %method,
+        otel.kind = "client",
+        http.request.method = %method,
+        url.full = %uri,
+        server.address = uri.host().unwrap_or_default(),
+        server.port = uri.port_u16().unwrap_or(default_port),
+        network.protocol.version = tracing::field::Empty,
+        http.response.status_code = tracing::field::Empty,
+        error.type = tracing::field::Empty,
+    )
+}
+
+#[cfg(not(feature = "tracing"))]
+fn request_span(_: &Method, _: &Uri) -> Span {
+    Span
+}
+
+#[cfg(feature = "tracing")]
+fn phase_span(phase: Phase) -> Span {
+    match phase {
+        Phase::Resolve => tracing::debug_span!("resolve"),
+        Phase::Connect => tracing::debug_span!("connect"),
+        Phase::TlsHandshake => tracing::debug_span!("tls_handshake"),
+        Phase::PoolCheckout => tracing::debug_span!("pool_checkout"),
+        Phase::RequestWrite => tracing::debug_span!("request_write"),
+        Phase::FirstByte => tracing::debug_span!("time_to_first_byte"),
+        Phase::BodyRead => tracing::debug_span!("body_read"),
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+fn phase_span(_: Phase) -> Span {
+    Span
+}
+
+#[cfg(feature = "tracing")]
+fn protocol_version(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "1.1",
+    }
+}
+
+/// Low cardinality `error.type` of a failed exchange.
+#[cfg(feature = "tracing")]
+fn error_type(err: &Error) -> &'static str {
+    match err {
+        Error::TransportError(_) | Error::UriKeyError(_) => "connect",
+        Error::IoError(_) => "io",
+        Error::ResponseBodyTooLarge(_) | Error::ResponseHeadersTooLarge(_) => "response_limit",
+        Error::HttpResponseHeadError(_) | Error::BytesError(_) => "protocol",
+        _ => "_OTHER",
+    }
+}
@@ -0,0 +1,369 @@
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE};
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, Uri, Version};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// How a `Cassette` uses its file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VcrMode {
+    /// Sends every request over the network and records the exchanges, replacing the cassette file.
+    Record,
+    /// Answers requests from the cassette only, a request matching no recorded exchange fails.
+    Replay,
+    /// Replays the matching recorded exchanges, sends the other requests over the network
+    /// and appends their exchanges to the cassette file.
+    Auto,
+}
+
+/// Part of a request compared against the recorded requests when replaying.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatchRule {
+    Method,
+    /// The full URI, scheme and authority included.
+    Uri,
+    Path,
+    /// The query parameters, regardless of their order.
+    Query,
+    /// The body, JSON bodies are compared regardless of formatting and key order.
+    Body,
+    /// The values of a header, after redaction.
+    Header(HeaderName),
+}
+
+/// Request/response exchanges recorded to a JSON file and served back in later runs,
+/// for deterministic tests of code calling external APIs.
+/// Requests match on their method and URI by default, each recorded exchange is replayed once
+/// in recording order. The values of the `Authorization`, `Proxy-Authorization`, `Cookie` and
+/// `Set-Cookie` headers are redacted before anything is written to the file.
+/// Response bodies are read in full before being recorded, so download progress is not reported
+/// while recording.
+/// Available only on crate feature vcr
+/// # Examples
+/// ```
+/// let cassette = Cassette::new("tests/cassettes/users.json", VcrMode::Auto)?
+///     .match_on([MatchRule::Method, MatchRule::Path, MatchRule::Query])
+///     .redact_header("x-api-key");
+///
+/// let client = MonoioClient::builder().enable_https().cassette(cassette).build();
+/// let users = client.make_request().set_uri("https://api.example.com/users?page=1").send().await?;
+/// ```
+#[derive(Clone)]
+pub struct Cassette {
+    state: Rc<RefCell<CassetteState>>,
+}
+
+struct CassetteState {
+    path: PathBuf,
+    mode: VcrMode,
+    rules: Vec<MatchRule>,
+    redacted: Vec<HeaderName>,
+    allow_repeats: bool,
+    interactions: Vec<Interaction>,
+    played: Vec<bool>,
+}
+
+impl Cassette {
+    /// Opens the cassette at `path`. The file must exist to replay, it is created once
+    /// an exchange is recorded.
+    pub fn new(path: impl Into<PathBuf>, mode: VcrMode) -> Result<Self, Error> {
+        let path = path.into();
+        let interactions = match mode {
+            VcrMode::Record => Vec::new(),
+            VcrMode::Replay => load(&path)?,
+            VcrMode::Auto if path.exists() => load(&path)?,
+            VcrMode::Auto => Vec::new(),
+        };
+
+        let state = CassetteState {
+            path,
+            mode,
+            rules: vec![MatchRule::Method, MatchRule::Uri],
+            redacted: vec![AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE],
+            allow_repeats: false,
+            played: vec![false; interactions.len()],
+            interactions,
+        };
+        Ok(Cassette {
+            state: Rc::new(RefCell::new(state)),
+        })
+    }
+
+    /// Replaces the rules requests are matched on.
+    pub fn match_on(self, rules: impl IntoIterator<Item = MatchRule>) -> Self {
+        self.state.borrow_mut().rules = rules.into_iter().collect();
+        self
+    }
+
+    /// Redacts the values of the `name` header in recorded requests and responses.
+    /// Invalid header names are ignored.
+    pub fn redact_header<K>(self, name: K) -> Self
+        where
+            HeaderName: TryFrom<K>,
+    {
+        if let Ok(name) = HeaderName::try_from(name) {
+            self.state.borrow_mut().redacted.push(name);
+        }
+        self
+    }
+
+    /// Replays a recorded exchange for every matching request instead of once.
+    pub fn allow_playback_repeats(self) -> Self {
+        self.state.borrow_mut().allow_repeats = true;
+        self
+    }
+
+    /// Returns the number of recorded exchanges.
+    pub fn len(&self) -> usize {
+        self.state.borrow().interactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Answers `req` from the recorded exchanges, `None` if it has to go over the network.
+    pub(crate) fn replay<B>(&self, req: &Request<B>, uri: &Uri, body: Option<&Bytes>) -> Result<Option<Response<Bytes>>, Error> {
+        let mut state = self.state.borrow_mut();
+        if state.mode == VcrMode::Record {
+            return Ok(None);
+        }
+
+        let request = RecordedRequest::new(req, uri, body, &state.redacted);
+        let state = &mut *state;
+        let matched = state
+            .interactions
+            .iter()
+            .enumerate()
+            .find(|(i, interaction)| {
+                (state.allow_repeats || !state.played[*i]) && interaction.request.matches(&request, &state.rules)
+            })
+            .map(|(i, interaction)| (i, interaction.response.to_response()));
+
+        match matched {
+            Some((i, response)) => {
+                state.played[i] = true;
+                response.map(Some)
+            }
+            None if state.mode == VcrMode::Replay => Err(Error::CassetteError(format!(
+                "no recorded exchange matches {} {uri}",
+                req.method()
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Starts recording the exchange of `req`, sent over the network.
+    pub(crate) fn recording<B>(&self, req: &Request<B>, uri: &Uri, body: Option<&Bytes>) -> Recording {
+        let request = RecordedRequest::new(req, uri, body, &self.state.borrow().redacted);
+        Recording {
+            cassette: self.clone(),
+            request,
+        }
+    }
+
+    fn record(&self, interaction: Interaction) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        state.interactions.push(interaction);
+        state.played.push(true);
+
+        let file = CassetteFile {
+            interactions: state.interactions.clone(),
+        };
+        let data = serde_json::to_vec_pretty(&file).map_err(Error::SerdeSerializeError)?;
+        if let Some(dir) = state.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(Error::IoError)?;
+        }
+        std::fs::write(&state.path, data).map_err(Error::IoError)
+    }
+}
+
+impl Debug for Cassette {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("Cassette")
+            .field("path", &state.path)
+            .field("mode", &state.mode)
+            .field("interactions", &state.interactions.len())
+            .finish()
+    }
+}
+
+/// An exchange being recorded, completed once the response body was read.
+pub(crate) struct Recording {
+    cassette: Cassette,
+    request: RecordedRequest,
+}
+
+impl Recording {
+    pub(crate) fn finish(self, status: StatusCode, version: Version, headers: &HeaderMap, body: &Bytes) -> Result<(), Error> {
+        let redacted = self.cassette.state.borrow().redacted.clone();
+        let response = RecordedResponse {
+            status: status.as_u16(),
+            version: format!("{version:?}"),
+            headers: recorded_headers(headers, &redacted),
+            body: CassetteBody::new(body),
+        };
+        self.cassette.record(Interaction {
+            request: self.request,
+            response,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CassetteFile {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+    body: CassetteBody,
+}
+
+impl RecordedRequest {
+    fn new<B>(req: &Request<B>, uri: &Uri, body: Option<&Bytes>, redacted: &[HeaderName]) -> Self {
+        RecordedRequest {
+            method: req.method().to_string(),
+            uri: uri.to_string(),
+            headers: recorded_headers(req.headers(), redacted),
+            body: CassetteBody::new(body.map_or(&[][..], |body| body.as_ref())),
+        }
+    }
+
+    fn matches(&self, other: &RecordedRequest, rules: &[MatchRule]) -> bool {
+        let uri = |request: &RecordedRequest| request.uri.parse::<Uri>().ok();
+        rules.iter().all(|rule| match rule {
+            MatchRule::Method => self.method == other.method,
+            MatchRule::Uri => self.uri == other.uri,
+            MatchRule::Path => uri(self).map(|u| u.path().to_string()) == uri(other).map(|u| u.path().to_string()),
+            MatchRule::Query => {
+                let query = |request: &RecordedRequest| {
+                    let mut pairs: Vec<String> = uri(request)
+                        .and_then(|u| u.query().map(str::to_string))
+                        .unwrap_or_default()
+                        .split('&')
+                        .filter(|pair| !pair.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    pairs.sort();
+                    pairs
+                };
+                query(self) == query(other)
+            }
+            MatchRule::Body => self.body.matches(&other.body),
+            MatchRule::Header(name) => {
+                let values = |request: &RecordedRequest| -> Vec<String> {
+                    request
+                        .headers
+                        .iter()
+                        .filter(|(header, _)| header.eq_ignore_ascii_case(name.as_str()))
+                        .map(|(_, value)| value.clone())
+                        .collect()
+                };
+                values(self) == values(other)
+            }
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    version: String,
+    headers: Vec<(String, String)>,
+    body: CassetteBody,
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<Response<Bytes>, Error> {
+        let invalid = |what: &str| Error::CassetteError(format!("invalid recorded response {what}"));
+        let mut response = Response::new(self.body.to_bytes().ok_or_else(|| invalid("body"))?);
+        *response.status_mut() = StatusCode::from_u16(self.status).map_err(|_| invalid("status"))?;
+        *response.version_mut() = match self.version.as_str() {
+            "HTTP/1.0" => Version::HTTP_10,
+            "HTTP/2.0" => Version::HTTP_2,
+            "HTTP/3.0" => Version::HTTP_3,
+            _ => Version::HTTP_11,
+        };
+        for (name, value) in self.headers.iter() {
+            let name = HeaderName::try_from(name.as_str()).map_err(|_| invalid("header name"))?;
+            let value = HeaderValue::try_from(value.as_str()).map_err(|_| invalid("header value"))?;
+            response.headers_mut().append(name, value);
+        }
+        Ok(response)
+    }
+}
+
+/// Body of a recorded message, kept as text when it is valid UTF-8.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "encoding", content = "data", rename_all = "lowercase")]
+enum CassetteBody {
+    Utf8(String),
+    Base64(String),
+}
+
+impl CassetteBody {
+    fn new(body: &[u8]) -> Self {
+        match std::str::from_utf8(body) {
+            Ok(text) => CassetteBody::Utf8(text.to_string()),
+            Err(_) => CassetteBody::Base64(STANDARD.encode(body)),
+        }
+    }
+
+    fn to_bytes(&self) -> Option<Bytes> {
+        match self {
+            CassetteBody::Utf8(text) => Some(Bytes::from(text.clone())),
+            CassetteBody::Base64(data) => STANDARD.decode(data).ok().map(Bytes::from),
+        }
+    }
+
+    fn matches(&self, other: &CassetteBody) -> bool {
+        let (Some(this), Some(other)) = (self.to_bytes(), other.to_bytes()) else {
+            return false;
+        };
+        let json = |body: &Bytes| serde_json::from_slice::<serde_json::Value>(body).ok();
+        match (json(&this), json(&other)) {
+            (Some(this), Some(other)) => this == other,
+            _ => this == other,
+        }
+    }
+}
+
+fn recorded_headers(headers: &HeaderMap, redacted: &[HeaderName]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = match redacted.contains(name) {
+                true => REDACTED.to_string(),
+                false => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+fn load(path: &Path) -> Result<Vec<Interaction>, Error> {
+    let data = std::fs::read(path).map_err(Error::IoError)?;
+    let file: CassetteFile = serde_json::from_slice(&data).map_err(Error::SerdeDeserializeError)?;
+    Ok(file.interactions)
+}
@@ -0,0 +1,1110 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::header::{HeaderName, AUTHORIZATION, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, RANGE};
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, Version};
+use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+use monoio_netreq::auth::{TokenFuture, TokenProvider};
+use monoio_netreq::batch::join_all_limited;
+use monoio_netreq::circuit::{CircuitBreaker, CircuitState};
+use monoio_netreq::download::DownloadOptions;
+use monoio_netreq::error::Error;
+use monoio_netreq::hedge::HedgePolicy;
+use monoio_netreq::http::client::MonoioClient;
+use monoio_netreq::propagation::{B3Propagator, TraceContext, W3CTraceContext};
+use monoio_netreq::rate_limit::{Rate, RateLimiter};
+use monoio_netreq::testing::{ResponseDelay, TestServer};
+use monoio_netreq::timings::RequestTimings;
+use serde_json::Value;
+
+async fn echo(client: &MonoioClient, url: String) -> Value {
+    let response = client.make_request().set_uri(url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+fn tls_client(server: &TestServer) -> monoio_netreq::http::client::ClientBuilder {
+    MonoioClient::builder()
+        .enable_https()
+        .add_root_certificate(server.certificate_der().unwrap())
+}
+
+#[monoio::test(timer = true)]
+async fn http1_plain() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().http1_only().build();
+
+    let response = client.make_request().set_uri(server.url("/echo?a=1")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["method"], "GET");
+    assert_eq!(body["path"], "/echo");
+    assert_eq!(body["query"], "a=1");
+    assert_eq!(body["version"], "HTTP/1.1");
+}
+
+#[monoio::test(timer = true)]
+async fn http2_prior_knowledge_plain() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().http2_prior_knowledge().build();
+
+    let response = client.make_request().set_uri(server.url("/echo")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["version"], "HTTP/2.0");
+}
+
+#[monoio::test(timer = true)]
+async fn auto_protocol_plain_uses_http1() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client.make_request().set_uri(server.url("/")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+}
+
+#[monoio::test(timer = true)]
+async fn tls_negotiates_http2() {
+    let server = TestServer::builder().tls().start().unwrap();
+    let client = tls_client(&server).build();
+
+    let response = client.make_request().set_uri(server.url("/echo")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert_eq!(client.known_protocol(&server.url("/").parse::<http::Uri>().unwrap()), Some(Version::HTTP_2));
+}
+
+#[monoio::test(timer = true)]
+async fn tls_http1_only() {
+    let server = TestServer::builder().tls().start().unwrap();
+    let client = tls_client(&server).http1_only().build();
+
+    let response = client.make_request().set_uri(server.url("/echo")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+}
+
+#[monoio::test(timer = true)]
+async fn tls_http2_prior_knowledge() {
+    let server = TestServer::builder().tls().start().unwrap();
+    let client = tls_client(&server).http2_prior_knowledge().build();
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/echo"))
+        .set_version(Version::HTTP_2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+}
+
+#[monoio::test(timer = true)]
+async fn tls_falls_back_to_http1() {
+    let server = TestServer::builder().tls().http1_only().start().unwrap();
+    let client = tls_client(&server).build();
+
+    let response = client.make_request().set_uri(server.url("/echo")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+}
+
+#[monoio::test(timer = true)]
+async fn untrusted_certificate_is_rejected() {
+    let server = TestServer::builder().tls().start().unwrap();
+    let client = MonoioClient::builder().enable_https().build();
+
+    let result = client.make_request().set_uri(server.url("/")).send().await;
+    assert!(result.is_err());
+    assert!(server.received_requests().is_empty());
+}
+
+#[monoio::test(timer = true)]
+async fn post_body_and_headers() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/submit"))
+        .set_header(CONTENT_TYPE, "application/json")
+        .set_header("x-request", "1")
+        .send_body(Bytes::from_static(br#"{"name":"monoio"}"#))
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["method"], "POST");
+    assert_eq!(body["body"], r#"{"name":"monoio"}"#);
+    assert_eq!(body["headers"]["x-request"], "1");
+
+    let received = server.received_requests();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].headers[CONTENT_TYPE], "application/json");
+}
+
+#[monoio::test(timer = true)]
+async fn default_headers_can_be_overridden() {
+    let server = TestServer::start().unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-default", HeaderValue::from_static("client"));
+    headers.insert("x-other", HeaderValue::from_static("client"));
+    let client = MonoioClient::builder().default_headers(headers).build();
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/"))
+        .set_header("x-other", "request")
+        .send()
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["headers"]["x-default"], "client");
+    assert_eq!(body["headers"]["x-other"], "request");
+}
+
+#[monoio::test(timer = true)]
+async fn status_and_text() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client.make_request().set_uri(server.url("/status/418")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::IM_A_TEAPOT);
+
+    let response = client.make_request().set_uri(server.url("/stream/3")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "line 0\nline 1\nline 2\n");
+}
+
+#[monoio::test(timer = true)]
+async fn connections_are_pooled() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().max_idle_connections(2).idle_connection_timeout(5).build();
+
+    for _ in 0..3 {
+        echo(&client, server.url("/")).await;
+    }
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[monoio::test(timer = true)]
+async fn read_timeout_fails_slow_responses() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().http1_only().set_read_timeout(1).build();
+
+    let result = client.make_request().set_uri(server.url("/delay/1500")).send().await;
+    assert!(result.is_err());
+}
+
+#[monoio::test(timer = true)]
+async fn concurrent_http2_streams_share_a_connection() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder()
+        .http2_prior_knowledge()
+        .initial_max_streams(8)
+        .max_concurrent_streams(8)
+        .build();
+
+    // Concurrent requests racing to the origin would each dial, open the connection first
+    echo(&client, server.url("/")).await;
+    let tasks: Vec<_> = (0..4)
+        .map(|i| {
+            let client = client.clone();
+            let url = server.url(&format!("/delay/{}", 50 + i * 10));
+            monoio::spawn(async move { echo(&client, url).await })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await["version"], "HTTP/2.0");
+    }
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[monoio::test(timer = true)]
+async fn response_body_size_limit() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().max_response_body_size(100).build();
+
+    let response = client.make_request().set_uri(server.url("/bytes/50")).send().await.unwrap();
+    assert_eq!(response.bytes().await.unwrap().len(), 50);
+
+    let result = async { client.make_request().set_uri(server.url("/bytes/1000")).send().await?.bytes().await }.await;
+    assert!(matches!(result, Err(Error::ResponseBodyTooLarge(100))));
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/bytes/1000"))
+        .set_max_response_body_size(2000)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.bytes().await.unwrap().len(), 1000);
+}
+
+#[monoio::test(timer = true)]
+async fn chunked_body_size_limit() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().max_response_body_size(100).build();
+
+    // Chunked bodies carry no Content-Length, the limit is enforced while reading
+    let result = async { client.make_request().set_uri(server.url("/stream/200")).send().await?.bytes().await }.await;
+    assert!(matches!(result, Err(Error::ResponseBodyTooLarge(100))));
+
+    let result = async {
+        let request = client.make_request().set_uri(server.url("/stream/200")).accept_trailers();
+        request.send().await?.bytes().await
+    }
+    .await;
+    assert!(matches!(result, Err(Error::ResponseBodyTooLarge(100))));
+}
+
+#[monoio::test(timer = true)]
+async fn response_header_limits() {
+    let server = TestServer::builder()
+        .handler(|_| {
+            let mut response = Response::new(Bytes::new());
+            for i in 0..10 {
+                let name = HeaderName::try_from(format!("x-header-{i}")).unwrap();
+                response.headers_mut().insert(name, HeaderValue::from_static("value"));
+            }
+            response
+        })
+        .start()
+        .unwrap();
+
+    let client = MonoioClient::builder().max_response_headers(5).build();
+    let result = client.make_request().set_uri(server.url("/")).send().await;
+    assert!(matches!(result, Err(Error::ResponseHeadersTooLarge(_))));
+
+    let client = MonoioClient::builder().max_response_header_size(64).build();
+    let result = client.make_request().set_uri(server.url("/")).send().await;
+    assert!(matches!(result, Err(Error::ResponseHeadersTooLarge(_))));
+
+    // The dedicated connections stop parsing at the same limits
+    let result = client.make_request().set_uri(server.url("/")).accept_trailers().send().await;
+    assert!(matches!(result, Err(Error::ResponseHeadersTooLarge(_))));
+
+    let client = MonoioClient::builder().max_response_headers(5).build();
+    let result = client.make_request().set_uri(server.url("/")).accept_trailers().send().await;
+    assert!(matches!(result, Err(Error::ResponseHeadersTooLarge(_))));
+}
+
+#[monoio::test(timer = true)]
+async fn expect_continue() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client
+        .make_request()
+        .set_method(Method::PUT)
+        .set_uri(server.url("/upload"))
+        .expect_continue()
+        .send_body(Bytes::from_static(b"payload"))
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["body"], "payload");
+    assert_eq!(body["headers"]["expect"], "100-continue");
+
+    let response = client
+        .make_request()
+        .set_method(Method::PUT)
+        .set_uri(server.url("/expect/reject"))
+        .expect_continue()
+        .send_body(Bytes::from_static(b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::EXPECTATION_FAILED);
+}
+
+#[monoio::test(timer = true)]
+async fn expect_continue_threshold() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder()
+        .expect_continue_threshold(16)
+        .expect_continue_timeout(Duration::from_millis(200))
+        .build();
+
+    for (body, expects) in [(&b"small"[..], false), (&[b'x'; 64][..], true)] {
+        client
+            .make_request()
+            .set_method(Method::POST)
+            .set_uri(server.url("/"))
+            .send_body(Bytes::copy_from_slice(body))
+            .await
+            .unwrap();
+        let received = server.received_requests().pop().unwrap();
+        assert_eq!(received.headers.contains_key("expect"), expects);
+        assert_eq!(received.body.as_ref(), body);
+    }
+}
+
+#[monoio::test(timer = true)]
+async fn expect_continue_requests_are_authenticated() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client
+        .make_request()
+        .set_method(Method::PUT)
+        .set_uri(server.url("/digest-auth/user/secret"))
+        .digest_auth("user", "secret")
+        .expect_continue()
+        .send_body(Bytes::from_static(b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let received = server.received_requests();
+    assert_eq!(received.len(), 2);
+    assert!(received.iter().all(|request| request.headers.contains_key("expect")));
+}
+
+#[monoio::test(timer = true)]
+async fn request_and_response_trailers() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/"))
+        .set_trailer(HeaderName::from_static("x-checksum"), HeaderValue::from_static("abc"))
+        .send_body(Bytes::from_static(b"body"))
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["trailers"]["x-checksum"], "abc");
+
+    let mut response = client
+        .make_request()
+        .set_uri(server.url("/trailers"))
+        .accept_trailers()
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.trailers().await.unwrap()["x-checksum"], "5d41402a");
+}
+
+#[monoio::test(timer = true)]
+async fn trailer_requests_are_guarded() {
+    let server = TestServer::start().unwrap();
+    let breaker = CircuitBreaker::new().consecutive_failures(1);
+    let client = MonoioClient::builder().circuit_breaker(breaker).build();
+
+    for status in ["/status/500", "/status/200"] {
+        let result = client
+            .make_request()
+            .set_uri(server.url(status))
+            .accept_trailers()
+            .send()
+            .await;
+        if status == "/status/500" {
+            assert_eq!(result.unwrap().status(), StatusCode::INTERNAL_SERVER_ERROR);
+        } else {
+            assert!(matches!(result, Err(Error::CircuitOpen(_))));
+        }
+    }
+    assert_eq!(server.received_requests().len(), 1);
+}
+
+#[monoio::test(timer = true)]
+async fn upgrade_takes_over_the_connection() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().token_provider(StaticToken).build();
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/upgrade"))
+        .set_header("connection", "upgrade")
+        .set_header("upgrade", "echo")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(server.received_requests().pop().unwrap().headers[AUTHORIZATION], "Bearer static");
+
+    let mut stream = response.upgrade().unwrap();
+    let (res, _) = stream.write_all(b"ping".to_vec()).await;
+    res.unwrap();
+    let (res, buf) = stream.read(Vec::with_capacity(16)).await;
+    assert_eq!(&buf[..res.unwrap()], b"ping");
+}
+
+#[cfg(feature = "websocket")]
+#[monoio::test(timer = true)]
+async fn websocket_messages_and_close_handshake() {
+    use monoio_netreq::http::websocket::{CloseFrame, Message};
+
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+    let mut ws = client
+        .websocket(server.url("/websocket"))
+        .protocol("chat")
+        .permessage_deflate()
+        .connect()
+        .await
+        .unwrap();
+    assert_eq!(ws.protocol(), Some("chat"));
+    assert!(ws.is_compressed());
+
+    ws.send(Message::Text("hello".to_string())).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Text("hello".to_string())));
+
+    // Fragments are echoed one by one and reassembled
+    let data = Bytes::from(vec![7; 100 * 1024]);
+    ws.send_fragmented(Message::Binary(data.clone()), 1000).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Binary(data)));
+
+    ws.ping(Bytes::from_static(b"beat")).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Pong(Bytes::from_static(b"beat"))));
+
+    ws.close(Some(CloseFrame::normal())).await.unwrap();
+
+    // A close started by the server is acknowledged
+    let mut ws = client.websocket(server.url("/websocket")).connect().await.unwrap();
+    assert!(!ws.is_compressed());
+    ws.send(Message::Text("close".to_string())).await.unwrap();
+    let close = CloseFrame { code: 1000, reason: "bye".to_string() };
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Close(Some(close))));
+    assert_eq!(ws.recv().await.unwrap(), None);
+}
+
+#[cfg(feature = "websocket")]
+#[monoio::test(timer = true)]
+async fn websocket_close_is_bounded_by_the_close_timeout() {
+    use monoio_netreq::http::websocket::{CloseFrame, Message};
+
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+    let mut ws = client
+        .websocket(server.url("/websocket?ignore-close"))
+        .close_timeout(Duration::from_millis(100))
+        .connect()
+        .await
+        .unwrap();
+
+    ws.send(Message::Text("hello".to_string())).await.unwrap();
+    assert_eq!(ws.recv().await.unwrap(), Some(Message::Text("hello".to_string())));
+
+    let result = ws.close(Some(CloseFrame::normal())).await;
+    assert!(matches!(result, Err(Error::WebSocketError(message)) if message.contains("not acknowledged")));
+}
+
+#[monoio::test(timer = true)]
+async fn server_sent_events() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let mut events = client.event_source(server.url("/sse"));
+    let first = events.next_event().await.unwrap().unwrap();
+    assert_eq!((first.event.as_str(), first.data.as_str()), ("greeting", "hello"));
+    let second = events.next_event().await.unwrap().unwrap();
+    assert_eq!((second.event.as_str(), second.data.as_str()), ("message", "world"));
+    assert_eq!(events.current_event_id(), Some("2"));
+    events.close();
+}
+
+#[monoio::test(timer = true)]
+async fn server_sent_events_are_bounded() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let mut events = client.event_source(server.url("/sse")).max_event_size(4);
+    assert!(matches!(events.next_event().await, Err(Error::SseError(_))));
+    assert!(events.next_event().await.unwrap().is_none());
+}
+
+struct StaticToken;
+
+impl TokenProvider for StaticToken {
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(async { Ok("static".to_string()) })
+    }
+
+    fn refresh(&self, _rejected: &str) -> TokenFuture<'_> {
+        self.token()
+    }
+}
+
+#[monoio::test(timer = true)]
+async fn event_source_requests_are_authenticated() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().token_provider(StaticToken).build();
+
+    let mut events = client.event_source(server.url("/sse"));
+    events.next_event().await.unwrap().unwrap();
+    let received = server.received_requests().pop().unwrap();
+    assert_eq!(received.headers[AUTHORIZATION], "Bearer static");
+}
+
+#[monoio::test(timer = true)]
+async fn event_source_returns_the_last_error_once_retries_are_exhausted() {
+    let client = MonoioClient::builder().build();
+
+    let mut events = client
+        .event_source("http://127.0.0.1:1/sse")
+        .retry_delay(Duration::from_millis(10))
+        .max_retries(2);
+    assert!(events.next_event().await.is_err());
+    assert!(events.next_event().await.unwrap().is_none());
+}
+
+#[monoio::test(timer = true)]
+async fn request_authentication() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/basic-auth/user/secret"))
+        .basic_auth("user", Some("secret"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/basic-auth/user/secret"))
+        .basic_auth("user", Some("wrong"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/bearer"))
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .make_request()
+        .set_uri(server.url("/digest-auth/user/secret"))
+        .digest_auth("user", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+struct RotatingToken {
+    refreshed: Cell<bool>,
+}
+
+impl TokenProvider for RotatingToken {
+    fn token(&self) -> TokenFuture<'_> {
+        let token = if self.refreshed.get() { "fresh" } else { "stale" };
+        Box::pin(async move { Ok(token.to_string()) })
+    }
+
+    fn refresh(&self, _rejected: &str) -> TokenFuture<'_> {
+        self.refreshed.set(true);
+        self.token()
+    }
+}
+
+#[monoio::test(timer = true)]
+async fn token_provider_refreshes_rejected_tokens() {
+    let server = TestServer::builder()
+        .handler(|request| {
+            let fresh = request.headers.get(AUTHORIZATION).is_some_and(|value| value == "Bearer fresh");
+            let mut response = Response::new(Bytes::new());
+            if !fresh {
+                *response.status_mut() = StatusCode::UNAUTHORIZED;
+            }
+            response
+        })
+        .start()
+        .unwrap();
+    let client = MonoioClient::builder()
+        .token_provider(RotatingToken { refreshed: Cell::new(false) })
+        .build();
+
+    let response = client.make_request().set_uri(server.url("/")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(server.received_requests().len(), 2);
+}
+
+#[monoio::test(timer = true)]
+async fn signer_sees_final_request() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder()
+        .signer(|parts: &mut http::request::Parts, body: &[u8]| {
+            let host = parts.headers.get(http::header::HOST).cloned();
+            parts.headers.insert("x-signed-host", host.unwrap_or(HeaderValue::from_static("")));
+            parts.headers.insert("x-signed-length", HeaderValue::from(body.len()));
+            Ok(())
+        })
+        .build();
+
+    let response = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/"))
+        .send_body(Bytes::from_static(b"12345"))
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["headers"]["x-signed-host"], server.addr().ip().to_string());
+    assert_eq!(body["headers"]["x-signed-length"], "5");
+}
+
+#[cfg(feature = "sigv4")]
+#[monoio::test(timer = true)]
+async fn sigv4_signed_requests() {
+    use std::time::UNIX_EPOCH;
+
+    use monoio_netreq::sign::SigV4Signer;
+
+    let server = TestServer::start().unwrap();
+    let signer = SigV4Signer::new("AKIDEXAMPLE", "secret", "us-east-1", "service")
+        .at(UNIX_EPOCH + Duration::from_secs(1_440_938_160));
+    let client = MonoioClient::builder().signer(signer).build();
+
+    let body = echo(&client, server.url("/")).await;
+    assert_eq!(body["headers"]["x-amz-date"], "20150830T123600Z");
+    let authorization = body["headers"]["authorization"].as_str().unwrap();
+    assert!(
+        authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, Signature="
+        ),
+        "{authorization}"
+    );
+}
+
+#[monoio::test(timer = true)]
+async fn save_to_file() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+    let path = std::env::temp_dir().join(format!("monoio-netreq-{}", server.addr().port()));
+
+    let response = client.make_request().set_uri(server.url("/bytes/2048")).send().await.unwrap();
+    assert_eq!(response.save_to(&path).await.unwrap(), 2048);
+    assert_eq!(std::fs::read(&path).unwrap().len(), 2048);
+    let _ = std::fs::remove_file(path);
+}
+
+/// Serves 100 bytes with a `"v2"` entity tag, honouring `Range` unless `If-Range` holds another tag.
+fn ranged_server() -> TestServer {
+    TestServer::builder()
+        .handler(|request| {
+            let content: Vec<u8> = (0..100).collect();
+            let start = request
+                .headers
+                .get(RANGE)
+                .and_then(|v| v.to_str().ok()?.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+            let current = request.headers.get(IF_RANGE).is_none_or(|v| v == "\"v2\"");
+            let mut response = match start {
+                Some(start) if current && start >= content.len() => {
+                    let mut response = Response::new(Bytes::new());
+                    *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                    response.headers_mut().insert(CONTENT_RANGE, HeaderValue::from_static("bytes */100"));
+                    response
+                }
+                Some(start) if current => {
+                    let mut response = Response::new(Bytes::from(content[start..].to_vec()));
+                    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+                    let range = format!("bytes {start}-99/100");
+                    response.headers_mut().insert(CONTENT_RANGE, HeaderValue::try_from(range).unwrap());
+                    response
+                }
+                _ => Response::new(Bytes::from(content)),
+            };
+            response.headers_mut().insert(ETAG, HeaderValue::from_static("\"v2\""));
+            response
+        })
+        .start()
+        .unwrap()
+}
+
+#[monoio::test(timer = true)]
+async fn resume_download_checks_the_partial_file() {
+    let server = ranged_server();
+    let client = MonoioClient::builder().build();
+    let path = std::env::temp_dir().join(format!("monoio-netreq-resume-{}", server.addr().port()));
+    let part = path.with_extension("part");
+    let validator = path.with_extension("part.validator");
+    let content: Vec<u8> = (0..100).collect();
+    let download = || client.make_request().set_uri(server.url("/file")).resume_download(&path, DownloadOptions::new());
+
+    // The partial file was started from the current content, the missing bytes are requested
+    std::fs::write(&part, &content[..40]).unwrap();
+    std::fs::write(&validator, "\"v2\"").unwrap();
+    assert_eq!(download().await.unwrap(), 100);
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert!(!validator.exists());
+    let received = server.received_requests().pop().unwrap();
+    assert_eq!(received.headers[RANGE], "bytes=40-");
+    assert_eq!(received.headers[IF_RANGE], "\"v2\"");
+
+    // The content changed since, the download starts over
+    std::fs::write(&part, [0xff; 40]).unwrap();
+    std::fs::write(&validator, "\"v1\"").unwrap();
+    assert_eq!(download().await.unwrap(), 100);
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+
+    // The partial file holds the complete content
+    std::fs::write(&part, &content).unwrap();
+    assert_eq!(download().await.unwrap(), 100);
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+
+    // The partial file is longer than the content
+    std::fs::write(&part, [0; 120]).unwrap();
+    assert!(matches!(download().await, Err(Error::UnexpectedStatus(StatusCode::RANGE_NOT_SATISFIABLE))));
+    assert!(!part.exists());
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[monoio::test(timer = true)]
+async fn trace_context_propagation() {
+    let server = TestServer::start().unwrap();
+    let context = TraceContext::new(1, 2, true);
+
+    let client = MonoioClient::builder().trace_propagation(W3CTraceContext).build();
+    let body = context.clone().scope(echo(&client, server.url("/"))).await;
+    assert_eq!(
+        body["headers"]["traceparent"],
+        "00-00000000000000000000000000000001-0000000000000002-01"
+    );
+    let body = echo(&client, server.url("/")).await;
+    assert!(body["headers"].get("traceparent").is_none());
+
+    let client = MonoioClient::builder()
+        .trace_propagation_with(B3Propagator::single_header(), move || Some(context.clone()))
+        .build();
+    let body = echo(&client, server.url("/")).await;
+    assert_eq!(body["headers"]["b3"], "00000000000000000000000000000001-0000000000000002-1");
+}
+
+#[monoio::test(timer = true)]
+async fn timings_report_reused_connections() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let mut reused = Vec::new();
+    for _ in 0..2 {
+        let response = client.make_request().set_uri(server.url("/")).send().await.unwrap();
+        let timings = *response.extensions().get::<RequestTimings>().unwrap();
+        assert!(timings.ttfb() <= timings.total());
+        reused.push(timings.reused());
+    }
+    assert_eq!(reused, [false, true]);
+}
+
+#[monoio::test(timer = true)]
+async fn progress_callbacks() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+    let uploaded = Rc::new(RefCell::new(Vec::new()));
+    let downloaded = Rc::new(Cell::new(0));
+
+    let (up, down) = (uploaded.clone(), downloaded.clone());
+    let response = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/bytes/4096"))
+        .on_upload_progress(move |sent, total| up.borrow_mut().push((sent, total)))
+        .on_download_progress(move |received, _| down.set(received))
+        .send_body(Bytes::from(vec![0; 200 * 1024]))
+        .await
+        .unwrap();
+    assert_eq!(response.bytes().await.unwrap().len(), 4096);
+    assert_eq!(downloaded.get(), 4096);
+
+    // The body is reported slice by slice as it is written
+    let total = Some(200 * 1024);
+    assert_eq!(
+        *uploaded.borrow(),
+        [(64 * 1024, total), (128 * 1024, total), (192 * 1024, total), (200 * 1024, total)]
+    );
+    assert_eq!(server.received_requests().pop().unwrap().body.len(), 200 * 1024);
+
+    // Chunked bodies carrying trailers as well
+    uploaded.borrow_mut().clear();
+    let up = uploaded.clone();
+    client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/"))
+        .set_trailer(HeaderName::from_static("x-checksum"), HeaderValue::from_static("abc"))
+        .on_upload_progress(move |sent, total| up.borrow_mut().push((sent, total)))
+        .send_body(Bytes::from(vec![1; 100 * 1024]))
+        .await
+        .unwrap();
+    assert_eq!(uploaded.borrow().len(), 2);
+    let received = server.received_requests().pop().unwrap();
+    assert_eq!(received.body.len(), 100 * 1024);
+    assert_eq!(received.trailers["x-checksum"], "abc");
+}
+
+#[monoio::test(timer = true)]
+async fn upload_progress_needs_http1() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().http2_prior_knowledge().build();
+
+    // The progress can't be reported over the pooled HTTP/2 connections
+    let result = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/"))
+        .on_upload_progress(|_, _| {})
+        .send_body(Bytes::from_static(b"body"))
+        .await;
+    assert!(matches!(result, Err(Error::HttpVersionMismatch(_))));
+    assert_eq!(server.connection_count(), 0);
+}
+
+#[monoio::test(timer = true)]
+async fn custom_handler_sees_requests() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let record = seen.clone();
+    let server = TestServer::builder()
+        .handler(move |request| {
+            record.borrow_mut().push(request.uri.to_string());
+            Response::new(Bytes::from_static(b"ok"))
+        })
+        .start()
+        .unwrap();
+    let client = MonoioClient::builder().build();
+
+    let response = client.make_request().set_uri(server.url("/a?b=c")).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "ok");
+    assert_eq!(*seen.borrow(), ["/a?b=c"]);
+}
+
+#[monoio::test(timer = true)]
+async fn batch_respects_limits_and_order() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().http1_only().build();
+
+    let started = std::time::Instant::now();
+    let results = client
+        .batch()
+        .max_concurrency(8)
+        .max_per_host(3)
+        .requests((0..6).map(|i| client.make_request().set_uri(server.url(&format!("/delay/100?i={i}")))))
+        .send()
+        .await;
+    let elapsed = started.elapsed();
+
+    // Six requests three at a time take two rounds
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(600), "{elapsed:?}");
+    assert_eq!(server.connection_count(), 3);
+    for (i, result) in results.into_iter().enumerate() {
+        let body: Value = result.unwrap().json().await.unwrap();
+        assert_eq!(body["query"], format!("i={i}"));
+    }
+}
+
+#[monoio::test(timer = true)]
+async fn batch_keeps_failures_in_place() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().build();
+
+    let results = client
+        .batch()
+        .request(client.make_request().set_uri(server.url("/status/200")))
+        .request(client.make_request().set_uri("http://127.0.0.1:1/"))
+        .request(client.make_request().set_uri(server.url("/status/201")))
+        .send()
+        .await;
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap().status(), StatusCode::OK);
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap().status(), StatusCode::CREATED);
+}
+
+#[monoio::test(timer = true)]
+async fn join_all_limited_runs_futures_in_order() {
+    let running = Rc::new(Cell::new(0));
+    let peak = Rc::new(Cell::new(0));
+
+    let outputs = join_all_limited(
+        (0..5).map(|i| {
+            let (running, peak) = (running.clone(), peak.clone());
+            async move {
+                running.set(running.get() + 1);
+                peak.set(peak.get().max(running.get()));
+                monoio::time::sleep(Duration::from_millis(10 * (5 - i))).await;
+                running.set(running.get() - 1);
+                i
+            }
+        }),
+        2,
+    )
+    .await;
+    assert_eq!(outputs, [0, 1, 2, 3, 4]);
+    assert_eq!(peak.get(), 2);
+}
+
+#[monoio::test(timer = true)]
+async fn rate_limiter_spaces_requests_per_host() {
+    let server = TestServer::start().unwrap();
+    let limiter = RateLimiter::new().per_host(Rate::per_second(20).burst(2));
+    let client = MonoioClient::builder().rate_limiter(limiter).build();
+
+    let started = std::time::Instant::now();
+    for _ in 0..6 {
+        client.make_request().set_uri(server.url("/")).send().await.unwrap();
+    }
+    // Two requests go out at once, the other four 50ms apart
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(600), "{elapsed:?}");
+}
+
+#[monoio::test(timer = true)]
+async fn adaptive_rate_limiter_waits_for_retry_after() {
+    let calls = Rc::new(Cell::new(0));
+    let server = TestServer::builder()
+        .handler({
+            let calls = calls.clone();
+            move |_| {
+                calls.set(calls.get() + 1);
+                let mut response = Response::new(Bytes::new());
+                if calls.get() == 1 {
+                    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    response.headers_mut().insert("retry-after", HeaderValue::from_static("1"));
+                }
+                response
+            }
+        })
+        .start()
+        .unwrap();
+    let client = MonoioClient::builder().rate_limiter(RateLimiter::new().adaptive()).build();
+
+    let response = client.make_request().set_uri(server.url("/")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let started = std::time::Instant::now();
+    let response = client.make_request().set_uri(server.url("/")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(started.elapsed() >= Duration::from_millis(900));
+}
+
+#[monoio::test(timer = true)]
+async fn circuit_breaker_opens_and_recovers() {
+    let server = TestServer::start().unwrap();
+    let transitions = Rc::new(RefCell::new(Vec::new()));
+    let breaker = CircuitBreaker::new()
+        .consecutive_failures(2)
+        .open_duration(Duration::from_millis(100))
+        .on_state_change({
+            let transitions = transitions.clone();
+            move |_, _, state| transitions.borrow_mut().push(state)
+        });
+    let client = MonoioClient::builder().circuit_breaker(breaker.clone()).build();
+
+    for _ in 0..2 {
+        let response = client.make_request().set_uri(server.url("/status/500")).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    let uri = server.url("/status/200").parse().unwrap();
+    assert_eq!(breaker.state(&uri), CircuitState::Open);
+
+    let result = client.make_request().set_uri(server.url("/status/200")).send().await;
+    assert!(matches!(result, Err(Error::CircuitOpen(_))));
+    assert_eq!(server.received_requests().len(), 2);
+
+    monoio::time::sleep(Duration::from_millis(150)).await;
+    let response = client.make_request().set_uri(server.url("/status/200")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(breaker.state(&uri), CircuitState::Closed);
+    assert_eq!(
+        *transitions.borrow(),
+        [CircuitState::Open, CircuitState::HalfOpen, CircuitState::Closed]
+    );
+
+    let snapshot = breaker.snapshot();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[0].consecutive_failures, 0);
+}
+
+#[monoio::test(timer = true)]
+async fn hedged_request_takes_the_faster_response() {
+    let calls = Rc::new(Cell::new(0));
+    let server = TestServer::builder()
+        .handler({
+            let calls = calls.clone();
+            move |_| {
+                calls.set(calls.get() + 1);
+                // Every other request is slow, starting with the first
+                if calls.get() % 2 == 1 {
+                    let mut response = Response::new(Bytes::from_static(b"slow"));
+                    response.extensions_mut().insert(ResponseDelay(Duration::from_millis(500)));
+                    response
+                } else {
+                    Response::new(Bytes::from_static(b"fast"))
+                }
+            }
+        })
+        .start()
+        .unwrap();
+    let client = MonoioClient::builder().http1_only().build();
+    let hedge = HedgePolicy::after(Duration::from_millis(50));
+
+    let started = std::time::Instant::now();
+    let response = client.make_request().set_uri(server.url("/")).hedge(hedge.clone()).send().await.unwrap();
+    assert_eq!(response.text().await.unwrap(), "fast");
+    assert!(started.elapsed() < Duration::from_millis(400));
+    assert_eq!(server.received_requests().len(), 2);
+
+    // Requests that aren't idempotent are sent once
+    let response = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/"))
+        .hedge(hedge)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "slow");
+    assert_eq!(server.received_requests().len(), 3);
+}
+
+#[cfg(feature = "cache")]
+#[monoio::test(timer = true)]
+async fn cache_serves_and_revalidates() {
+    use monoio_netreq::cache::{CacheStatus, HttpCache};
+
+    let server = TestServer::start().unwrap();
+    let client = MonoioClient::builder().with_cache(HttpCache::in_memory(16)).build();
+
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        let response = client.make_request().set_uri(server.url("/cache")).send().await.unwrap();
+        statuses.push(*response.extensions().get::<CacheStatus>().unwrap());
+        assert_eq!(response.text().await.unwrap(), "cached body");
+    }
+    assert_eq!(statuses, [CacheStatus::Miss, CacheStatus::Hit]);
+    assert_eq!(server.received_requests().len(), 1);
+}
+
+#[cfg(feature = "cache")]
+#[monoio::test(timer = true)]
+async fn disk_cache_survives_the_client() {
+    use monoio_netreq::cache::{CacheStatus, HttpCache};
+
+    let server = TestServer::start().unwrap();
+    let dir = std::env::temp_dir().join(format!("monoio-netreq-cache-{}", server.addr().port()));
+
+    let mut statuses = Vec::new();
+    for _ in 0..2 {
+        let client = MonoioClient::builder()
+            .with_cache(HttpCache::on_disk(&dir).unwrap())
+            .build();
+        let response = client.make_request().set_uri(server.url("/cache")).send().await.unwrap();
+        statuses.push(*response.extensions().get::<CacheStatus>().unwrap());
+        assert_eq!(response.text().await.unwrap(), "cached body");
+    }
+    assert_eq!(statuses, [CacheStatus::Miss, CacheStatus::Hit]);
+    assert_eq!(server.received_requests().len(), 1);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use http::header::{AUTHORIZATION, CONTENT_TYPE};
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, Uri, Version};
+use monoio_netreq::auth::{TokenFuture, TokenProvider};
+use monoio_netreq::error::Error;
+use monoio_netreq::grpc::{BytesCodec, Code, GrpcClient};
+use monoio_netreq::http::client::MonoioClient;
+use monoio_netreq::mock::{Expectation, MockResponse, MockTransport};
+use monoio_netreq::propagation::{TraceContext, W3CTraceContext};
+use monoio_netreq::testing::{ResponseTrailers, TestServer};
+
+struct StaticToken;
+
+impl TokenProvider for StaticToken {
+    fn token(&self) -> TokenFuture<'_> {
+        Box::pin(async { Ok("static".to_string()) })
+    }
+
+    fn refresh(&self, _rejected: &str) -> TokenFuture<'_> {
+        self.token()
+    }
+}
+
+/// Echoes the request messages back, ending the call with an OK status.
+fn echo_server() -> TestServer {
+    TestServer::builder()
+        .handler(|request| {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            let mut response = Response::new(request.body.clone());
+            response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+            response.extensions_mut().insert(ResponseTrailers(trailers));
+            response
+        })
+        .start()
+        .unwrap()
+}
+
+fn origin(server: &TestServer) -> Uri {
+    server.url("/").parse().unwrap()
+}
+
+#[monoio::test(timer = true)]
+async fn calls_go_through_the_client_pipeline() {
+    let server = echo_server();
+    let client = MonoioClient::builder()
+        .http2_prior_knowledge()
+        .token_provider(StaticToken)
+        .trace_propagation_with(W3CTraceContext, || Some(TraceContext::new(1, 2, true)))
+        .build();
+    let grpc = GrpcClient::new(client, origin(&server)).timeout(Duration::from_secs(5));
+
+    let reply = grpc
+        .unary("/echo.Echo/Say", &Bytes::from_static(b"hello"), BytesCodec)
+        .await
+        .unwrap();
+    assert_eq!(reply.as_ref(), b"hello");
+
+    let received = server.received_requests().pop().unwrap();
+    assert_eq!(received.version, Version::HTTP_2);
+    assert_eq!(received.headers[AUTHORIZATION], "Bearer static");
+    assert_eq!(received.headers["grpc-timeout"], "5000000u");
+    assert_eq!(
+        received.headers["traceparent"],
+        "00-00000000000000000000000000000001-0000000000000002-01"
+    );
+}
+
+#[monoio::test(timer = true)]
+async fn request_messages_are_fed_while_the_call_is_in_flight() {
+    let server = echo_server();
+    let client = MonoioClient::builder().http2_prior_knowledge().build();
+    let grpc = GrpcClient::new(client, origin(&server)).timeout(Duration::from_secs(5));
+
+    let (mut sender, mut stream) = grpc.streaming("/echo.Echo/Chat", BytesCodec).unwrap();
+    sender.send(&Bytes::from_static(b"one")).unwrap();
+    monoio::spawn(async move {
+        monoio::time::sleep(Duration::from_millis(50)).await;
+        sender.send(&Bytes::from_static(b"two")).unwrap();
+        sender.finish();
+    });
+
+    // The call was started before the second message was fed
+    assert_eq!(stream.message().await.unwrap().unwrap().as_ref(), b"one");
+    assert_eq!(stream.message().await.unwrap().unwrap().as_ref(), b"two");
+    assert!(stream.message().await.unwrap().is_none());
+    assert_eq!(stream.trailers().await.unwrap()["grpc-status"], "0");
+
+    let (mut sender, stream) = grpc.client_streaming("/echo.Echo/Single", BytesCodec).unwrap();
+    sender.send(&Bytes::from_static(b"only")).unwrap();
+    sender.finish();
+    assert_eq!(stream.single().await.unwrap().as_ref(), b"only");
+}
+
+#[monoio::test(timer = true)]
+async fn huge_timeouts_mean_no_deadline() {
+    let server = echo_server();
+    let client = MonoioClient::builder().http2_prior_knowledge().build();
+    let grpc = GrpcClient::new(client, origin(&server)).timeout(Duration::MAX);
+
+    let reply = grpc
+        .unary("/echo.Echo/Say", &Bytes::from_static(b"hello"), BytesCodec)
+        .await
+        .unwrap();
+    assert_eq!(reply.as_ref(), b"hello");
+}
+
+#[monoio::test(timer = true)]
+async fn http1_responses_are_rejected() {
+    let mock = MockTransport::new();
+    let reply = MockResponse::new(StatusCode::OK)
+        .version(Version::HTTP_11)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header("grpc-status", "0");
+    mock.register(Expectation::new(Method::POST, "/echo.Echo/Say").respond_with(reply));
+    let client = MonoioClient::builder().mock(mock).build();
+    let grpc = GrpcClient::new(client, Uri::from_static("http://grpc.local"));
+
+    let result = grpc.unary("/echo.Echo/Say", &Bytes::from_static(b"hello"), BytesCodec).await;
+    match result {
+        Err(Error::GrpcError(status)) => assert_eq!(status.code(), Code::Unavailable),
+        other => panic!("expected an HTTP/2 error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[cfg(feature = "hyper")]
+#[monoio::test(timer = true)]
+async fn hyper_calls_are_signed() {
+    use monoio_netreq::hyper::client::MonoioHyperClient;
+
+    let server = echo_server();
+    let client = MonoioHyperClient::builder()
+        .signer(|parts: &mut http::request::Parts, body: &[u8]| {
+            parts.headers.insert("x-signed-length", HeaderValue::from(body.len()));
+            Ok(())
+        })
+        .build();
+    let grpc = GrpcClient::new(client, origin(&server));
+
+    let reply = grpc
+        .unary("/echo.Echo/Say", &Bytes::from_static(b"hello"), BytesCodec)
+        .await
+        .unwrap();
+    assert_eq!(reply.as_ref(), b"hello");
+
+    let received = server.received_requests().pop().unwrap();
+    assert_eq!(received.version, Version::HTTP_2);
+    assert_eq!(received.headers["x-signed-length"], "10");
+}
+
+#[cfg(feature = "hyper")]
+#[monoio::test(timer = true)]
+async fn hyper_request_messages_are_streamed() {
+    use monoio_netreq::hyper::client::MonoioHyperClient;
+
+    let server = echo_server();
+    let client = MonoioHyperClient::builder().http2_prior_knowledge().build();
+    let grpc = GrpcClient::new(client, origin(&server));
+
+    let (mut sender, mut stream) = grpc.streaming("/echo.Echo/Chat", BytesCodec).unwrap();
+    sender.send(&Bytes::from_static(b"one")).unwrap();
+    monoio::spawn(async move {
+        monoio::time::sleep(Duration::from_millis(50)).await;
+        sender.send(&Bytes::from_static(b"two")).unwrap();
+    });
+
+    // Dropping the sender ends the request stream
+    assert_eq!(stream.message().await.unwrap().unwrap().as_ref(), b"one");
+    assert_eq!(stream.message().await.unwrap().unwrap().as_ref(), b"two");
+    assert!(stream.message().await.unwrap().is_none());
+}
@@ -0,0 +1,190 @@
+use std::cell::Cell;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use http::{StatusCode, Version};
+use monoio::net::udp::UdpSocket;
+use monoio_netreq::h3::Http3Config;
+use monoio_netreq::http::client::MonoioClient;
+use monoio_netreq::testing::TestServer;
+use quiche::h3::NameValue;
+
+const BODY: &[u8] = b"over quic";
+
+/// Writes a self-signed certificate and its key to PEM files quiche can load.
+fn certificate_files() -> (String, String) {
+    let generated = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("monoio-netreq-h3-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let cert = dir.join("cert.pem");
+    let key = dir.join("key.pem");
+    std::fs::write(&cert, generated.cert.pem()).unwrap();
+    std::fs::write(&key, generated.key_pair.serialize_pem()).unwrap();
+    (cert.to_str().unwrap().to_string(), key.to_str().unwrap().to_string())
+}
+
+fn server_config() -> quiche::Config {
+    let (cert, key) = certificate_files();
+    let mut config = quiche::Config::new(quiche::PROTOCOL_VERSION).unwrap();
+    config.load_cert_chain_from_pem_file(&cert).unwrap();
+    config.load_priv_key_from_pem_file(&key).unwrap();
+    config.set_application_protos(quiche::h3::APPLICATION_PROTOCOL).unwrap();
+    config.set_max_idle_timeout(5000);
+    config.set_max_recv_udp_payload_size(1350);
+    config.set_max_send_udp_payload_size(1350);
+    config.set_initial_max_data(10 * 1024 * 1024);
+    config.set_initial_max_stream_data_bidi_local(1024 * 1024);
+    config.set_initial_max_stream_data_bidi_remote(1024 * 1024);
+    config.set_initial_max_stream_data_uni(1024 * 1024);
+    config.set_initial_max_streams_bidi(100);
+    config.set_initial_max_streams_uni(100);
+    config.set_disable_active_migration(true);
+    config
+}
+
+struct Peer {
+    addr: SocketAddr,
+    quic: quiche::Connection,
+    h3: Option<quiche::h3::Connection>,
+}
+
+/// Starts a QUIC server on a local port answering every request with `BODY`,
+/// counting the connections and requests it accepts.
+fn start_quic_server(connections: Rc<Cell<usize>>, requests: Rc<Cell<usize>>) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let local = socket.local_addr().unwrap();
+    let mut config = server_config();
+    let h3_config = quiche::h3::Config::new().unwrap();
+
+    monoio::spawn(async move {
+        let mut peer: Option<Peer> = None;
+        let mut buf = vec![0u8; 65535];
+
+        loop {
+            let timeout = peer.as_ref().and_then(|peer| peer.quic.timeout());
+            let received = match timeout {
+                Some(timeout) => monoio::time::timeout(timeout, socket.recv_from(buf)).await.ok(),
+                None => Some(socket.recv_from(buf).await),
+            };
+
+            match received {
+                Some((res, mut data)) => {
+                    let (len, from) = res.unwrap();
+                    let open = peer.as_ref().is_some_and(|peer| !peer.quic.is_closed());
+                    if !open {
+                        let mut scid = [0u8; quiche::MAX_CONN_ID_LEN];
+                        scid.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8 ^ connections.get() as u8);
+                        let scid = quiche::ConnectionId::from_ref(&scid);
+                        let quic = quiche::accept(&scid, None, local, from, &mut config).unwrap();
+                        connections.set(connections.get() + 1);
+                        peer = Some(Peer { addr: from, quic, h3: None });
+                    }
+
+                    let peer = peer.as_mut().unwrap();
+                    let info = quiche::RecvInfo { from, to: local };
+                    let _ = peer.quic.recv(&mut data[..len], info);
+                    buf = data;
+                }
+                None => {
+                    if let Some(peer) = peer.as_mut() {
+                        peer.quic.on_timeout();
+                    }
+                    buf = vec![0u8; 65535];
+                }
+            }
+
+            let Some(peer) = peer.as_mut() else { continue };
+            if peer.quic.is_established() && peer.h3.is_none() {
+                peer.h3 = Some(quiche::h3::Connection::with_transport(&mut peer.quic, &h3_config).unwrap());
+            }
+            if let Some(h3) = peer.h3.as_mut() {
+                loop {
+                    match h3.poll(&mut peer.quic) {
+                        Ok((stream_id, quiche::h3::Event::Headers { list, .. })) => {
+                            assert!(list.iter().any(|h| h.name() == b":method" && h.value() == b"GET"));
+                            requests.set(requests.get() + 1);
+                            let headers = [
+                                quiche::h3::Header::new(b":status", b"200"),
+                                quiche::h3::Header::new(b"content-length", BODY.len().to_string().as_bytes()),
+                            ];
+                            h3.send_response(&mut peer.quic, stream_id, &headers, false).unwrap();
+                            h3.send_body(&mut peer.quic, stream_id, BODY, true).unwrap();
+                        }
+                        Ok((stream_id, quiche::h3::Event::Data)) => {
+                            let mut body = [0u8; 1024];
+                            while h3.recv_body(&mut peer.quic, stream_id, &mut body).is_ok() {}
+                        }
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+            }
+
+            loop {
+                let mut out = vec![0u8; 1350];
+                let Ok((len, _)) = peer.quic.send(&mut out) else { break };
+                out.truncate(len);
+                let (res, _) = socket.send_to(out, peer.addr).await;
+                res.unwrap();
+            }
+        }
+    });
+
+    local
+}
+
+#[monoio::test(timer = true)]
+async fn requests_go_over_quic_and_reuse_the_connection() {
+    let connections = Rc::new(Cell::new(0));
+    let requests = Rc::new(Cell::new(0));
+    let addr = start_quic_server(connections.clone(), requests.clone());
+    let client = MonoioClient::builder()
+        .enable_https()
+        .http3(Http3Config::new().prior_knowledge().danger_accept_invalid_certs())
+        .build();
+
+    for _ in 0..2 {
+        let response = client
+            .make_request()
+            .set_uri(format!("https://{addr}/"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), Version::HTTP_3);
+        assert_eq!(response.bytes().await.unwrap().as_ref(), BODY);
+    }
+
+    assert_eq!(requests.get(), 2);
+    assert_eq!(connections.get(), 1);
+}
+
+#[monoio::test(timer = true)]
+async fn unanswered_handshakes_fall_back_to_tcp() {
+    let server = TestServer::builder().tls().start().unwrap();
+    // Datagrams to the port of the TCP server are received but never answered
+    let _silent = UdpSocket::bind(server.addr()).unwrap();
+    let client = MonoioClient::builder()
+        .enable_https()
+        .add_root_certificate(server.certificate_der().unwrap())
+        .http3(
+            Http3Config::new()
+                .prior_knowledge()
+                .handshake_timeout(Duration::from_millis(200)),
+        )
+        .build();
+
+    let started = Instant::now();
+    let response = client
+        .make_request()
+        .set_uri(format!("https://127.0.0.1:{}/status/204", server.addr().port()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_ne!(response.version(), Version::HTTP_3);
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+}
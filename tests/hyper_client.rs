@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, HeaderValue, Method, StatusCode, Version};
+use monoio_netreq::download::DownloadOptions;
+use monoio_netreq::error::Error;
+use monoio_netreq::hyper::client::MonoioHyperClient;
+use monoio_netreq::propagation::{TraceContext, W3CTraceContext};
+use monoio_netreq::testing::TestServer;
+use monoio_netreq::timings::RequestTimings;
+use serde_json::Value;
+
+#[monoio::test(timer = true)]
+async fn http1_plain() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().http1_only().build();
+
+    let response = client.new_request().set_uri(server.url("/echo?a=1")).send().await.unwrap();
+    assert_eq!(response.version(), Version::HTTP_11);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["path"], "/echo");
+    assert_eq!(body["query"], "a=1");
+}
+
+#[monoio::test(timer = true)]
+async fn http2_prior_knowledge_plain() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().http2_prior_knowledge().build();
+
+    let response = client
+        .new_request()
+        .set_uri(server.url("/echo"))
+        .set_version(Version::HTTP_2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["version"], "HTTP/2.0");
+}
+
+#[monoio::test(timer = true)]
+async fn auto_protocol_plain() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().build();
+
+    let response = client.new_request().set_uri(server.url("/status/204")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+}
+
+#[monoio::test(timer = true)]
+async fn post_body_with_default_headers() {
+    let server = TestServer::start().unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert("x-default", HeaderValue::from_static("client"));
+    headers.insert("x-other", HeaderValue::from_static("client"));
+
+    for builder in [MonoioHyperClient::builder(), MonoioHyperClient::builder().http1_only()] {
+        let client = builder.default_headers(headers.clone()).build();
+        let response = client
+            .new_request()
+            .set_method(Method::POST)
+            .set_uri(server.url("/submit"))
+            .set_header(CONTENT_TYPE, "text/plain")
+            .set_header("x-other", "request")
+            .send_body(Bytes::from_static(b"hello"))
+            .await
+            .unwrap();
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["method"], "POST");
+        assert_eq!(body["body"], "hello");
+        assert_eq!(body["headers"]["x-default"], "client");
+        assert_eq!(body["headers"]["x-other"], "request");
+    }
+}
+
+#[monoio::test(timer = true)]
+async fn connection_pooling() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().http1_only().max_idle_connections(2).build();
+    for _ in 0..3 {
+        client.new_request().set_uri(server.url("/")).send().await.unwrap();
+    }
+    assert_eq!(server.connection_count(), 1);
+}
+
+#[monoio::test(timer = true)]
+async fn response_body_size_limit() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().max_response_body_size(100).build();
+
+    let result = client.new_request().set_uri(server.url("/bytes/1000")).send().await;
+    assert!(matches!(result, Err(Error::ResponseBodyTooLarge(100))));
+
+    // Chunked bodies carry no Content-Length, the limit is enforced while reading
+    let result = client.new_request().set_uri(server.url("/stream/200")).send().await;
+    assert!(matches!(result, Err(Error::ResponseBodyTooLarge(100))));
+
+    let client = MonoioHyperClient::builder().max_response_headers(1).build();
+    let result = client.new_request().set_uri(server.url("/echo")).send().await;
+    assert!(matches!(result, Err(Error::ResponseHeadersTooLarge(_))));
+}
+
+#[monoio::test(timer = true)]
+async fn upload_progress_over_http2() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().http2_prior_knowledge().build();
+    let uploaded = Rc::new(RefCell::new(Vec::new()));
+
+    let up = uploaded.clone();
+    let response = client
+        .new_request()
+        .set_method(Method::POST)
+        .set_uri(server.url("/"))
+        .on_upload_progress(move |sent, total| up.borrow_mut().push((sent, total)))
+        .send_body(Bytes::from(vec![0; 100 * 1024]))
+        .await
+        .unwrap();
+    assert_eq!(response.version(), Version::HTTP_2);
+
+    // The pooled connection pulls the body frame by frame
+    let total = Some(100 * 1024);
+    assert_eq!(*uploaded.borrow(), [(64 * 1024, total), (100 * 1024, total)]);
+    assert_eq!(server.received_requests().pop().unwrap().body.len(), 100 * 1024);
+}
+
+#[monoio::test(timer = true)]
+async fn downloads_are_streamed_to_disk() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().build();
+    let path = std::env::temp_dir().join(format!("monoio-netreq-hyper-{}", server.addr().port()));
+    let progress = Rc::new(RefCell::new(Vec::new()));
+
+    // Each frame is reported once written
+    let reported = progress.clone();
+    let options = DownloadOptions::new().on_progress(move |done, total| reported.borrow_mut().push((done, total)));
+    let size = client
+        .new_request()
+        .set_uri(server.url("/bytes/4096"))
+        .resume_download(&path, options)
+        .await
+        .unwrap();
+    assert_eq!(size, 4096);
+    assert_eq!(std::fs::read(&path).unwrap().len(), 4096);
+    assert_eq!(progress.borrow().last(), Some(&(4096, Some(4096))));
+    let _ = std::fs::remove_file(path);
+}
+
+#[monoio::test(timer = true)]
+async fn trailers_are_received() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().http2_prior_knowledge().build();
+
+    let mut response = client
+        .new_request()
+        .set_uri(server.url("/trailers"))
+        .set_version(Version::HTTP_2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.trailers().await.unwrap()["x-checksum"], "5d41402a");
+}
+
+#[cfg(feature = "cache")]
+#[monoio::test(timer = true)]
+async fn trailers_are_kept_through_the_cache() {
+    use monoio_netreq::cache::HttpCache;
+
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder()
+        .http2_prior_knowledge()
+        .with_cache(HttpCache::in_memory(16))
+        .build();
+
+    let mut response = client
+        .new_request()
+        .set_uri(server.url("/trailers"))
+        .set_version(Version::HTTP_2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.trailers().await.unwrap()["x-checksum"], "5d41402a");
+}
+
+#[monoio::test(timer = true)]
+async fn request_authentication() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().build();
+
+    let response = client
+        .new_request()
+        .set_uri(server.url("/digest-auth/user/secret"))
+        .digest_auth("user", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = client
+        .new_request()
+        .set_uri(server.url("/bearer"))
+        .bearer_auth("token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[monoio::test(timer = true)]
+async fn trace_context_propagation() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().trace_propagation(W3CTraceContext).build();
+
+    let request = client.new_request().set_uri(server.url("/"));
+    let response = TraceContext::new(1, 2, true).scope(request.send()).await.unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["headers"]["traceparent"],
+        "00-00000000000000000000000000000001-0000000000000002-01"
+    );
+}
+
+#[monoio::test(timer = true)]
+async fn timings_are_attached() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().http1_only().build();
+
+    let response = client.new_request().set_uri(server.url("/delay/20")).send().await.unwrap();
+    let timings = response.extensions().get::<RequestTimings>().unwrap();
+    assert!(!timings.reused());
+    assert!(timings.total() >= timings.ttfb());
+}
+
+#[monoio::test(timer = true)]
+async fn event_source_requests_are_signed() {
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder()
+        .signer(|parts: &mut http::request::Parts, _body: &[u8]| {
+            parts.headers.insert("x-signature", HeaderValue::from_static("signed"));
+            Ok(())
+        })
+        .build();
+
+    let mut events = client.event_source(server.url("/sse"));
+    let first = events.next_event().await.unwrap().unwrap();
+    assert_eq!(first.data, "hello");
+    let received = server.received_requests().pop().unwrap();
+    assert_eq!(received.headers["x-signature"], "signed");
+}
+
+#[monoio::test(timer = true)]
+async fn upgrade_takes_over_the_connection() {
+    use monoio::io::{AsyncReadRent, AsyncWriteRentExt};
+
+    let server = TestServer::start().unwrap();
+    let client = MonoioHyperClient::builder().build();
+
+    let response = client
+        .new_request()
+        .set_uri(server.url("/upgrade"))
+        .set_header("connection", "upgrade")
+        .set_header("upgrade", "echo")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+    let mut stream = response.upgrade().unwrap();
+    let (res, _) = stream.write_all(b"ping".to_vec()).await;
+    res.unwrap();
+    let (res, buf) = stream.read(Vec::with_capacity(16)).await;
+    assert_eq!(&buf[..res.unwrap()], b"ping");
+}
@@ -0,0 +1,227 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use bytes::Bytes;
+use http::header::{ACCEPT, CONTENT_TYPE, UPGRADE};
+use http::{HeaderName, HeaderValue, Method, StatusCode};
+use monoio_netreq::error::Error;
+use monoio_netreq::http::client::MonoioClient;
+use monoio_netreq::mock::{Expectation, MockResponse, MockTransport};
+use serde_json::{json, Value};
+
+const ORIGIN: &str = "http://api.local";
+
+fn client(mock: &MockTransport) -> MonoioClient {
+    MonoioClient::builder().mock(mock.clone()).build()
+}
+
+fn verify_failure(mock: &MockTransport) -> String {
+    let panic = catch_unwind(AssertUnwindSafe(|| mock.verify())).unwrap_err();
+    panic.downcast_ref::<String>().cloned().unwrap()
+}
+
+#[monoio::test(timer = true)]
+async fn expectations_match_method_path_query_headers_and_body() {
+    let mock = MockTransport::new();
+    mock.register(
+        Expectation::new(Method::GET, "/users")
+            .match_query("page", "2")
+            .match_header(ACCEPT, "application/json")
+            .respond_with(MockResponse::new(StatusCode::OK).json(&json!({"page": 2})).unwrap()),
+    );
+    mock.register(
+        Expectation::new(Method::POST, "/users")
+            .match_json(&json!({"name": "ada", "admin": false}))
+            .unwrap()
+            .respond_with(MockResponse::new(StatusCode::CREATED)),
+    );
+    mock.register(
+        Expectation::any_method("/users")
+            .match_body_contains("ada")
+            .respond_with(MockResponse::new(StatusCode::ACCEPTED)),
+    );
+    mock.register(Expectation::any_method("/users").respond_with(MockResponse::new(StatusCode::NO_CONTENT)));
+    let client = client(&mock);
+
+    let response = client
+        .make_request()
+        .set_uri(format!("{ORIGIN}/users?sort=name&page=2"))
+        .set_header(ACCEPT, "application/json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["page"], 2);
+
+    // JSON bodies match regardless of formatting and key order
+    let response = client
+        .make_request()
+        .set_method(Method::POST)
+        .set_uri(format!("{ORIGIN}/users"))
+        .send_body(Bytes::from_static(b"{ \"admin\": false, \"name\": \"ada\" }"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // The first matching expectation answers
+    let response = client
+        .make_request()
+        .set_method(Method::PUT)
+        .set_uri(format!("{ORIGIN}/users"))
+        .send_body(Bytes::from_static(b"name=ada"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let response = client
+        .make_request()
+        .set_uri(format!("{ORIGIN}/users?page=3"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let received = mock.received_requests();
+    assert_eq!(received.len(), 4);
+    assert_eq!(received[2].method, Method::PUT);
+    assert_eq!(received[2].body.as_ref(), b"name=ada");
+    mock.verify();
+}
+
+#[monoio::test(timer = true)]
+async fn verify_checks_the_expected_number_of_calls() {
+    let mock = MockTransport::new();
+    mock.register(Expectation::new(Method::GET, "/once").times(1));
+    mock.register(Expectation::new(Method::GET, "/twice").times(2));
+    let client = client(&mock);
+
+    for path in ["/once", "/twice"] {
+        client.make_request().set_uri(format!("{ORIGIN}{path}")).send().await.unwrap();
+    }
+    let failure = verify_failure(&mock);
+    assert!(failure.contains("GET /twice expected 2 calls, received 1"), "{failure}");
+    assert!(!failure.contains("/once"), "{failure}");
+
+    client.make_request().set_uri(format!("{ORIGIN}/twice")).send().await.unwrap();
+    mock.verify();
+}
+
+#[monoio::test(timer = true)]
+async fn unmatched_requests_fail_and_are_reported() {
+    let mock = MockTransport::new();
+    mock.register(Expectation::new(Method::GET, "/users").match_header("x-tenant", "a"));
+    let client = client(&mock);
+
+    let result = client
+        .make_request()
+        .set_uri(format!("{ORIGIN}/users"))
+        .set_header("x-tenant", "b")
+        .send()
+        .await;
+    match result {
+        Err(Error::MockError(message)) => assert!(message.contains("GET http://api.local/users"), "{message}"),
+        other => panic!("expected a mock error, got {:?}", other.map(|r| r.status())),
+    }
+
+    assert_eq!(mock.received_requests().len(), 1);
+    let failure = verify_failure(&mock);
+    assert!(failure.contains("unexpected request GET http://api.local/users"), "{failure}");
+}
+
+#[monoio::test(timer = true)]
+async fn dedicated_connection_requests_are_mocked() {
+    let mock = MockTransport::new();
+    mock.register(
+        Expectation::new(Method::PUT, "/upload")
+            .match_body("payload")
+            .respond_with(MockResponse::new(StatusCode::CREATED))
+            .times(2),
+    );
+    mock.register(
+        Expectation::new(Method::GET, "/socket")
+            .match_header(UPGRADE, "websocket")
+            .respond_with(MockResponse::new(StatusCode::SWITCHING_PROTOCOLS).header(UPGRADE, "websocket")),
+    );
+    let client = client(&mock);
+
+    let response = client
+        .make_request()
+        .set_method(Method::PUT)
+        .set_uri(format!("{ORIGIN}/upload"))
+        .expect_continue()
+        .send_body(Bytes::from_static(b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = client
+        .make_request()
+        .set_method(Method::PUT)
+        .set_uri(format!("{ORIGIN}/upload"))
+        .set_trailer(HeaderName::from_static("x-checksum"), HeaderValue::from_static("abc"))
+        .send_body(Bytes::from_static(b"payload"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    // Mocked upgrades have no connection to take over
+    let response = client
+        .make_request()
+        .set_uri(format!("{ORIGIN}/socket"))
+        .set_header(UPGRADE, "websocket")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert!(matches!(response.upgrade(), Err(Error::UpgradeError(_))));
+
+    mock.verify();
+}
+
+#[monoio::test(timer = true)]
+async fn event_streams_are_mocked() {
+    let mock = MockTransport::new();
+    mock.register(
+        Expectation::new(Method::GET, "/events").respond_with(
+            MockResponse::new(StatusCode::OK)
+                .header(CONTENT_TYPE, "text/event-stream")
+                .body("id: 1\ndata: hello\n\n"),
+        ),
+    );
+    let client = client(&mock);
+
+    let mut events = client.event_source(format!("{ORIGIN}/events"));
+    let event = events.next_event().await.unwrap().unwrap();
+    assert_eq!(event.data, "hello");
+    assert_eq!(event.id.as_deref(), Some("1"));
+    assert_eq!(mock.received_requests()[0].headers[ACCEPT], "text/event-stream");
+}
+
+#[cfg(feature = "grpc")]
+#[monoio::test(timer = true)]
+async fn grpc_calls_are_mocked() {
+    use http::Uri;
+    use monoio_netreq::grpc::{BytesCodec, Code, GrpcClient};
+
+    let mock = MockTransport::new();
+    let reply = MockResponse::new(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header("grpc-status", "5")
+        .header("grpc-message", "no%20such%20user");
+    mock.register(
+        Expectation::new(Method::POST, "/users.Users/Get")
+            .match_header(CONTENT_TYPE, "application/grpc")
+            .respond_with(reply),
+    );
+    let grpc = GrpcClient::new(client(&mock), Uri::from_static("http://grpc.local"));
+
+    let result = grpc.unary("/users.Users/Get", &Bytes::from_static(b"ada"), BytesCodec).await;
+    match result {
+        Err(Error::GrpcError(status)) => {
+            assert_eq!(status.code(), Code::NotFound);
+            assert_eq!(status.message(), "no such user");
+        }
+        other => panic!("expected a gRPC error, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(mock.received_requests()[0].body.as_ref(), b"\0\0\0\0\x03ada");
+}
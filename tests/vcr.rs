@@ -0,0 +1,63 @@
+use http::header::AUTHORIZATION;
+use http::StatusCode;
+use monoio_netreq::error::Error;
+use monoio_netreq::http::client::MonoioClient;
+use monoio_netreq::testing::TestServer;
+use monoio_netreq::vcr::{Cassette, MatchRule, VcrMode};
+
+#[monoio::test(timer = true)]
+async fn records_then_replays() {
+    let server = TestServer::start().unwrap();
+    let path = std::env::temp_dir().join(format!("monoio-netreq-cassette-{}.json", server.addr().port()));
+
+    let cassette = Cassette::new(&path, VcrMode::Record).unwrap().redact_header("x-api-key");
+    let client = MonoioClient::builder().cassette(cassette.clone()).build();
+    let response = client
+        .make_request()
+        .set_uri(server.url("/bytes/8?b=2&a=1"))
+        .set_header(AUTHORIZATION, "Bearer secret")
+        .set_header("x-api-key", "key")
+        .send()
+        .await
+        .unwrap();
+    let recorded = response.bytes().await.unwrap();
+    assert_eq!(cassette.len(), 1);
+
+    let file = std::fs::read_to_string(&path).unwrap();
+    assert!(!file.contains("secret"));
+    assert!(!file.contains("\"key\""));
+
+    let cassette = Cassette::new(&path, VcrMode::Replay)
+        .unwrap()
+        .match_on([MatchRule::Method, MatchRule::Path, MatchRule::Query]);
+    let client = MonoioClient::builder().cassette(cassette).build();
+    let response = client.make_request().set_uri(server.url("/bytes/8?a=1&b=2")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.bytes().await.unwrap(), recorded);
+    assert_eq!(server.received_requests().len(), 1);
+
+    // Each exchange is replayed once
+    let result = client.make_request().set_uri(server.url("/bytes/8?a=1&b=2")).send().await;
+    assert!(matches!(result, Err(Error::CassetteError(_))));
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[monoio::test(timer = true)]
+async fn auto_mode_records_unmatched_requests() {
+    let server = TestServer::start().unwrap();
+    let path = std::env::temp_dir().join(format!("monoio-netreq-cassette-{}.json", server.addr().port()));
+
+    for _ in 0..2 {
+        let cassette = Cassette::new(&path, VcrMode::Auto).unwrap().allow_playback_repeats();
+        let client = MonoioClient::builder().cassette(cassette.clone()).build();
+        for _ in 0..2 {
+            let response = client.make_request().set_uri(server.url("/bytes/16")).send().await.unwrap();
+            assert_eq!(response.bytes().await.unwrap().len(), 16);
+        }
+        assert_eq!(cassette.len(), 1);
+    }
+    assert_eq!(server.received_requests().len(), 1);
+
+    let _ = std::fs::remove_file(path);
+}